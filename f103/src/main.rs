@@ -5,17 +5,24 @@ use panic_rtt_target as _;
 
 #[rtic::app(device = stm32f1xx_hal::stm32, peripherals = true, dispatchers = [EXTI0])]
 mod app {
+    use core::convert::Infallible;
+
     use cortex_m::{asm::delay, peripheral::DWT};
+    use embedded_hal::serial::{Read, Write};
+    use heapless::spsc::{Consumer, Producer, Queue};
     use mbkb::{
-        phy::{self, Layout},
+        phy::{self, Layout, Sleep},
         proto::{
             usb::{UsbV1, UsbV1Report},
             KeyCode, Protocol, Report,
         },
+        split::{Election, Remote, Role, Secondary, State},
     };
     use stm32f1xx_hal::{
-        gpio::{ErasedPin, Input, PullUp},
+        gpio::{Edge, ErasedPin, ExtiPin, Input, PullUp},
+        pac::{EXTI, USART1},
         prelude::*,
+        serial::{self, Serial},
         usb::{Peripheral, UsbBus, UsbBusType},
     };
     use systick_monotonic::*;
@@ -25,12 +32,77 @@ mod app {
     #[monotonic(binds = SysTick, default = true)]
     type Mono = Systick<100>; // 100 Hz / 10 ms granularity
 
+    /// Number of ticks without pressed keys after which the layout is put to
+    /// sleep (~1 s).
+    const IDLE_TICKS: u16 = 60;
+
+    type PhyLayout = phy::layouts::Array<ErasedPin<Input<PullUp>>, 4>;
+
+    /// Size of the queue of received bytes, a bit more than two frames.
+    const RX_QUEUE: usize = 160;
+
+    /// Serial link to the other half.
+    ///
+    /// USART1 only holds one received byte, so bytes are moved to a queue by
+    /// `on_rx` as soon as they are received, otherwise all but the first byte
+    /// of a frame would be lost between the polls of the link.
+    struct Link {
+        tx: serial::Tx<USART1>,
+        rx: Consumer<'static, u8, RX_QUEUE>,
+    }
+
+    impl Read<u8> for Link {
+        type Error = Infallible;
+
+        fn read(&mut self) -> nb::Result<u8, Self::Error> {
+            self.rx.dequeue().ok_or(nb::Error::WouldBlock)
+        }
+    }
+
+    impl Write<u8> for Link {
+        type Error = Infallible;
+
+        fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+            self.tx.write(byte)
+        }
+
+        fn flush(&mut self) -> nb::Result<(), Self::Error> {
+            self.tx.flush()
+        }
+    }
+
+    /// Role of this half, both halves run this firmware.
+    enum Half {
+        Undecided(Election<Link>),
+        Primary(Remote<Link, 4>),
+        Secondary(Secondary<Link>),
+    }
+
+    impl Half {
+        fn elect(self, host: bool) -> Self {
+            match self {
+                Self::Undecided(mut election) => match election.poll(host) {
+                    Ok(Some(Role::Primary)) => Self::Primary(Remote::new(election.into_inner())),
+                    Ok(Some(Role::Secondary)) => {
+                        // Requests of the primary half are handled in `on_link`
+                        Self::Secondary(Secondary::new(election.into_inner()))
+                    }
+                    _ => Self::Undecided(election),
+                },
+                half => half,
+            }
+        }
+    }
+
+    type Led = stm32f1xx_hal::gpio::gpioc::PC13<
+        stm32f1xx_hal::gpio::Output<stm32f1xx_hal::gpio::PushPull>,
+    >;
+
     #[local]
     struct Local {
-        phy_layout: phy::layouts::Array<ErasedPin<Input<PullUp>>, 4>,
-        led: stm32f1xx_hal::gpio::gpioc::PC13<
-            stm32f1xx_hal::gpio::Output<stm32f1xx_hal::gpio::PushPull>,
-        >,
+        led: Led,
+        rx: serial::Rx<USART1>,
+        rx_queue: Producer<'static, u8, RX_QUEUE>,
     }
 
     #[shared]
@@ -39,9 +111,19 @@ mod app {
         usb_dev: UsbDevice<'static, UsbBusType>,
         #[lock_free]
         proto: UsbV1<'static, UsbBusType>,
+        #[lock_free]
+        phy_layout: PhyLayout,
+        #[lock_free]
+        exti: EXTI,
+        /// `None` only while the role is being changed.
+        #[lock_free]
+        half: Option<Half>,
     }
 
-    #[init(local = [usb_bus: Option<bus::UsbBusAllocator<UsbBusType>> = None])]
+    #[init(local = [
+        usb_bus: Option<bus::UsbBusAllocator<UsbBusType>> = None,
+        rx_buf: Queue<u8, RX_QUEUE> = Queue::new(),
+    ])]
     fn init(mut cx: init::Context) -> (Shared, Local, init::Monotonics) {
         // I do not remember what this does (waffle)
         cx.core.DCB.enable_trace();
@@ -70,10 +152,10 @@ mod app {
 
         rtt_target::rtt_init_print!();
 
+        let mut gpioa = cx.device.GPIOA.split();
+
         // Setup usb
         let (usb_dev, proto) = {
-            let mut gpioa = cx.device.GPIOA.split();
-
             // BluePill board has a pull-up resistor on the D+ line.
             // Pull the D+ pin down to send a RESET condition to the USB bus.
             let mut usb_dp = gpioa.pa12.into_push_pull_output(&mut gpioa.crh);
@@ -105,15 +187,47 @@ mod app {
             (usb_dev, proto)
         };
 
+        let exti = cx.device.EXTI;
+        let mut afio = cx.device.AFIO.constrain();
+
+        let (half, rx, rx_queue) = {
+            let tx = gpioa.pa9.into_alternate_push_pull(&mut gpioa.crh);
+            let rx = gpioa.pa10;
+
+            let serial = Serial::usart1(
+                cx.device.USART1,
+                (tx, rx),
+                &mut afio.mapr,
+                serial::Config::default().baudrate(115_200.bps()),
+                clocks,
+            );
+
+            // Received bytes are queued by `on_rx` whatever the role is
+            let (tx, mut rx) = serial.split();
+            rx.listen();
+
+            let (producer, consumer) = cx.local.rx_buf.split();
+            let link = Link { tx, rx: consumer };
+
+            (Half::Undecided(Election::new(link)), rx, producer)
+        };
+
         let phy_layout = {
             let mut gpiob = cx.device.GPIOB.split();
-            let pins = [
+            let mut pins = [
                 gpiob.pb12.into_pull_up_input(&mut gpiob.crh).erase(),
                 gpiob.pb13.into_pull_up_input(&mut gpiob.crh).erase(),
                 gpiob.pb14.into_pull_up_input(&mut gpiob.crh).erase(),
                 gpiob.pb15.into_pull_up_input(&mut gpiob.crh).erase(),
             ];
 
+            // Pressing a button pulls its pin low. Interrupts are enabled only
+            // while the layout sleeps (see `on_tick`).
+            for pin in &mut pins {
+                pin.make_interrupt_source(&mut afio);
+                pin.trigger_on_edge(&exti, Edge::Falling);
+            }
+
             phy::layouts::Array::new(pins)
         };
 
@@ -127,37 +241,115 @@ mod app {
         // Wait some time so usb can connect first.
         on_tick::spawn_after(1.secs()).ok();
 
-        let local = Local { phy_layout, led };
-        let shared = Shared { usb_dev, proto };
+        let local = Local { led, rx, rx_queue };
+        let shared = Shared {
+            usb_dev,
+            proto,
+            phy_layout,
+            exti,
+            half: Some(half),
+        };
 
         (shared, local, init::Monotonics(mono))
     }
 
-    #[task(local = [phy_layout, led], shared=[proto])]
+    #[task(local = [led, idle_ticks: u16 = 0], shared=[usb_dev, proto, phy_layout, exti, half])]
     fn on_tick(cx: on_tick::Context) {
-        // Repeat the same task after 16 ms
-        on_tick::spawn_after(16.millis()).ok();
-
+        let usb_dev = &*cx.shared.usb_dev;
         let proto = &mut *cx.shared.proto;
-        let phy_layout = &mut *cx.local.phy_layout;
+        let phy_layout = &mut *cx.shared.phy_layout;
+        let exti = &*cx.shared.exti;
+        let half = &mut *cx.shared.half;
         let led = &mut *cx.local.led;
+        let idle_ticks = &mut *cx.local.idle_ticks;
+
+        let host = usb_dev.state() == UsbDeviceState::Configured;
+        *half = half.take().map(|half| half.elect(host));
+
+        let remote = match half {
+            Some(Half::Primary(remote)) => remote,
+            Some(Half::Secondary(secondary)) => {
+                secondary.scan(phy_layout);
+                set_led(led, secondary.state().leds.caps_lock.enabled());
+                on_tick::spawn_after(16.millis()).ok();
+                return;
+            }
+            // Wait for the host or the other half
+            _ => {
+                on_tick::spawn_after(16.millis()).ok();
+                return;
+            }
+        };
 
         let mut report = UsbV1Report::empty();
+        let mut pressed = false;
+
+        let mut press = |key: usize| {
+            let (kc, shiftness) = KeyCode::from_ascii(b"AaBbCcDd"[key]).unwrap();
+            if shiftness {
+                report.press(KeyCode::LShift);
+            }
+            report.press(kc);
+            pressed = true;
+        };
 
-        phy_layout.poll(&mut |iter| {
-            iter.for_each(|key| {
-                let (kc, shiftness) =
-                    KeyCode::from_ascii(b"AaBb"[key.into_raw() as usize]).unwrap();
-                if shiftness {
-                    report.press(KeyCode::LShift);
-                }
-                report.press(kc);
-            })
-        });
+        phy_layout.poll(&mut |iter| iter.for_each(|key| press(key.into_raw() as usize)));
+
+        // Keys of the secondary half go after the keys of this half
+        remote.poll(&mut |iter| iter.for_each(|key| press(4 + key.into_raw() as usize)));
 
         proto.set_report(report);
 
-        if proto.leds().caps_lock.enabled() {
+        let leds = proto.leds();
+        set_led(led, leds.caps_lock.enabled());
+        remote.set_state(State {
+            leds,
+            ..State::default()
+        });
+
+        *idle_ticks = if pressed { 0 } else { idle_ticks.saturating_add(1) };
+
+        // The secondary half can only be polled while awake
+        if *idle_ticks >= IDLE_TICKS && !remote.connected() && sleep(phy_layout, exti) {
+            // `on_exti` will wake us up
+            *idle_ticks = 0;
+        } else {
+            // Repeat the same task after 16 ms
+            on_tick::spawn_after(16.millis()).ok();
+        }
+    }
+
+    /// Moves received bytes to the queue of the link.
+    ///
+    /// This has a higher priority than other tasks, so that bytes are not lost
+    /// while they run.
+    #[task(binds=USART1, priority=2, local=[rx, rx_queue])]
+    fn on_rx(cx: on_rx::Context) {
+        loop {
+            match cx.local.rx.read() {
+                // If the queue is full, the frame is broken anyway
+                Ok(byte) => {
+                    cx.local.rx_queue.enqueue(byte).ok();
+                }
+                // The byte is lost, the frame will be dropped by its CRC
+                Err(nb::Error::Other(_)) => {}
+                Err(nb::Error::WouldBlock) => break,
+            }
+        }
+
+        on_link::spawn().ok();
+    }
+
+    /// Responds to requests of the primary half, if this is the secondary one.
+    #[task(shared=[half])]
+    fn on_link(cx: on_link::Context) {
+        if let Some(Half::Secondary(secondary)) = cx.shared.half {
+            secondary.serve().ok();
+        }
+    }
+
+    fn set_led(led: &mut Led, on: bool) {
+        if on {
             // turn led on (??)
             led.set_low()
         } else {
@@ -165,6 +357,42 @@ mod app {
         }
     }
 
+    /// Puts the layout to sleep and enables interrupts on its pins, returns
+    /// `false` if a button was pressed in the meantime (and so the layout
+    /// should be polled instead).
+    fn sleep(phy_layout: &mut PhyLayout, exti: &EXTI) -> bool {
+        phy_layout.sleep().ok();
+
+        for pin in phy_layout.pins_mut() {
+            pin.clear_interrupt_pending_bit();
+            pin.enable_interrupt(exti);
+        }
+
+        // A button could have been pressed before interrupts were enabled
+        if phy_layout.pins_mut().iter().any(|pin| pin.is_low()) {
+            wake(phy_layout, exti);
+            return false;
+        }
+
+        true
+    }
+
+    fn wake(phy_layout: &mut PhyLayout, exti: &EXTI) {
+        for pin in phy_layout.pins_mut() {
+            pin.disable_interrupt(exti);
+            pin.clear_interrupt_pending_bit();
+        }
+
+        phy_layout.wake().ok();
+    }
+
+    #[task(binds=EXTI15_10, shared=[phy_layout, exti])]
+    fn on_exti(cx: on_exti::Context) {
+        wake(cx.shared.phy_layout, cx.shared.exti);
+
+        on_tick::spawn().ok();
+    }
+
     #[task(binds=USB_HP_CAN_TX, shared=[usb_dev, proto])]
     fn usb_tx(mut cx: usb_tx::Context) {
         usb_poll(&mut cx.shared.usb_dev, cx.shared.proto.usb_class());
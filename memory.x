@@ -1,6 +1,12 @@
 /* Linker script for the STM32F103C8T6 */
 MEMORY
 {
-  FLASH : ORIGIN = 0x08000000, LENGTH = 64K
+  FLASH : ORIGIN = 0x08000000, LENGTH = 60K
+  /* Configuration store (see `mbkb::storage::Store`), 4 pages of 1K */
+  CONFIG : ORIGIN = 0x0800F000, LENGTH = 4K
   RAM : ORIGIN = 0x20000000, LENGTH = 20K
-}
\ No newline at end of file
+}
+
+/* Bounds of the configuration store */
+_config_start = ORIGIN(CONFIG);
+_config_end = ORIGIN(CONFIG) + LENGTH(CONFIG);
@@ -0,0 +1,121 @@
+use crate::{phy::KeyId, proto::KeyCode};
+
+/// Compact binary format of keymaps, for storing them on flash.
+///
+/// All numbers are little-endian. The format starts with a header:
+///
+/// - magic bytes `mbkm`
+/// - version, `u8`
+/// - number of layers, `u8`
+/// - [`max_key_id`](crate::phy::Layout::max_key_id), `u16`, i.e. the number
+///   of actions in every layer
+/// - layout fingerprint, `u32` (see [`fingerprint`])
+/// - number of combos, `u16`
+///
+/// Followed by actions of every layer, combos and a CRC-16 of everything
+/// before it. Every action is 3 bytes: a tag and 2 arguments. Every combo is
+/// 12 bytes: the number of keys, 4 [`KeyId`]s (unused ones are `0`) and the
+/// action.
+mod binary;
+/// Human-readable text format of keymaps, for writing them by hand.
+///
+/// A keymap is a sequence of lines, `#` starts a comment:
+///
+/// - `fingerprint = 0x1234abcd` sets the [`fingerprint`] of the layout
+///   (optional, `0` by default)
+/// - `layer name { ... }` defines a layer, actions are separated by
+///   whitespace and are assigned to [`KeyId`]s in order, rows can be split
+///   across lines as convenient
+/// - `combo 0 1 = Escape` defines a [`Combo`] of keys with the given ids
+///
+/// Actions are written as [`KeyCode`] names (see [`KeyCode::from_name`]),
+/// `XXX` for [`Action::No`], `___` for [`Action::Transparent`], `MO(layer)`,
+/// `TG(layer)`, `LT(layer, tap)` and `TH(tap, hold)`. Layers are referred to
+/// by name or by index.
+///
+/// Keymaps can be compiled into the binary format at build time: a build
+/// script parses the text, writes [`text::Keymap::to_binary`] to a file in
+/// `OUT_DIR`, which the firmware includes with `include_bytes!`.
+#[cfg(any(test, feature = "std"))]
+pub mod text;
+
+pub use binary::{encode, encoded_len, fingerprint, Keymap, VERSION};
+
+/// Maximum number of keys in a [`Combo`].
+pub const MAX_COMBO_KEYS: usize = 4;
+
+/// What happens when a key is pressed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Nothing happens.
+    No,
+    /// The action of the next active layer below is used.
+    Transparent,
+    /// Presses a key.
+    Key(KeyCode),
+    /// Activates a layer while held.
+    Momentary(u8),
+    /// Toggles a layer.
+    Toggle(u8),
+    /// Presses `tap` when tapped, activates `layer` while held.
+    LayerTap { layer: u8, tap: KeyCode },
+    /// Presses `tap` when tapped, `hold` while held (usually a modifier).
+    TapHold { tap: KeyCode, hold: KeyCode },
+}
+
+/// Action triggered by pressing several keys at the same time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Combo {
+    keys: [KeyId; MAX_COMBO_KEYS],
+    len: u8,
+    pub action: Action,
+}
+
+/// Error of encoding or decoding a keymap.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Error {
+    /// The data is not a keymap or is truncated.
+    Format,
+    /// The keymap has an unsupported version.
+    Version(u8),
+    /// The CRC doesn't match, the keymap is corrupted.
+    Checksum,
+    /// An action or a combo is invalid, e.g. has an unknown key code or a key
+    /// that doesn't exist.
+    Action,
+    /// Layers have different number of actions, or there are too many
+    /// layers, keys or combos.
+    Size,
+    /// The output buffer is too small.
+    BufferTooSmall,
+}
+
+impl Combo {
+    /// Creates a combo of `keys`.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if there are more than [`MAX_COMBO_KEYS`] keys.
+    pub const fn new(keys: &[KeyId], action: Action) -> Self {
+        assert!(keys.len() <= MAX_COMBO_KEYS);
+
+        let mut this = Self {
+            keys: [KeyId::from_raw(0); MAX_COMBO_KEYS],
+            len: keys.len() as u8,
+            action,
+        };
+
+        let mut i = 0;
+        while i < keys.len() {
+            this.keys[i] = keys[i];
+            i += 1;
+        }
+
+        this
+    }
+
+    /// Returns the keys that have to be pressed to trigger the combo.
+    pub fn keys(&self) -> &[KeyId] {
+        &self.keys[..usize::from(self.len)]
+    }
+}
@@ -0,0 +1,532 @@
+use core::convert::TryFrom;
+
+use crate::{
+    crc::crc16,
+    keymap::{Action, Combo, Error, MAX_COMBO_KEYS},
+    phy::{top::fixed, KeyId, Layout},
+    proto::KeyCode,
+};
+
+const MAGIC: [u8; 4] = *b"mbkm";
+
+/// Current version of the format.
+///
+/// [`Keymap::decode`] also accepts keymaps of older versions, but rejects the
+/// ones of newer versions.
+pub const VERSION: u8 = 1;
+
+const HEADER_SIZE: usize = 14;
+/// Size of the header of version 0, which had no combos and so no combo count.
+const HEADER_SIZE_V0: usize = 12;
+const ACTION_SIZE: usize = 3;
+const COMBO_SIZE: usize = 12;
+const CRC_SIZE: usize = 2;
+
+const NO: u8 = 0;
+const TRANSPARENT: u8 = 1;
+const KEY: u8 = 2;
+const MOMENTARY: u8 = 3;
+const TOGGLE: u8 = 4;
+const LAYER_TAP: u8 = 5;
+const TAP_HOLD: u8 = 6;
+
+/// Keymap decoded from the binary format, borrows the encoded data.
+#[derive(Debug, Copy, Clone)]
+pub struct Keymap<'a> {
+    version: u8,
+    layers: u8,
+    max_key_id: KeyId,
+    fingerprint: u32,
+    actions: &'a [u8],
+    combos: &'a [u8],
+}
+
+/// Returns the size of a keymap encoded by [`encode`].
+pub const fn encoded_len(layers: usize, max_key_id: usize, combos: usize) -> usize {
+    HEADER_SIZE + layers * max_key_id * ACTION_SIZE + combos * COMBO_SIZE + CRC_SIZE
+}
+
+/// Encodes a keymap into `out`, returns the size of the keymap.
+///
+/// All `layers` must have the same number of actions, the
+/// [`max_key_id`](Layout::max_key_id) of the layout, and `combos` can only
+/// use keys below it. `fingerprint` should be the [`fingerprint`] of the
+/// layout.
+pub fn encode(
+    layers: &[&[Action]],
+    combos: &[Combo],
+    fingerprint: u32,
+    out: &mut [u8],
+) -> Result<usize, Error> {
+    let keys = layers.first().map_or(0, |layer| layer.len());
+    if layers.iter().any(|layer| layer.len() != keys) {
+        return Err(Error::Size);
+    }
+
+    let (layers_u8, keys_u16, combos_u16) = match (
+        u8::try_from(layers.len()),
+        u16::try_from(keys),
+        u16::try_from(combos.len()),
+    ) {
+        (Ok(layers), Ok(keys), Ok(combos)) => (layers, keys, combos),
+        _ => return Err(Error::Size),
+    };
+
+    if !combos.iter().all(|combo| valid_keys(combo, keys_u16)) {
+        return Err(Error::Action);
+    }
+
+    let len = encoded_len(layers.len(), keys, combos.len());
+    if out.len() < len {
+        return Err(Error::BufferTooSmall);
+    }
+
+    out[..4].copy_from_slice(&MAGIC);
+    out[4] = VERSION;
+    out[5] = layers_u8;
+    out[6..8].copy_from_slice(&keys_u16.to_le_bytes());
+    out[8..12].copy_from_slice(&fingerprint.to_le_bytes());
+    out[12..14].copy_from_slice(&combos_u16.to_le_bytes());
+
+    let actions = layers.iter().flat_map(|layer| layer.iter());
+    let (actions_out, combos_out) =
+        out[HEADER_SIZE..].split_at_mut(layers.len() * keys * ACTION_SIZE);
+    for (out, &action) in actions_out.chunks_mut(ACTION_SIZE).zip(actions) {
+        out.copy_from_slice(&encode_action(action));
+    }
+
+    for (out, combo) in combos_out.chunks_mut(COMBO_SIZE).zip(combos) {
+        out[0] = combo.len;
+        for (out, key) in out[1..9].chunks_mut(2).zip(&combo.keys) {
+            out.copy_from_slice(&key.into_raw().to_le_bytes());
+        }
+        out[9..12].copy_from_slice(&encode_action(combo.action));
+    }
+
+    let crc = crc16(&out[..len - CRC_SIZE]);
+    out[len - CRC_SIZE..len].copy_from_slice(&crc.to_le_bytes());
+
+    Ok(len)
+}
+
+/// Returns the fingerprint of `layout`, a hash of its
+/// [`max_key_id`](Layout::max_key_id) and the positions of its keys (if it has
+/// a [topological representation](Layout::topological_repr) or a
+/// [fixed-point one](Layout::fixed_repr)).
+///
+/// If the fingerprint of a keymap doesn't match the fingerprint of the layout,
+/// the keymap was made for a different layout and probably assigns actions to
+/// wrong keys.
+pub fn fingerprint<L>(layout: &L) -> u32
+where
+    L: Layout + ?Sized,
+{
+    // FNV-1a
+    let mut hash = 0x811c_9dc5_u32;
+    let mut feed = |bytes: &[u8]| {
+        for &byte in bytes {
+            hash = (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193);
+        }
+    };
+
+    feed(&layout.max_key_id().into_raw().to_le_bytes());
+
+    let mut feed_key = |key: &fixed::KeyPos| {
+        feed(&key.id.into_raw().to_le_bytes());
+        feed(&key.x.0.to_le_bytes());
+        feed(&key.y.0.to_le_bytes());
+    };
+
+    if let Some(repr) = layout.topological_repr() {
+        for key in repr.keys {
+            // Fixed-point, so that the fingerprint doesn't depend on rounding
+            feed_key(&fixed::KeyPos::from_f32(key));
+        }
+    } else if let Some(repr) = layout.fixed_repr() {
+        repr.keys.iter().for_each(feed_key);
+    }
+
+    hash
+}
+
+impl<'a> Keymap<'a> {
+    /// Decodes a keymap, checking that it's valid.
+    ///
+    /// Keymaps of older versions are upgraded, e.g. a version 0 keymap has no
+    /// combos.
+    pub fn decode(bytes: &'a [u8]) -> Result<Self, Error> {
+        let version = match *bytes {
+            [m0, m1, m2, m3, version, ..] if [m0, m1, m2, m3] == MAGIC => version,
+            _ => return Err(Error::Format),
+        };
+
+        let header_size = match version {
+            0 => HEADER_SIZE_V0,
+            VERSION => HEADER_SIZE,
+            _ => return Err(Error::Version(version)),
+        };
+
+        if bytes.len() < header_size + CRC_SIZE {
+            return Err(Error::Format);
+        }
+
+        let (data, crc) = bytes.split_at(bytes.len() - CRC_SIZE);
+        if crc16(data).to_le_bytes() != crc {
+            return Err(Error::Checksum);
+        }
+
+        let u16_at = |i: usize| u16::from_le_bytes([data[i], data[i + 1]]);
+        let layers = data[5];
+        let keys = u16_at(6);
+        let fingerprint = u32::from_le_bytes([data[8], data[9], data[10], data[11]]);
+        let combos = if version == 0 { 0 } else { u16_at(12) };
+
+        let actions_len = usize::from(layers) * usize::from(keys) * ACTION_SIZE;
+        let combos_len = usize::from(combos) * COMBO_SIZE;
+        if data.len() != header_size + actions_len + combos_len {
+            return Err(Error::Format);
+        }
+
+        let (actions, combos) = data[header_size..].split_at(actions_len);
+        let this = Self {
+            version,
+            layers,
+            max_key_id: KeyId::from_raw(keys),
+            fingerprint,
+            actions,
+            combos,
+        };
+
+        let actions_valid = actions
+            .chunks(ACTION_SIZE)
+            .all(|action| decode_action(action).is_some());
+        let combos_valid = combos
+            .chunks(COMBO_SIZE)
+            .all(|combo| decode_combo(combo).is_some_and(|combo| valid_keys(&combo, keys)));
+
+        if !(actions_valid && combos_valid) {
+            return Err(Error::Action);
+        }
+
+        Ok(this)
+    }
+
+    /// Returns the version of the format the keymap was encoded with.
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// Returns the number of layers of the keymap.
+    pub fn layers(&self) -> u8 {
+        self.layers
+    }
+
+    /// Returns the [`max_key_id`](Layout::max_key_id) of the layout the keymap
+    /// was made for.
+    pub fn max_key_id(&self) -> KeyId {
+        self.max_key_id
+    }
+
+    /// Returns the [`fingerprint`] of the layout the keymap was made for.
+    pub fn fingerprint(&self) -> u32 {
+        self.fingerprint
+    }
+
+    /// Returns `true` if the keymap was made for `layout`, i.e. their
+    /// fingerprints match.
+    pub fn matches<L>(&self, layout: &L) -> bool
+    where
+        L: Layout + ?Sized,
+    {
+        fingerprint(layout) == self.fingerprint
+    }
+
+    /// Returns the action of `key` on `layer`, or [`Action::No`] if the layer
+    /// or the key doesn't exist.
+    pub fn action(&self, layer: u8, key: KeyId) -> Action {
+        if layer >= self.layers || key >= self.max_key_id {
+            return Action::No;
+        }
+
+        let keys = usize::from(self.max_key_id.into_raw());
+        let i = usize::from(layer) * keys + usize::from(key.into_raw());
+
+        // Actions are checked by `decode`
+        decode_action(&self.actions[i * ACTION_SIZE..][..ACTION_SIZE]).unwrap_or(Action::No)
+    }
+
+    /// Returns the combos of the keymap, in the order they were encoded in.
+    pub fn combos(&self) -> impl Iterator<Item = Combo> + 'a {
+        self.combos.chunks(COMBO_SIZE).filter_map(decode_combo)
+    }
+}
+
+fn encode_action(action: Action) -> [u8; ACTION_SIZE] {
+    match action {
+        Action::No => [NO, 0, 0],
+        Action::Transparent => [TRANSPARENT, 0, 0],
+        Action::Key(kc) => [KEY, kc as u8, 0],
+        Action::Momentary(layer) => [MOMENTARY, layer, 0],
+        Action::Toggle(layer) => [TOGGLE, layer, 0],
+        Action::LayerTap { layer, tap } => [LAYER_TAP, layer, tap as u8],
+        Action::TapHold { tap, hold } => [TAP_HOLD, tap as u8, hold as u8],
+    }
+}
+
+fn decode_action(bytes: &[u8]) -> Option<Action> {
+    let kc = KeyCode::n;
+
+    match *bytes {
+        [NO, 0, 0] => Some(Action::No),
+        [TRANSPARENT, 0, 0] => Some(Action::Transparent),
+        [KEY, kc_, 0] => kc(kc_).map(Action::Key),
+        [MOMENTARY, layer, 0] => Some(Action::Momentary(layer)),
+        [TOGGLE, layer, 0] => Some(Action::Toggle(layer)),
+        [LAYER_TAP, layer, tap] => Some(Action::LayerTap {
+            layer,
+            tap: kc(tap)?,
+        }),
+        [TAP_HOLD, tap, hold] => Some(Action::TapHold {
+            tap: kc(tap)?,
+            hold: kc(hold)?,
+        }),
+        _ => None,
+    }
+}
+
+/// Returns `true` if all keys of `combo` are below `max_key_id`.
+fn valid_keys(combo: &Combo, max_key_id: u16) -> bool {
+    combo.keys().iter().all(|key| key.into_raw() < max_key_id)
+}
+
+fn decode_combo(bytes: &[u8]) -> Option<Combo> {
+    let len = usize::from(bytes[0]);
+    if len > MAX_COMBO_KEYS {
+        return None;
+    }
+
+    let mut keys = [KeyId::from_raw(0); MAX_COMBO_KEYS];
+    for (key, raw) in keys.iter_mut().zip(bytes[1..9].chunks(2)) {
+        *key = KeyId::from_raw(u16::from_le_bytes([raw[0], raw[1]]));
+    }
+
+    Some(Combo::new(&keys[..len], decode_action(&bytes[9..12])?))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::{
+        encode, encoded_len, Keymap, COMBO_SIZE, CRC_SIZE, HEADER_SIZE, HEADER_SIZE_V0, VERSION,
+    };
+    use crate::{
+        crc::crc16,
+        keymap::{Action, Combo, Error},
+        phy::KeyId,
+        proto::KeyCode,
+    };
+
+    const BASE: [Action; 4] = [
+        Action::Key(KeyCode::A),
+        Action::LayerTap {
+            layer: 1,
+            tap: KeyCode::Space,
+        },
+        Action::TapHold {
+            tap: KeyCode::Escape,
+            hold: KeyCode::LCtrl,
+        },
+        Action::No,
+    ];
+
+    const FN: [Action; 4] = [
+        Action::Transparent,
+        Action::Momentary(2),
+        Action::Toggle(1),
+        Action::Key(KeyCode::F1),
+    ];
+
+    const COMBOS: [Combo; 2] = [
+        Combo::new(
+            &[KeyId::from_raw(0), KeyId::from_raw(3)],
+            Action::Key(KeyCode::Tab),
+        ),
+        Combo::new(&[KeyId::from_raw(2)], Action::Toggle(1)),
+    ];
+
+    const LEN: usize = encoded_len(2, 4, 2);
+
+    fn encoded() -> [u8; LEN] {
+        let mut out = [0; LEN];
+        let len = encode(&[&BASE, &FN], &COMBOS, 0x1234_abcd, &mut out).unwrap();
+        assert_eq!(len, LEN);
+        out
+    }
+
+    /// Fixes the CRC after `bytes` were changed.
+    fn seal(bytes: &mut [u8]) {
+        let (data, crc) = bytes.split_at_mut(bytes.len() - CRC_SIZE);
+        crc.copy_from_slice(&crc16(data).to_le_bytes());
+    }
+
+    #[test]
+    fn round_trip() {
+        let bytes = encoded();
+        let keymap = Keymap::decode(&bytes).unwrap();
+
+        assert_eq!(keymap.version(), VERSION);
+        assert_eq!(keymap.layers(), 2);
+        assert_eq!(keymap.max_key_id(), KeyId::from_raw(4));
+        assert_eq!(keymap.fingerprint(), 0x1234_abcd);
+
+        for (layer, actions) in [BASE, FN].iter().enumerate() {
+            for (key, &action) in actions.iter().enumerate() {
+                let key = KeyId::from_raw(key as u16);
+                assert_eq!(keymap.action(layer as u8, key), action);
+            }
+        }
+
+        assert_eq!(keymap.action(2, KeyId::from_raw(0)), Action::No);
+        assert_eq!(keymap.action(0, KeyId::from_raw(4)), Action::No);
+        assert!(keymap.combos().eq(COMBOS.iter().copied()));
+
+        // Encoding the decoded keymap gives the same bytes
+        let layers: Vec<Vec<Action>> = (0..keymap.layers())
+            .map(|layer| {
+                (0..4)
+                    .map(|key| keymap.action(layer, KeyId::from_raw(key)))
+                    .collect()
+            })
+            .collect();
+        let layers: Vec<&[Action]> = layers.iter().map(Vec::as_slice).collect();
+        let combos: Vec<Combo> = keymap.combos().collect();
+
+        let mut out = [0; LEN];
+        assert_eq!(
+            encode(&layers, &combos, keymap.fingerprint(), &mut out),
+            Ok(LEN)
+        );
+        assert_eq!(out, bytes);
+    }
+
+    #[test]
+    fn empty() {
+        let mut out = [0; encoded_len(0, 0, 0)];
+        assert_eq!(encode(&[], &[], 0, &mut out), Ok(out.len()));
+
+        let keymap = Keymap::decode(&out).unwrap();
+        assert_eq!(keymap.layers(), 0);
+        assert_eq!(keymap.combos().count(), 0);
+    }
+
+    #[test]
+    fn version_0() {
+        // Same as the current version, but without combos and the combo count
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&encoded()[..HEADER_SIZE_V0]);
+        bytes[4] = 0;
+        let actions = HEADER_SIZE..LEN - CRC_SIZE - COMBOS.len() * COMBO_SIZE;
+        bytes.extend_from_slice(&encoded()[actions]);
+        bytes.extend_from_slice(&[0; CRC_SIZE]);
+        seal(&mut bytes);
+
+        let keymap = Keymap::decode(&bytes).unwrap();
+        assert_eq!(keymap.version(), 0);
+        assert_eq!(keymap.layers(), 2);
+        assert_eq!(keymap.max_key_id(), KeyId::from_raw(4));
+        assert_eq!(keymap.fingerprint(), 0x1234_abcd);
+        assert_eq!(keymap.action(0, KeyId::from_raw(1)), BASE[1]);
+        assert_eq!(keymap.action(1, KeyId::from_raw(3)), FN[3]);
+        assert_eq!(keymap.combos().count(), 0);
+
+        // Trailing bytes are not combos
+        let len = bytes.len();
+        bytes.splice(
+            len - CRC_SIZE..len - CRC_SIZE,
+            [0; COMBO_SIZE].iter().copied(),
+        );
+        seal(&mut bytes);
+        assert_eq!(Keymap::decode(&bytes).map(|_| ()), Err(Error::Format));
+    }
+
+    #[test]
+    fn encode_errors() {
+        let mut out = [0; LEN];
+        assert_eq!(
+            encode(&[&BASE, &FN[..3]], &[], 0, &mut out),
+            Err(Error::Size)
+        );
+        assert_eq!(
+            encode(&[&BASE, &FN], &COMBOS, 0, &mut out[..LEN - 1]),
+            Err(Error::BufferTooSmall)
+        );
+
+        let combo = Combo::new(&[KeyId::from_raw(4)], Action::No);
+        assert_eq!(
+            encode(&[&BASE, &FN], &[combo], 0, &mut out),
+            Err(Error::Action)
+        );
+    }
+
+    #[test]
+    fn decode_errors() {
+        let bytes = encoded();
+        let decode = |f: fn(&mut [u8; LEN])| {
+            let mut bytes = bytes;
+            f(&mut bytes);
+            Keymap::decode(&bytes).map(|_| ())
+        };
+
+        assert_eq!(
+            Keymap::decode(&bytes[..HEADER_SIZE]).map(|_| ()),
+            Err(Error::Format)
+        );
+        assert_eq!(
+            Keymap::decode(&bytes[..LEN - 1]).map(|_| ()),
+            Err(Error::Checksum)
+        );
+        assert_eq!(decode(|b| b[0] = b'x'), Err(Error::Format));
+        assert_eq!(
+            decode(|b| b[4] = VERSION + 1),
+            Err(Error::Version(VERSION + 1))
+        );
+        assert_eq!(decode(|b| b[HEADER_SIZE + 1] ^= 1), Err(Error::Checksum));
+
+        // Too many layers for the data
+        assert_eq!(
+            decode(|b| {
+                b[5] = 3;
+                seal(b);
+            }),
+            Err(Error::Format)
+        );
+
+        // Unknown action
+        assert_eq!(
+            decode(|b| {
+                b[HEADER_SIZE] = 0xFF;
+                seal(b);
+            }),
+            Err(Error::Action)
+        );
+
+        // Key of the second combo is `max_key_id`
+        assert_eq!(
+            decode(|b| {
+                b[LEN - CRC_SIZE - COMBO_SIZE + 1] = 4;
+                seal(b);
+            }),
+            Err(Error::Action)
+        );
+
+        // Too many keys in the second combo
+        assert_eq!(
+            decode(|b| {
+                b[LEN - CRC_SIZE - COMBO_SIZE] = 5;
+                seal(b);
+            }),
+            Err(Error::Action)
+        );
+    }
+}
@@ -0,0 +1,791 @@
+use core::{convert::TryFrom, fmt, ops::Range};
+use std::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+use crate::{
+    keymap::{self, Action, Combo, MAX_COMBO_KEYS},
+    phy::KeyId,
+    proto::KeyCode,
+};
+
+/// Keymap in the text format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    /// Fingerprint of the layout, see [`keymap::fingerprint`].
+    pub fingerprint: u32,
+    pub layers: Vec<Layer>,
+    pub combos: Vec<Combo>,
+}
+
+/// Layer of a [`Keymap`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layer {
+    /// Name of the layer, an identifier.
+    pub name: String,
+    /// Actions of keys, in rows as they are written. [`KeyId`]s are assigned
+    /// in order, starting with `0`.
+    pub rows: Vec<Vec<Action>>,
+}
+
+/// Error of parsing the text format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    /// Byte range of the text that caused the error.
+    pub span: Range<usize>,
+    pub message: String,
+}
+
+/// Parses a keymap in the text format.
+pub fn parse(text: &str) -> Result<Keymap, Error> {
+    Parser {
+        lexer: Lexer { text, pos: 0 },
+        peeked: None,
+    }
+    .keymap()
+}
+
+impl Keymap {
+    /// Encodes the keymap into the binary format, see [`keymap::encode`].
+    pub fn to_binary(&self) -> Result<Vec<u8>, keymap::Error> {
+        let layers: Vec<Vec<Action>> = self
+            .layers
+            .iter()
+            .map(|layer| layer.rows.concat())
+            .collect();
+        let layers: Vec<&[Action]> = layers.iter().map(Vec::as_slice).collect();
+
+        let keys = layers.first().map_or(0, |layer| layer.len());
+        let mut out = vec![0; keymap::encoded_len(layers.len(), keys, self.combos.len())];
+        let len = keymap::encode(&layers, &self.combos, self.fingerprint, &mut out)?;
+        out.truncate(len);
+
+        Ok(out)
+    }
+
+    /// Returns the name of the layer to refer to it in actions, if it can be
+    /// used instead of the index.
+    fn layer_name(&self, layer: u8) -> Option<&str> {
+        let name = &self.layers.get(usize::from(layer))?.name;
+        let first = self.layers.iter().position(|l| l.name == *name);
+
+        Some(name.as_str()).filter(|name| is_ident(name) && first == Some(usize::from(layer)))
+    }
+
+    fn fmt_action(&self, action: Action) -> String {
+        let layer = |layer: u8| match self.layer_name(layer) {
+            Some(name) => name.to_string(),
+            None => layer.to_string(),
+        };
+
+        match action {
+            Action::No => "XXX".to_string(),
+            Action::Transparent => "___".to_string(),
+            Action::Key(kc) => kc.to_string(),
+            Action::Momentary(l) => format!("MO({})", layer(l)),
+            Action::Toggle(l) => format!("TG({})", layer(l)),
+            Action::LayerTap { layer: l, tap } => format!("LT({}, {})", layer(l), tap),
+            Action::TapHold { tap, hold } => format!("TH({}, {})", tap, hold),
+        }
+    }
+}
+
+/// Formats the keymap in the text format, aligning actions in columns.
+///
+/// If the keymap was returned by [`parse`], [`parse`] of the result returns the
+/// same keymap. A keymap built by hand may be formatted as text that doesn't
+/// parse, e.g. if a layer name is not an identifier or is used by several
+/// layers.
+impl fmt::Display for Keymap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "fingerprint = {:#010x}", self.fingerprint)?;
+
+        for layer in &self.layers {
+            let rows: Vec<Vec<String>> = layer
+                .rows
+                .iter()
+                .map(|row| row.iter().map(|&action| self.fmt_action(action)).collect())
+                .collect();
+
+            let mut widths = Vec::new();
+            for row in &rows {
+                widths.resize(widths.len().max(row.len()), 0);
+                for (width, action) in widths.iter_mut().zip(row) {
+                    *width = action.len().max(*width);
+                }
+            }
+
+            writeln!(f, "\nlayer {} {{", layer.name)?;
+            for row in &rows {
+                let line: Vec<String> = row
+                    .iter()
+                    .zip(&widths)
+                    .map(|(action, &width)| format!("{:width$}", action, width = width))
+                    .collect();
+
+                writeln!(f, "    {}", line.join("  ").trim_end())?;
+            }
+            writeln!(f, "}}")?;
+        }
+
+        if !self.combos.is_empty() {
+            writeln!(f)?;
+        }
+
+        for combo in &self.combos {
+            write!(f, "combo")?;
+            for key in combo.keys() {
+                write!(f, " {}", key.into_raw())?;
+            }
+            writeln!(f, " = {}", self.fmt_action(combo.action))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Error {
+    fn new(span: Range<usize>, message: impl Into<String>) -> Self {
+        Self {
+            span,
+            message: message.into(),
+        }
+    }
+
+    /// Returns the line and the column (both starting with `1`) of the start
+    /// of the error in `text`.
+    pub fn line_col(&self, text: &str) -> (usize, usize) {
+        let before = &text[..self.span.start.min(text.len())];
+        let line = before.matches('\n').count() + 1;
+        let col = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+
+        (line, col)
+    }
+
+    /// Formats the error with the line of `text` where it happened,
+    /// underlining the span.
+    pub fn render(&self, text: &str) -> String {
+        let (line, col) = self.line_col(text);
+        let source = text.lines().nth(line - 1).unwrap_or("");
+
+        let start = self.span.start.min(text.len());
+        let end = self.span.end.clamp(start, text.len());
+        let width = text[start..end]
+            .chars()
+            .take_while(|&c| c != '\n')
+            .count()
+            .max(1);
+
+        let number = line.to_string();
+        let pad = " ".repeat(number.len());
+
+        format!(
+            "error: {msg}\n{pad}--> {line}:{col}\n{pad} |\n{number} | {source}\n{pad} | {space}{marks}\n",
+            msg = self.message,
+            pad = pad,
+            line = line,
+            col = col,
+            number = number,
+            source = source,
+            space = " ".repeat(col - 1),
+            marks = "^".repeat(width),
+        )
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Token<'a> {
+    Ident(&'a str),
+    Number(u32),
+    Punct(char),
+    Newline,
+    End,
+}
+
+struct Lexer<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn next(&mut self) -> Result<(Token<'a>, Range<usize>), Error> {
+        let rest = &self.text[self.pos..];
+        let skipped = rest.len() - rest.trim_start_matches([' ', '\t', '\r']).len();
+        self.pos += skipped;
+
+        let rest = &self.text[self.pos..];
+        let start = self.pos;
+
+        let len = match rest.chars().next() {
+            None => return Ok((Token::End, start..start)),
+            Some('#') => {
+                self.pos += rest.find('\n').unwrap_or(rest.len());
+                return self.next();
+            }
+            Some('\n') => 1,
+            Some(c) if "{}(),=".contains(c) => 1,
+            Some(c) if c.is_ascii_alphanumeric() || c == '_' => rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len()),
+            Some(c) => {
+                let span = start..start + c.len_utf8();
+                return Err(Error::new(span, format!("unexpected character `{}`", c)));
+            }
+        };
+
+        self.pos += len;
+        let span = start..self.pos;
+        let word = &rest[..len];
+
+        let token = match word.as_bytes()[0] {
+            b'\n' => Token::Newline,
+            b'0'..=b'9' => {
+                let number = match word.strip_prefix("0x") {
+                    Some(hex) => u32::from_str_radix(hex, 16),
+                    None => word.parse(),
+                };
+
+                Token::Number(number.map_err(|_| Error::new(span.clone(), "invalid number"))?)
+            }
+            c if c.is_ascii_punctuation() && c != b'_' => Token::Punct(char::from(c)),
+            _ => Token::Ident(word),
+        };
+
+        Ok((token, span))
+    }
+}
+
+/// Action that may refer to a layer by name, which is resolved after all
+/// layers are parsed.
+struct Parsed<'a> {
+    action: Action,
+    layer: Option<(LayerRef<'a>, Range<usize>)>,
+}
+
+/// Layer before layer references are resolved.
+struct ParsedLayer<'a> {
+    name: &'a str,
+    span: Range<usize>,
+    rows: Vec<Vec<Parsed<'a>>>,
+}
+
+struct ParsedCombo<'a> {
+    keys: Vec<(u32, Range<usize>)>,
+    action: Parsed<'a>,
+}
+
+enum LayerRef<'a> {
+    Index(u32),
+    Name(&'a str),
+}
+
+struct Parser<'a> {
+    lexer: Lexer<'a>,
+    peeked: Option<(Token<'a>, Range<usize>)>,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&mut self) -> Result<(Token<'a>, Range<usize>), Error> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.lexer.next()?);
+        }
+
+        Ok(self.peeked.clone().unwrap())
+    }
+
+    fn bump(&mut self) -> Result<(Token<'a>, Range<usize>), Error> {
+        let token = self.peek()?;
+        self.peeked = None;
+        Ok(token)
+    }
+
+    fn skip_newlines(&mut self) -> Result<(), Error> {
+        while self.peek()?.0 == Token::Newline {
+            self.bump()?;
+        }
+
+        Ok(())
+    }
+
+    fn expect(&mut self, punct: char) -> Result<Range<usize>, Error> {
+        match self.bump()? {
+            (Token::Punct(c), span) if c == punct => Ok(span),
+            (_, span) => Err(Error::new(span, format!("expected `{}`", punct))),
+        }
+    }
+
+    fn expect_line_end(&mut self) -> Result<(), Error> {
+        match self.bump()? {
+            (Token::Newline | Token::End, _) => Ok(()),
+            (_, span) => Err(Error::new(span, "expected the end of the line")),
+        }
+    }
+
+    fn ident(&mut self, what: &str) -> Result<(&'a str, Range<usize>), Error> {
+        match self.bump()? {
+            (Token::Ident(ident), span) => Ok((ident, span)),
+            (_, span) => Err(Error::new(span, format!("expected {}", what))),
+        }
+    }
+
+    fn keymap(mut self) -> Result<Keymap, Error> {
+        let mut fingerprint = None;
+        let mut layers: Vec<ParsedLayer<'a>> = Vec::new();
+        let mut combos: Vec<ParsedCombo<'a>> = Vec::new();
+
+        loop {
+            self.skip_newlines()?;
+
+            match self.bump()? {
+                (Token::End, _) => break,
+                (Token::Ident("fingerprint"), span) => {
+                    if fingerprint.is_some() {
+                        return Err(Error::new(span, "fingerprint is already set"));
+                    }
+
+                    self.expect('=')?;
+                    fingerprint = match self.bump()? {
+                        (Token::Number(n), _) => Some(n),
+                        (_, span) => return Err(Error::new(span, "expected a number")),
+                    };
+                    self.expect_line_end()?;
+                }
+                (Token::Ident("layer"), _) => {
+                    let (name, span) = self.ident("a layer name")?;
+                    if layers.iter().any(|layer| layer.name == name) {
+                        return Err(Error::new(
+                            span,
+                            format!("layer `{}` is already defined", name),
+                        ));
+                    }
+
+                    // The number of layers is a `u8` in the binary format
+                    if layers.len() >= usize::from(u8::MAX) {
+                        return Err(Error::new(span, "too many layers"));
+                    }
+
+                    self.expect('{')?;
+                    let rows = self.rows()?;
+                    self.expect_line_end()?;
+
+                    layers.push(ParsedLayer { name, span, rows });
+                }
+                (Token::Ident("combo"), start) => {
+                    let mut keys = Vec::new();
+                    while let (Token::Number(key), span) = self.peek()? {
+                        self.bump()?;
+                        keys.push((key, span));
+                    }
+
+                    let span = start.start..keys.last().map_or(start.end, |(_, span)| span.end);
+                    if keys.is_empty() || keys.len() > MAX_COMBO_KEYS {
+                        let message = format!("combos must have 1 to {} keys", MAX_COMBO_KEYS);
+                        return Err(Error::new(span, message));
+                    }
+
+                    self.expect('=')?;
+                    let action = self.action()?;
+                    self.expect_line_end()?;
+
+                    combos.push(ParsedCombo { keys, action });
+                }
+                (_, span) => {
+                    return Err(Error::new(
+                        span,
+                        "expected `fingerprint`, `layer` or `combo`",
+                    ));
+                }
+            }
+        }
+
+        let keys = layers
+            .first()
+            .map_or(0, |layer| layer.rows.iter().map(Vec::len).sum());
+        for layer in &layers {
+            let len: usize = layer.rows.iter().map(Vec::len).sum();
+            if len != keys {
+                let message = format!(
+                    "layer `{}` has {} keys, but layer `{}` has {}",
+                    layer.name, len, layers[0].name, keys,
+                );
+                return Err(Error::new(layer.span.clone(), message));
+            }
+        }
+
+        let resolve = |parsed: &Parsed<'a>| -> Result<Action, Error> {
+            let (layer, span) = match &parsed.layer {
+                Some(layer) => layer,
+                None => return Ok(parsed.action),
+            };
+
+            let index = match *layer {
+                LayerRef::Index(index) => usize::try_from(index).ok().filter(|&i| i < layers.len()),
+                LayerRef::Name(name) => layers.iter().position(|layer| layer.name == name),
+            };
+
+            let layer = match index {
+                Some(index) => index as u8,
+                None => return Err(Error::new(span.clone(), "there is no such layer")),
+            };
+
+            Ok(match parsed.action {
+                Action::Momentary(_) => Action::Momentary(layer),
+                Action::Toggle(_) => Action::Toggle(layer),
+                Action::LayerTap { tap, .. } => Action::LayerTap { layer, tap },
+                action => action,
+            })
+        };
+
+        let mut combos_out = Vec::new();
+        for combo in &combos {
+            let mut ids = Vec::new();
+            for (key, span) in &combo.keys {
+                if *key as usize >= keys {
+                    let message = format!("there is no key {}, layers have {} keys", key, keys);
+                    return Err(Error::new(span.clone(), message));
+                }
+
+                ids.push(KeyId::from_raw(*key as u16));
+            }
+
+            combos_out.push(Combo::new(&ids, resolve(&combo.action)?));
+        }
+
+        let mut layers_out = Vec::new();
+        for layer in &layers {
+            let rows = layer
+                .rows
+                .iter()
+                .map(|row| row.iter().map(resolve).collect())
+                .collect::<Result<_, _>>()?;
+
+            layers_out.push(Layer {
+                name: layer.name.to_string(),
+                rows,
+            });
+        }
+
+        Ok(Keymap {
+            fingerprint: fingerprint.unwrap_or(0),
+            layers: layers_out,
+            combos: combos_out,
+        })
+    }
+
+    /// Parses rows of a layer, up to and including `}`.
+    fn rows(&mut self) -> Result<Vec<Vec<Parsed<'a>>>, Error> {
+        let mut rows = Vec::new();
+
+        loop {
+            self.skip_newlines()?;
+
+            let mut row = Vec::new();
+            loop {
+                match self.peek()? {
+                    (Token::Punct('}'), _) => {
+                        self.bump()?;
+                        if !row.is_empty() {
+                            rows.push(row);
+                        }
+                        return Ok(rows);
+                    }
+                    (Token::Newline, _) => break,
+                    (Token::End, span) => return Err(Error::new(span, "expected `}`")),
+                    _ => row.push(self.action()?),
+                }
+            }
+
+            rows.push(row);
+        }
+    }
+
+    fn action(&mut self) -> Result<Parsed<'a>, Error> {
+        let (name, span) = self.ident("an action")?;
+
+        let simple = |action| Parsed {
+            action,
+            layer: None,
+        };
+
+        if self.peek()?.0 != Token::Punct('(') {
+            return match name {
+                "XXX" => Ok(simple(Action::No)),
+                "___" => Ok(simple(Action::Transparent)),
+                _ => key_code(name, span).map(Action::Key).map(simple),
+            };
+        }
+
+        self.bump()?;
+
+        let parsed = match name {
+            "MO" | "TG" => {
+                let layer = self.layer_ref()?;
+                let action = if name == "MO" {
+                    Action::Momentary(0)
+                } else {
+                    Action::Toggle(0)
+                };
+
+                Parsed {
+                    action,
+                    layer: Some(layer),
+                }
+            }
+            "LT" => {
+                let layer = self.layer_ref()?;
+                self.expect(',')?;
+                let tap = self.key_code()?;
+
+                Parsed {
+                    action: Action::LayerTap { layer: 0, tap },
+                    layer: Some(layer),
+                }
+            }
+            "TH" => {
+                let tap = self.key_code()?;
+                self.expect(',')?;
+                let hold = self.key_code()?;
+
+                simple(Action::TapHold { tap, hold })
+            }
+            _ => {
+                let message = format!(
+                    "unknown action `{}`, expected `MO`, `TG`, `LT` or `TH`",
+                    name
+                );
+                return Err(Error::new(span, message));
+            }
+        };
+
+        self.expect(')')?;
+        Ok(parsed)
+    }
+
+    fn layer_ref(&mut self) -> Result<(LayerRef<'a>, Range<usize>), Error> {
+        match self.bump()? {
+            (Token::Number(index), span) => Ok((LayerRef::Index(index), span)),
+            (Token::Ident(name), span) => Ok((LayerRef::Name(name), span)),
+            (_, span) => Err(Error::new(span, "expected a layer name or index")),
+        }
+    }
+
+    fn key_code(&mut self) -> Result<KeyCode, Error> {
+        let (name, span) = self.ident("a key code")?;
+        key_code(name, span)
+    }
+}
+
+fn key_code(name: &str, span: Range<usize>) -> Result<KeyCode, Error> {
+    KeyCode::from_name(name).ok_or_else(|| Error::new(span, format!("unknown key code `{}`", name)))
+}
+
+fn is_ident(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        format,
+        string::{String, ToString},
+        vec,
+    };
+
+    use super::{parse, Layer};
+    use crate::{
+        keymap::{self, Action, Combo},
+        phy::KeyId,
+        proto::KeyCode,
+    };
+
+    const TEXT: &str = "
+# Two layers and combos
+fingerprint = 0x1234abcd
+
+layer base {
+    Esc            Q             W      E
+    LT(nav, Space) TH(A, LCtrl)  MO(1)  XXX
+}
+
+layer nav {
+    ___  TG(base)  Left  Right
+    Backspace Semicolon
+    XXX ___  # rows can be split
+}
+
+combo 0 1 = Tab
+combo 3 = LT(nav, Enter)
+";
+
+    #[test]
+    fn parse_keymap() {
+        let keymap = parse(TEXT).unwrap();
+
+        assert_eq!(keymap.fingerprint, 0x1234_abcd);
+        assert_eq!(
+            keymap.layers[0],
+            Layer {
+                name: "base".to_string(),
+                rows: vec![
+                    vec![
+                        Action::Key(KeyCode::Escape),
+                        Action::Key(KeyCode::Q),
+                        Action::Key(KeyCode::W),
+                        Action::Key(KeyCode::E),
+                    ],
+                    vec![
+                        Action::LayerTap {
+                            layer: 1,
+                            tap: KeyCode::Space,
+                        },
+                        Action::TapHold {
+                            tap: KeyCode::A,
+                            hold: KeyCode::LCtrl,
+                        },
+                        Action::Momentary(1),
+                        Action::No,
+                    ],
+                ],
+            }
+        );
+        assert_eq!(
+            keymap.layers[1].rows.concat(),
+            [
+                Action::Transparent,
+                Action::Toggle(0),
+                Action::Key(KeyCode::Left),
+                Action::Key(KeyCode::Right),
+                Action::Key(KeyCode::BSpace),
+                Action::Key(KeyCode::SColon),
+                Action::No,
+                Action::Transparent,
+            ]
+        );
+        assert_eq!(
+            keymap.combos,
+            [
+                Combo::new(
+                    &[KeyId::from_raw(0), KeyId::from_raw(1)],
+                    Action::Key(KeyCode::Tab)
+                ),
+                Combo::new(
+                    &[KeyId::from_raw(3)],
+                    Action::LayerTap {
+                        layer: 1,
+                        tap: KeyCode::Enter,
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let keymap = parse(TEXT).unwrap();
+        let printed = keymap.to_string();
+
+        assert_eq!(parse(&printed), Ok(keymap.clone()));
+        assert_eq!(parse(&printed).unwrap().to_string(), printed);
+
+        // Layers are referred to by name and actions are aligned
+        assert!(printed.contains("\n    LT(nav, Space)  TH(A, LCtrl)  MO(nav)  XXX\n"));
+        assert!(printed.contains("\ncombo 3 = LT(nav, Enter)\n"));
+
+        let empty = parse("").unwrap();
+        assert_eq!(parse(&empty.to_string()), Ok(empty));
+    }
+
+    #[test]
+    fn to_binary() {
+        let keymap = parse(TEXT).unwrap();
+        let bytes = keymap.to_binary().unwrap();
+        let binary = keymap::Keymap::decode(&bytes).unwrap();
+
+        assert_eq!(binary.fingerprint(), keymap.fingerprint);
+        assert_eq!(binary.layers(), 2);
+        for (l, layer) in keymap.layers.iter().enumerate() {
+            for (k, &action) in layer.rows.concat().iter().enumerate() {
+                assert_eq!(binary.action(l as u8, KeyId::from_raw(k as u16)), action);
+            }
+        }
+        assert!(binary.combos().eq(keymap.combos.iter().copied()));
+    }
+
+    /// Returns the text of the span of the error, its line, column and
+    /// message.
+    fn error(text: &str) -> (&str, (usize, usize), String) {
+        let err = parse(text).unwrap_err();
+        (&text[err.span.clone()], err.line_col(text), err.message)
+    }
+
+    #[test]
+    fn errors() {
+        #[rustfmt::skip]
+        let cases = [
+            ("layer a { A Foo }", "Foo", (1, 13), "unknown key code `Foo`"),
+            ("layer a { A; }", ";", (1, 12), "unexpected character `;`"),
+            ("layer a {\n  A B\n", "", (3, 1), "expected `}`"),
+            ("layer a { MO(b) }", "b", (1, 14), "there is no such layer"),
+            ("layer a { TG(1) }", "1", (1, 14), "there is no such layer"),
+            ("layer a { XY(A) }", "XY", (1, 11), "unknown action `XY`, expected `MO`, `TG`, `LT` or `TH`"),
+            ("layer a { LT(a A) }", "A", (1, 16), "expected `,`"),
+            ("layer a { A }\nlayer a { B }", "a", (2, 7), "layer `a` is already defined"),
+            ("layer a { A B }\n\nlayer b { C }", "b", (3, 7), "layer `b` has 1 keys, but layer `a` has 2"),
+            ("layer a { A B }\ncombo 0 2 = C", "2", (2, 9), "there is no key 2, layers have 2 keys"),
+            ("combo 0 1 2 3 4 = A", "combo 0 1 2 3 4", (1, 1), "combos must have 1 to 4 keys"),
+            ("combo = A", "combo", (1, 1), "combos must have 1 to 4 keys"),
+            ("fingerprint = 1\nfingerprint = 2", "fingerprint", (2, 1), "fingerprint is already set"),
+            ("fingerprint = 0xZZ", "0xZZ", (1, 15), "invalid number"),
+            ("fingerprint = 1 2", "2", (1, 17), "expected the end of the line"),
+            ("keys { A }", "keys", (1, 1), "expected `fingerprint`, `layer` or `combo`"),
+        ];
+
+        for &(text, span, line_col, message) in &cases {
+            assert_eq!(
+                error(text),
+                (span, line_col, message.to_string()),
+                "{:?}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn too_many_layers() {
+        let text: String = (0..255)
+            .map(|i| format!("layer l{} {{ A }}\n", i))
+            .collect();
+        let keymap = parse(&text).unwrap();
+        assert_eq!(keymap.layers.len(), 255);
+        assert!(keymap.to_binary().is_ok());
+
+        let text = text + "layer extra { A }\n";
+        assert_eq!(
+            error(&text),
+            ("extra", (256, 7), "too many layers".to_string())
+        );
+    }
+
+    #[test]
+    fn render() {
+        let text = "layer base {\n    A Foo\n}\n";
+        let err = parse(text).unwrap_err();
+
+        assert_eq!(
+            err.render(text),
+            "error: unknown key code `Foo`\n --> 2:7\n  |\n2 |     A Foo\n  |       ^^^\n"
+        );
+    }
+}
@@ -1,13 +1,36 @@
 #![no_std]
 
+#[cfg(any(test, feature = "std"))]
+extern crate std;
+
+mod crc;
+
 /// Things related to the **phy**sical layout of a keyboard (where keys located,
 /// how to read their state, etc).
 ///
 /// Note that this module only identifies keys (via [`KeyId`]) and does not
-/// assign any meaning to them.
-// FIXME: mention what does assign meaning to keys (when something will)
+/// assign any meaning to them, see [`keymap`] for that.
 pub mod phy;
 
 /// Things related to the **proto**calls that communicate with the host
 /// (computer) to tell it which keys are pressed.
 pub mod proto;
+
+/// Split keyboards, communication between the halves.
+///
+/// The halves are connected by a serial link. The primary half (the one
+/// connected to the host) periodically polls the secondary half, which
+/// responds with the state of its keys. See [`split::Remote`] and
+/// [`split::Secondary`]. Both halves can run the same firmware, deciding
+/// which one is the primary at runtime with [`split::Election`].
+pub mod split;
+
+/// Keymaps, assigning actions to [`KeyId`]s, and their binary format.
+///
+/// [`KeyId`]: phy::KeyId
+pub mod keymap;
+
+/// Persistent storage of configuration (keymaps, settings, etc) on flash.
+///
+/// See [`storage::Store`].
+pub mod storage;
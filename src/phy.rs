@@ -1,3 +1,5 @@
+use core::convert::Infallible;
+
 /// Prebuilt [`Layout`] implementations.
 pub mod layouts;
 
@@ -14,6 +16,10 @@ pub trait Layout {
     /// executions and even non-breaking library changes. i.e. users should be
     /// able to assign meaning to [`KeyId`]s and then save it.
     ///
+    /// Keys whose state can't be determined (for example because of ghosting in
+    /// a matrix without diodes) are not yielded. Use [`TryLayout::try_poll`] to
+    /// distinguish them from released keys.
+    ///
     /// It is preferred to choose the smallest possible [`KeyId`]s. For example:
     /// if a keyboard has 4 keys, then [`poll`] should return [`KeyId`]s in
     /// range `[KeyId(0); KeyId(4)]` and [`max_key_id`] should return
@@ -32,6 +38,143 @@ pub trait Layout {
     fn topological_repr(&self) -> Option<top::Repr<'_>> {
         None
     }
+
+    /// Fixed-point version of [`topological_repr`], for layouts whose
+    /// geometry is stored in the firmware of an MCU without an FPU.
+    ///
+    /// Layouts should implement at most one of the two.
+    ///
+    /// [`topological_repr`]: Layout::topological_repr
+    fn fixed_repr(&self) -> Option<top::fixed::Repr<'_>> {
+        None
+    }
+}
+
+/// Physical layout of a keyboard which may fail to read the state of its keys.
+///
+/// This is a fallible version of [`Layout`] for layouts that are scanned
+/// through something that can fail, for example pins of an I2C or SPI GPIO
+/// expander.
+///
+/// Methods are prefixed with `try_`, so that they don't clash with the methods
+/// of [`Layout`] for layouts that implement both traits.
+pub trait TryLayout {
+    /// Error which can happen while scanning keys.
+    type Error;
+
+    /// Calls `f` with an iterator of keys that are pressed or whose state is
+    /// unknown.
+    ///
+    /// Released keys are not yielded, same as in [`Layout::poll`]. If the
+    /// state of some keys couldn't be read, they are yielded with
+    /// [`KeyState::Unknown`] and an error is returned after `f` is called.
+    /// This allows users to decide what to do with such keys, for example keep
+    /// their last known state.
+    ///
+    /// Keys whose state was read, but can't be determined, are yielded with
+    /// [`KeyState::Blocked`]. This is not an error.
+    ///
+    /// [`KeyId`]s must follow the same rules as the ones returned from
+    /// [`Layout::poll`].
+    fn try_poll(&mut self, f: &mut dyn FnMut(&mut KeyStates<'_>)) -> Result<(), Self::Error>;
+
+    /// Maximum [`KeyId`] that can be returned from this [`try_poll`]'s
+    /// iterator.
+    ///
+    /// [`try_poll`]: TryLayout::try_poll
+    fn try_max_key_id(&self) -> KeyId;
+
+    /// Same as [`Layout::topological_repr`].
+    fn try_topological_repr(&self) -> Option<top::Repr<'_>> {
+        None
+    }
+
+    /// Same as [`Layout::fixed_repr`].
+    fn try_fixed_repr(&self) -> Option<top::fixed::Repr<'_>> {
+        None
+    }
+}
+
+/// Layout which can wait for a key press without being polled.
+///
+/// While a layout is waiting, pressing any key changes the level of at least
+/// one of its input pins. For example all rows of a matrix are active, so
+/// pressing any key activates its column. This allows the firmware to configure
+/// edge interrupts on the input pins and sleep instead of polling the layout
+/// periodically.
+///
+/// Configuring the interrupts is up to the firmware, since it depends on the
+/// MCU.
+pub trait Sleep {
+    /// Error which can happen while switching between modes.
+    type Error;
+
+    /// Makes the layout wait for a key press.
+    ///
+    /// The layout shouldn't be polled until [`Sleep::wake`] is called.
+    fn sleep(&mut self) -> Result<(), Self::Error>;
+
+    /// Returns the layout to normal scanning.
+    ///
+    /// This should be called on the first edge of an input pin.
+    fn wake(&mut self) -> Result<(), Self::Error>;
+}
+
+/// Iterator of keys and their states, see [`TryLayout::try_poll`].
+pub type KeyStates<'a> = dyn Iterator<Item = (KeyId, KeyState)> + 'a;
+
+/// State of a key, as reported by [`TryLayout::try_poll`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum KeyState {
+    /// The key is pressed.
+    Pressed,
+    /// The state of the key couldn't be read.
+    Unknown,
+    /// The key may or may not be pressed, the layout can't tell because of the
+    /// state of other keys.
+    ///
+    /// For example in a matrix without diodes pressing three keys which form
+    /// corners of a rectangle makes the fourth key look pressed too.
+    Blocked,
+}
+
+impl KeyState {
+    /// Returns `true` if the key is [pressed](KeyState::Pressed).
+    pub fn pressed(self) -> bool {
+        matches!(self, Self::Pressed)
+    }
+
+    /// Returns `true` if the state of the key is [unknown](KeyState::Unknown).
+    pub fn unknown(self) -> bool {
+        matches!(self, Self::Unknown)
+    }
+
+    /// Returns `true` if the key is [blocked](KeyState::Blocked).
+    pub fn blocked(self) -> bool {
+        matches!(self, Self::Blocked)
+    }
+}
+
+/// Implements [`Layout::poll`] for a [`TryLayout`] which can't fail.
+///
+/// This is useful for layouts which are generic over fallible things (like
+/// pins) and implement [`Layout`] when those can't fail.
+pub fn poll_infallible<L>(layout: &mut L, f: &mut dyn FnMut(&mut dyn Iterator<Item = KeyId>))
+where
+    L: TryLayout<Error = Infallible> + ?Sized,
+{
+    let res = layout.try_poll(&mut |iter| {
+        // `Unknown` can't be returned without an error, so this only filters out
+        // `Blocked` keys
+        f(&mut iter
+            .filter(|(_, state)| state.pressed())
+            .map(|(key, _)| key))
+    });
+
+    match res {
+        Ok(()) => {}
+        Err(never) => match never {},
+    }
 }
 
 /// Identifier of a physical key (switch, button, etc).
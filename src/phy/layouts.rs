@@ -1,20 +1,129 @@
 use core::convert::Infallible;
 
-use embedded_hal::digital::v2::InputPin;
+use embedded_hal::{
+    blocking::delay::DelayUs,
+    digital::v2::{InputPin, OutputPin},
+};
 
-use crate::phy::{KeyId, Layout};
+use crate::phy::{self, KeyId, KeyState, KeyStates, Layout, Sleep, TryLayout};
+
+/// Layout which combines several layouts into one.
+mod chain;
+/// Layout of a quadrature rotary encoder.
+mod encoder;
+/// Layouts of keys connected to MCP23017 or PCA9555 I2C GPIO expanders.
+mod expander;
+/// Layout of analog hall-effect keys.
+mod hall;
+/// Layout of keys placed at intersections of rows and columns.
+mod matrix;
+/// Layout adapter which renumbers and masks keys of another layout.
+mod remap;
+/// Layout of keys connected to a chain of parallel-in/serial-out shift
+/// registers, like 74HC165.
+mod shift_register;
+
+pub use chain::{Chain, ChainError};
+pub use encoder::{
+    Encoder, EncoderError, StepsPerDetent, ENCODER_BUTTON, ENCODER_CLOCKWISE,
+    ENCODER_COUNTER_CLOCKWISE,
+};
+pub use expander::{Chip, Expander, ExpanderMode};
+pub use hall::{Actuation, AnalogSource, Calibration, Channel, Hall, Mux, MuxError};
+pub use matrix::Matrix;
+pub use remap::Remap;
+pub use shift_register::{BitOrder, ShiftIn, ShiftPins, ShiftRegister, ShiftSpi, ShiftSpiError};
+
+/// Level of a pin that means that a key is pressed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ActiveLevel {
+    /// Low = key is pressed, high = key is depressed.
+    ///
+    /// This is the case for switches connected to ground with **pull up**
+    /// resistors.
+    Low,
+    /// High = key is pressed, low = key is depressed.
+    ///
+    /// This is the case for switches connected to power with **pull down**
+    /// resistors, hall-effect sensors with active-high outputs, etc.
+    High,
+}
+
+impl ActiveLevel {
+    /// Returns `true` if `pin` is at this level.
+    pub fn is_active<P: InputPin + ?Sized>(self, pin: &P) -> Result<bool, P::Error> {
+        match self {
+            Self::Low => pin.is_low(),
+            Self::High => pin.is_high(),
+        }
+    }
+
+    /// Sets `pin` to this level.
+    pub fn set_active<P: OutputPin + ?Sized>(self, pin: &mut P) -> Result<(), P::Error> {
+        match self {
+            Self::Low => pin.set_low(),
+            Self::High => pin.set_high(),
+        }
+    }
+
+    /// Sets `pin` to the opposite of this level.
+    pub fn set_inactive<P: OutputPin + ?Sized>(self, pin: &mut P) -> Result<(), P::Error> {
+        match self {
+            Self::Low => pin.set_high(),
+            Self::High => pin.set_low(),
+        }
+    }
+}
+
+/// A pin that is not connected.
+///
+/// This is used as the type of optional pins which are not used, for example
+/// of the interrupt pin of an [`Expander`] that doesn't use interrupts.
+///
+/// This type can't be constructed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum NoPin {}
+
+impl InputPin for NoPin {
+    type Error = Infallible;
+
+    fn is_high(&self) -> Result<bool, Self::Error> {
+        match *self {}
+    }
+
+    fn is_low(&self) -> Result<bool, Self::Error> {
+        match *self {}
+    }
+}
+
+/// A delay that is not used.
+///
+/// This is used as the type of optional delays which are not used, for example
+/// of the settling delay of a [`Mux`] that doesn't wait.
+///
+/// This type can't be constructed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum NoDelay {}
+
+impl DelayUs<u32> for NoDelay {
+    fn delay_us(&mut self, _us: u32) {
+        match *self {}
+    }
+}
 
 /// Array physical layout - every key has it's own pin.
 ///
 /// This layout is "effective" when there are no more than 4 keys. If you have
-/// more than 4 keys, `Matrix` layout (to-be-implemented) uses less pins for the
-/// same amount of keys. Since keyboards rarely have this few keys, this layout
-/// is only useful for testing purposes.
+/// more than 4 keys, [`Matrix`] layout uses less pins for the same amount of
+/// keys. Since keyboards rarely have this few keys, this layout is only useful
+/// for testing purposes.
 ///
-/// **Note**: this layout expects **pull up** pins, i.e. low = key is pressed,
-/// high = key is depressed.
+/// **Note**: by default this layout expects **pull up** pins, i.e. low = key is
+/// pressed, high = key is depressed. Use [`Array::with_active_level`] to
+/// change that.
 pub struct Array<P, const N: usize> {
     pins: [P; N],
+    active: ActiveLevel,
 }
 
 impl<P, const N: usize> Array<P, N> {
@@ -23,34 +132,197 @@ impl<P, const N: usize> Array<P, N> {
     /// **Note**: this expects **pull up** pins, i.e. low = key pressed, high =
     /// key is depressed.
     pub fn new(pins: [P; N]) -> Self {
-        Self { pins }
+        Self::with_active_level(pins, ActiveLevel::Low)
+    }
+
+    /// Creates new array physical layout, where keys are pressed when their
+    /// pins are at the `active` level.
+    pub fn with_active_level(pins: [P; N], active: ActiveLevel) -> Self {
+        Self { pins, active }
+    }
+
+    /// Returns a mutable reference to the pins, for example to configure
+    /// interrupts on them.
+    pub fn pins_mut(&mut self) -> &mut [P; N] {
+        &mut self.pins
     }
 }
 
-impl<P, const N: usize> Layout for Array<P, N>
+impl<P, const N: usize> TryLayout for Array<P, N>
 where
-    P: InputPin<Error = Infallible>,
+    P: InputPin,
 {
-    fn poll(&mut self, f: &mut dyn FnMut(&mut dyn Iterator<Item = KeyId>)) {
-        let mut pressed = [false; N];
+    type Error = P::Error;
 
-        self.pins
-            .iter()
-            .zip(&mut pressed)
-            // Unwrap: Error = Infallible
-            .for_each(|(pin, state)| *state = pin.is_low().unwrap());
+    fn try_poll(&mut self, f: &mut dyn FnMut(&mut KeyStates<'_>)) -> Result<(), Self::Error> {
+        let mut states = [None; N];
+        let mut error = None;
 
-        let mut iter = pressed
+        self.pins.iter().zip(&mut states).for_each(|(pin, state)| {
+            match self.active.is_active(pin) {
+                Ok(true) => *state = Some(KeyState::Pressed),
+                Ok(false) => {}
+                Err(err) => {
+                    *state = Some(KeyState::Unknown);
+                    error.get_or_insert(err);
+                }
+            }
+        });
+
+        let mut iter = states
             .iter()
-            .copied()
             .enumerate()
-            .filter(|&(_, pressed)| pressed)
-            .map(|(k, _)| KeyId::from_raw(k as u16));
+            .filter_map(|(k, &state)| Some((KeyId::from_raw(k as u16), state?)));
+
+        f(iter.by_ref());
 
-        f(iter.by_ref())
+        match error {
+            None => Ok(()),
+            Some(err) => Err(err),
+        }
+    }
+
+    fn try_max_key_id(&self) -> KeyId {
+        KeyId::from_raw(N as _)
+    }
+}
+
+impl<P, const N: usize> Layout for Array<P, N>
+where
+    P: InputPin<Error = Infallible>,
+{
+    fn poll(&mut self, f: &mut dyn FnMut(&mut dyn Iterator<Item = KeyId>)) {
+        phy::poll_infallible(self, f)
     }
 
     fn max_key_id(&self) -> KeyId {
         KeyId::from_raw(N as _)
     }
 }
+
+/// Every key has its own pin, so there is nothing to do.
+impl<P, const N: usize> Sleep for Array<P, N> {
+    type Error = Infallible;
+
+    fn sleep(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn wake(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Raw ids and states of keys yielded by a layout and the result of the poll.
+#[cfg(test)]
+type Scan<E> = (std::vec::Vec<(u16, KeyState)>, Result<(), E>);
+
+/// Polls `layout` once, for tests of layouts.
+#[cfg(test)]
+fn scan<L>(layout: &mut L) -> Scan<L::Error>
+where
+    L: TryLayout + ?Sized,
+{
+    let mut keys = std::vec::Vec::new();
+    let res = layout.try_poll(&mut |iter| keys.extend(iter.map(|(k, s)| (k.into_raw(), s))));
+    (keys, res)
+}
+
+#[cfg(test)]
+mod tests {
+    use core::convert::Infallible;
+    use std::vec;
+
+    use embedded_hal::digital::v2::InputPin;
+
+    use super::{scan, ActiveLevel, Array};
+    use crate::phy::{KeyId, KeyState, Layout, TryLayout};
+
+    /// Pin at a fixed level, or one that fails to read.
+    struct Pin(Result<bool, u8>);
+
+    impl InputPin for Pin {
+        type Error = u8;
+
+        fn is_high(&self) -> Result<bool, Self::Error> {
+            self.0
+        }
+
+        fn is_low(&self) -> Result<bool, Self::Error> {
+            self.0.map(|high| !high)
+        }
+    }
+
+    /// Pin at a fixed level which can't fail to read.
+    struct Level(bool);
+
+    impl InputPin for Level {
+        type Error = Infallible;
+
+        fn is_high(&self) -> Result<bool, Self::Error> {
+            Ok(self.0)
+        }
+
+        fn is_low(&self) -> Result<bool, Self::Error> {
+            Ok(!self.0)
+        }
+    }
+
+    #[test]
+    fn try_poll() {
+        let mut array = Array::new([Pin(Ok(true)), Pin(Ok(false)), Pin(Ok(true)), Pin(Ok(false))]);
+
+        assert_eq!(array.try_max_key_id(), KeyId::from_raw(4));
+        assert_eq!(
+            scan(&mut array),
+            (vec![(1, KeyState::Pressed), (3, KeyState::Pressed)], Ok(()))
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let mut array = Array::new([Pin(Ok(false)), Pin(Err(1)), Pin(Ok(true)), Pin(Err(2))]);
+
+        // All keys are still yielded, and the first error is returned
+        assert_eq!(
+            scan(&mut array),
+            (
+                vec![
+                    (0, KeyState::Pressed),
+                    (1, KeyState::Unknown),
+                    (3, KeyState::Unknown)
+                ],
+                Err(1)
+            )
+        );
+    }
+
+    #[test]
+    fn active_high() {
+        let pins = [Pin(Ok(true)), Pin(Ok(false)), Pin(Ok(true)), Pin(Err(1))];
+        let mut array = Array::with_active_level(pins, ActiveLevel::High);
+
+        assert_eq!(
+            scan(&mut array),
+            (
+                vec![
+                    (0, KeyState::Pressed),
+                    (2, KeyState::Pressed),
+                    (3, KeyState::Unknown)
+                ],
+                Err(1)
+            )
+        );
+    }
+
+    #[test]
+    fn poll() {
+        let mut array = Array::new([Level(false), Level(true), Level(false)]);
+
+        let mut keys = vec![];
+        array.poll(&mut |iter| keys.extend(iter.map(KeyId::into_raw)));
+
+        assert_eq!(array.max_key_id(), KeyId::from_raw(3));
+        assert_eq!(keys, [0, 2]);
+    }
+}
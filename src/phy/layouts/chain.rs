@@ -0,0 +1,569 @@
+use core::{cell::OnceCell, convert::Infallible};
+
+use crate::phy::{
+    top::{
+        self,
+        fixed::{self, Units},
+        KeyPos, ReprBuf,
+    },
+    KeyId, KeyStates, Layout, Sleep, TryLayout,
+};
+
+/// Layout which combines several layouts into one.
+///
+/// `T` is a tuple of 2 to 6 layouts. [`KeyId`]s of every layout are offset by
+/// the sum of [`max_key_id`]s of the preceding layouts, i.e. keys of the first
+/// layout keep their ids, keys of the second layout start right after the keys
+/// of the first one, etc.
+///
+/// This implements [`Layout`] if all the layouts implement [`Layout`] and
+/// [`TryLayout`] if all the layouts implement [`TryLayout`].
+///
+/// `K` is the maximum number of keys in the combined topological
+/// representation. Representations of the layouts are expected to be in the
+/// same coordinate system. If `K` is too small or one of the layouts doesn't
+/// have a topological representation, the combined layout doesn't have one
+/// either. The same goes for fixed-point representations.
+///
+/// ## Panics
+///
+/// Polling the layout or getting its [`max_key_id`] or representations panics
+/// if the sum of [`max_key_id`]s of the layouts doesn't fit in a `u16`, since
+/// the offset [`KeyId`]s would collide.
+///
+/// [`max_key_id`]: Layout::max_key_id
+pub struct Chain<T, const K: usize = 0> {
+    layouts: T,
+    repr: OnceCell<Option<ReprBuf<K>>>,
+    fixed_repr: OnceCell<Option<fixed::ReprBuf<K>>>,
+}
+
+/// Error of the [`Chain`] layout, contains the error of the first layout that
+/// failed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ChainError<A, B, C = Infallible, D = Infallible, E = Infallible, F = Infallible> {
+    /// The first layout failed.
+    First(A),
+    /// The second layout failed.
+    Second(B),
+    /// The third layout failed.
+    Third(C),
+    /// The fourth layout failed.
+    Fourth(D),
+    /// The fifth layout failed.
+    Fifth(E),
+    /// The sixth layout failed.
+    Sixth(F),
+}
+
+impl<T> Chain<T> {
+    /// Combines `layouts` into one layout, without a topological
+    /// representation.
+    pub fn new(layouts: T) -> Self {
+        Self::with_topology(layouts)
+    }
+}
+
+impl<T, const K: usize> Chain<T, K> {
+    /// Combines `layouts` into one layout, with a topological representation
+    /// of up to `K` keys.
+    pub fn with_topology(layouts: T) -> Self {
+        Self {
+            layouts,
+            repr: OnceCell::new(),
+            fixed_repr: OnceCell::new(),
+        }
+    }
+
+    /// Returns a reference to the combined layouts.
+    pub fn inner(&self) -> &T {
+        &self.layouts
+    }
+
+    /// Returns a mutable reference to the combined layouts.
+    ///
+    /// The merged representations are computed again after that, since the
+    /// layouts may change.
+    pub fn inner_mut(&mut self) -> &mut T {
+        self.repr = OnceCell::new();
+        self.fixed_repr = OnceCell::new();
+        &mut self.layouts
+    }
+
+    /// Returns the combined layouts.
+    pub fn into_inner(self) -> T {
+        self.layouts
+    }
+}
+
+/// Returns `id` offset by `offset`.
+///
+/// ## Panics
+///
+/// Panics if the result doesn't fit in a `u16`, see [`Chain`].
+fn add_offset(id: u16, offset: u16) -> u16 {
+    match id.checked_add(offset) {
+        Some(id) => id,
+        None => panic!("`KeyId`s of the chained layouts don't fit in a `u16`"),
+    }
+}
+
+/// Returns the sum of `max_key_ids`, the [`max_key_id`] of the chain.
+///
+/// [`max_key_id`]: Layout::max_key_id
+fn sum_max_key_ids(max_key_ids: &[KeyId]) -> KeyId {
+    let sum = max_key_ids
+        .iter()
+        .fold(0, |sum, id| add_offset(sum, id.into_raw()));
+
+    KeyId::from_raw(sum)
+}
+
+/// Merges representations of layouts, offsetting [`KeyId`]s.
+fn merge<const K: usize>(parts: &[(Option<top::Repr<'_>>, KeyId)]) -> Option<ReprBuf<K>> {
+    let mut buf = ReprBuf::new((0., 0.));
+    let mut offset = 0;
+
+    for (repr, max_key_id) in parts {
+        let repr = repr.as_ref()?;
+
+        for key in repr.keys {
+            let id = KeyId::from_raw(add_offset(key.id.into_raw(), offset));
+            buf.push(KeyPos { id, ..*key }).ok()?;
+        }
+
+        buf.centre.0 += repr.centre.0 / parts.len() as f32;
+        buf.centre.1 += repr.centre.1 / parts.len() as f32;
+        offset = add_offset(offset, max_key_id.into_raw());
+    }
+
+    Some(buf)
+}
+
+/// Same as [`merge`], but for fixed-point representations.
+fn merge_fixed<const K: usize>(
+    parts: &[(Option<fixed::Repr<'_>>, KeyId)],
+) -> Option<fixed::ReprBuf<K>> {
+    let mut buf = fixed::ReprBuf::new((Units(0), Units(0)));
+    let mut centre = (0, 0);
+    let mut offset = 0;
+
+    for (repr, max_key_id) in parts {
+        let repr = repr.as_ref()?;
+
+        for key in repr.keys {
+            let id = KeyId::from_raw(add_offset(key.id.into_raw(), offset));
+            buf.push(fixed::KeyPos { id, ..*key }).ok()?;
+        }
+
+        centre.0 += i32::from(repr.centre.0 .0);
+        centre.1 += i32::from(repr.centre.1 .0);
+        offset = add_offset(offset, max_key_id.into_raw());
+    }
+
+    let len = parts.len() as i32;
+    buf.centre = (
+        Units((centre.0 / len) as i16),
+        Units((centre.1 / len) as i16),
+    );
+
+    Some(buf)
+}
+
+/// Polls layouts one inside another, so that `f` can be called with a single
+/// iterator.
+macro_rules! nest_try_poll {
+    ($f:ident, $error:ident, $offset:expr, $iter:expr;) => {{
+        // There are no layouts left to offset
+        let _: u16 = $offset;
+        $f(&mut $iter)
+    }};
+    ($f:ident, $error:ident, $offset:expr, $iter:expr; $l:ident $v:ident $(, $rest:ident $rest_v:ident)*) => {{
+        let offset: u16 = $offset;
+        let next = add_offset(offset, $l.try_max_key_id().into_raw());
+
+        let res = $l.try_poll(&mut |iter| {
+            let mut iter = iter.map(move |(k, s)| (KeyId::from_raw(add_offset(k.into_raw(), offset)), s));
+            nest_try_poll!($f, $error, next, $iter.chain(&mut iter); $($rest $rest_v),*)
+        });
+
+        // Outer layouts overwrite errors of inner ones, so the first error wins
+        if let Err(err) = res {
+            $error = Some(ChainError::$v(err));
+        }
+    }};
+}
+
+/// Same as [`nest_try_poll`], but for infallible layouts.
+macro_rules! nest_poll {
+    ($f:ident, $offset:expr, $iter:expr;) => {{
+        // There are no layouts left to offset
+        let _: u16 = $offset;
+        $f(&mut $iter)
+    }};
+    ($f:ident, $offset:expr, $iter:expr; $l:ident $(, $rest:ident)*) => {{
+        let offset: u16 = $offset;
+        let next = add_offset(offset, $l.max_key_id().into_raw());
+
+        $l.poll(&mut |iter| {
+            let mut iter = iter.map(move |k| KeyId::from_raw(add_offset(k.into_raw(), offset)));
+            nest_poll!($f, next, $iter.chain(&mut iter); $($rest),*)
+        });
+    }};
+}
+
+macro_rules! chain_impls {
+    ($($L:ident $l:ident $v:ident),+) => {
+        impl<$($L,)+ const K: usize> TryLayout for Chain<($($L,)+), K>
+        where
+            $($L: TryLayout,)+
+        {
+            type Error = ChainError<$($L::Error),+>;
+
+            fn try_poll(&mut self, f: &mut dyn FnMut(&mut KeyStates<'_>)) -> Result<(), Self::Error> {
+                let ($($l,)+) = &mut self.layouts;
+                let mut error = None;
+
+                nest_try_poll!(f, error, 0, core::iter::empty(); $($l $v),+);
+
+                error.map_or(Ok(()), Err)
+            }
+
+            fn try_max_key_id(&self) -> KeyId {
+                let ($($l,)+) = &self.layouts;
+                sum_max_key_ids(&[$($l.try_max_key_id()),+])
+            }
+
+            fn try_topological_repr(&self) -> Option<top::Repr<'_>> {
+                let ($($l,)+) = &self.layouts;
+
+                self.repr
+                    .get_or_init(|| {
+                        merge(&[$(($l.try_topological_repr(), $l.try_max_key_id())),+])
+                    })
+                    .as_ref()
+                    .map(ReprBuf::as_repr)
+            }
+
+            fn try_fixed_repr(&self) -> Option<fixed::Repr<'_>> {
+                let ($($l,)+) = &self.layouts;
+
+                self.fixed_repr
+                    .get_or_init(|| {
+                        merge_fixed(&[$(($l.try_fixed_repr(), $l.try_max_key_id())),+])
+                    })
+                    .as_ref()
+                    .map(fixed::ReprBuf::as_repr)
+            }
+        }
+
+        impl<$($L,)+ const K: usize> Sleep for Chain<($($L,)+), K>
+        where
+            $($L: Sleep,)+
+        {
+            type Error = ChainError<$($L::Error),+>;
+
+            fn sleep(&mut self) -> Result<(), Self::Error> {
+                let ($($l,)+) = &mut self.layouts;
+                $($l.sleep().map_err(ChainError::$v)?;)+
+                Ok(())
+            }
+
+            fn wake(&mut self) -> Result<(), Self::Error> {
+                let ($($l,)+) = &mut self.layouts;
+                $($l.wake().map_err(ChainError::$v)?;)+
+                Ok(())
+            }
+        }
+
+        impl<$($L,)+ const K: usize> Layout for Chain<($($L,)+), K>
+        where
+            $($L: Layout,)+
+        {
+            fn poll(&mut self, f: &mut dyn FnMut(&mut dyn Iterator<Item = KeyId>)) {
+                let ($($l,)+) = &mut self.layouts;
+
+                nest_poll!(f, 0, core::iter::empty(); $($l),+);
+            }
+
+            fn max_key_id(&self) -> KeyId {
+                let ($($l,)+) = &self.layouts;
+                sum_max_key_ids(&[$($l.max_key_id()),+])
+            }
+
+            fn topological_repr(&self) -> Option<top::Repr<'_>> {
+                let ($($l,)+) = &self.layouts;
+
+                self.repr
+                    .get_or_init(|| {
+                        merge(&[$(($l.topological_repr(), $l.max_key_id())),+])
+                    })
+                    .as_ref()
+                    .map(ReprBuf::as_repr)
+            }
+
+            fn fixed_repr(&self) -> Option<fixed::Repr<'_>> {
+                let ($($l,)+) = &self.layouts;
+
+                self.fixed_repr
+                    .get_or_init(|| merge_fixed(&[$(($l.fixed_repr(), $l.max_key_id())),+]))
+                    .as_ref()
+                    .map(fixed::ReprBuf::as_repr)
+            }
+        }
+    };
+}
+
+chain_impls!(A a First, B b Second);
+chain_impls!(A a First, B b Second, C c Third);
+chain_impls!(A a First, B b Second, C c Third, D d Fourth);
+chain_impls!(A a First, B b Second, C c Third, D d Fourth, E e Fifth);
+chain_impls!(A a First, B b Second, C c Third, D d Fourth, E e Fifth, F f_ Sixth);
+
+#[cfg(test)]
+mod tests {
+    use std::{vec, vec::Vec};
+
+    use super::{Chain, ChainError};
+    use crate::phy::{
+        layouts::scan,
+        top::{
+            self,
+            fixed::{self, Units},
+            KeyPos,
+        },
+        KeyId, KeyState, KeyStates, Layout, TryLayout,
+    };
+
+    /// Layout which always reports the same keys.
+    struct Keys {
+        keys: &'static [(u16, KeyState)],
+        max_key_id: u16,
+        repr: Option<top::Repr<'static>>,
+        fixed_repr: Option<fixed::Repr<'static>>,
+        /// Error returned from every poll.
+        error: Option<u8>,
+    }
+
+    impl Keys {
+        const fn new(keys: &'static [(u16, KeyState)], max_key_id: u16) -> Self {
+            Self {
+                keys,
+                max_key_id,
+                repr: None,
+                fixed_repr: None,
+                error: None,
+            }
+        }
+    }
+
+    impl TryLayout for Keys {
+        type Error = u8;
+
+        fn try_poll(&mut self, f: &mut dyn FnMut(&mut KeyStates<'_>)) -> Result<(), Self::Error> {
+            f(&mut self.keys.iter().map(|&(k, s)| (KeyId::from_raw(k), s)));
+            self.error.map_or(Ok(()), Err)
+        }
+
+        fn try_max_key_id(&self) -> KeyId {
+            KeyId::from_raw(self.max_key_id)
+        }
+
+        fn try_topological_repr(&self) -> Option<top::Repr<'_>> {
+            self.repr
+        }
+
+        fn try_fixed_repr(&self) -> Option<fixed::Repr<'_>> {
+            self.fixed_repr
+        }
+    }
+
+    impl Layout for Keys {
+        fn poll(&mut self, f: &mut dyn FnMut(&mut dyn Iterator<Item = KeyId>)) {
+            f(&mut self.keys.iter().map(|&(k, _)| KeyId::from_raw(k)))
+        }
+
+        fn max_key_id(&self) -> KeyId {
+            KeyId::from_raw(self.max_key_id)
+        }
+
+        fn topological_repr(&self) -> Option<top::Repr<'_>> {
+            self.repr
+        }
+
+        fn fixed_repr(&self) -> Option<fixed::Repr<'_>> {
+            self.fixed_repr
+        }
+    }
+
+    const P: KeyState = KeyState::Pressed;
+    const U: KeyState = KeyState::Unknown;
+
+    #[test]
+    fn offsets() {
+        let mut chain = Chain::new((
+            Keys::new(&[(0, P), (2, P)], 3),
+            Keys::new(&[(1, P)], 2),
+            Keys::new(&[(0, P), (3, P)], 4),
+        ));
+
+        assert_eq!(chain.try_max_key_id(), KeyId::from_raw(9));
+        assert_eq!(chain.max_key_id(), KeyId::from_raw(9));
+        assert_eq!(
+            scan(&mut chain),
+            (vec![(0, P), (2, P), (4, P), (5, P), (8, P)], Ok(()))
+        );
+
+        let mut keys = Vec::new();
+        chain.poll(&mut |iter| keys.extend(iter.map(KeyId::into_raw)));
+        assert_eq!(keys, [0, 2, 4, 5, 8]);
+    }
+
+    #[test]
+    fn errors() {
+        let mut chain = Chain::new((
+            Keys::new(&[(0, P)], 1),
+            Keys {
+                error: Some(2),
+                ..Keys::new(&[(0, U), (1, U)], 2)
+            },
+            Keys {
+                error: Some(3),
+                ..Keys::new(&[(0, U)], 1)
+            },
+        ));
+
+        // Keys of all layouts are reported, the first error wins
+        assert_eq!(
+            scan(&mut chain),
+            (
+                vec![(0, P), (1, U), (2, U), (3, U)],
+                Err(ChainError::Second(2))
+            )
+        );
+    }
+
+    #[test]
+    #[should_panic = "don't fit in a `u16`"]
+    fn overflow() {
+        let chain = Chain::new((Keys::new(&[], u16::MAX), Keys::new(&[], 1)));
+        chain.max_key_id();
+    }
+
+    #[test]
+    #[should_panic = "don't fit in a `u16`"]
+    fn overflow_poll() {
+        let mut chain = Chain::new((Keys::new(&[], u16::MAX), Keys::new(&[(0, P)], 1)));
+        chain.poll(&mut |iter| iter.for_each(drop));
+    }
+
+    #[test]
+    fn topology() {
+        const LEFT: [KeyPos; 2] = [
+            KeyPos::new(KeyId::from_raw(0), 0.5, 0.5),
+            KeyPos::new(KeyId::from_raw(1), 1.5, 0.5),
+        ];
+        const RIGHT: [KeyPos; 1] = [KeyPos::new(KeyId::from_raw(0), 5.5, 1.5)];
+
+        let half = |keys: &'static [KeyPos], centre| Keys {
+            repr: Some(top::Repr { keys, centre }),
+            ..Keys::new(&[], keys.len() as u16)
+        };
+
+        let chain = Chain::<_, 3>::with_topology((half(&LEFT, (3., 0.5)), half(&RIGHT, (3., 1.5))));
+        let repr = chain.topological_repr().unwrap();
+        assert_eq!(
+            repr.keys
+                .iter()
+                .map(|k| k.id.into_raw())
+                .collect::<Vec<_>>(),
+            [0, 1, 2]
+        );
+        assert_eq!(repr.key(KeyId::from_raw(2)).unwrap().x, 5.5);
+        assert_eq!(repr.centre, (3., 1.));
+        assert_eq!(chain.try_topological_repr(), Some(repr));
+
+        // Too many keys
+        let chain = Chain::<_, 2>::with_topology((half(&LEFT, (3., 0.5)), half(&RIGHT, (3., 1.5))));
+        assert_eq!(chain.topological_repr(), None);
+
+        // One of the layouts doesn't have a representation
+        let chain = Chain::<_, 3>::with_topology((half(&LEFT, (3., 0.5)), Keys::new(&[], 1)));
+        assert_eq!(chain.topological_repr(), None);
+    }
+
+    #[test]
+    fn inner_mut() {
+        const KEYS: [KeyPos; 1] = [KeyPos::new(KeyId::from_raw(0), 0.5, 0.5)];
+        const FIXED: [fixed::KeyPos; 1] = [fixed::KeyPos::new(
+            KeyId::from_raw(0),
+            Units(128),
+            Units(128),
+        )];
+
+        let mut chain = Chain::<_, 2>::with_topology((Keys::new(&[], 1), Keys::new(&[], 1)));
+        assert_eq!(chain.topological_repr(), None);
+        assert_eq!(chain.fixed_repr(), None);
+
+        // The representations are merged again after the layouts change
+        let (left, right) = chain.inner_mut();
+        for keys in [left, right].iter_mut() {
+            keys.repr = Some(top::Repr {
+                keys: &KEYS,
+                centre: (0.5, 0.5),
+            });
+            keys.fixed_repr = Some(fixed::Repr {
+                keys: &FIXED,
+                centre: (Units(128), Units(128)),
+            });
+        }
+        assert_eq!(chain.topological_repr().unwrap().keys.len(), 2);
+        assert_eq!(chain.fixed_repr().unwrap().keys.len(), 2);
+
+        chain.inner_mut().1.repr = None;
+        chain.inner_mut().1.fixed_repr = None;
+        assert_eq!(chain.topological_repr(), None);
+        assert_eq!(chain.fixed_repr(), None);
+    }
+
+    #[test]
+    fn fixed_topology() {
+        const LEFT: [fixed::KeyPos; 2] = [
+            fixed::KeyPos::new(KeyId::from_raw(0), Units(128), Units(128)),
+            fixed::KeyPos::new(KeyId::from_raw(1), Units(384), Units(128)),
+        ];
+        const RIGHT: [fixed::KeyPos; 1] = [fixed::KeyPos::new(
+            KeyId::from_raw(0),
+            Units(1408),
+            Units(384),
+        )];
+
+        let half = |keys: &'static [fixed::KeyPos], centre| Keys {
+            fixed_repr: Some(fixed::Repr { keys, centre }),
+            ..Keys::new(&[], keys.len() as u16)
+        };
+        let (left, right) = ((Units(768), Units(128)), (Units(768), Units(385)));
+
+        let chain = Chain::<_, 3>::with_topology((half(&LEFT, left), half(&RIGHT, right)));
+        let repr = chain.fixed_repr().unwrap();
+        assert_eq!(
+            repr.keys
+                .iter()
+                .map(|k| (k.id.into_raw(), k.x))
+                .collect::<Vec<_>>(),
+            [(0, Units(128)), (1, Units(384)), (2, Units(1408))]
+        );
+        // Rounded towards zero
+        assert_eq!(repr.centre, (Units(768), Units(256)));
+        assert_eq!(chain.try_fixed_repr(), Some(repr));
+        assert_eq!(chain.topological_repr(), None);
+
+        // Too many keys
+        let chain = Chain::<_, 2>::with_topology((half(&LEFT, left), half(&RIGHT, right)));
+        assert_eq!(chain.fixed_repr(), None);
+
+        // One of the layouts doesn't have a representation
+        let chain = Chain::<_, 3>::with_topology((half(&LEFT, left), Keys::new(&[], 1)));
+        assert_eq!(chain.fixed_repr(), None);
+    }
+}
@@ -0,0 +1,405 @@
+use core::convert::Infallible;
+
+use embedded_hal::digital::v2::InputPin;
+
+use crate::phy::{
+    layouts::{ActiveLevel, NoPin},
+    KeyId, KeyState, KeyStates, Layout, Sleep, TryLayout,
+};
+
+/// [`KeyId`] of the clockwise rotation of an [`Encoder`].
+pub const ENCODER_CLOCKWISE: KeyId = KeyId::from_raw(0);
+/// [`KeyId`] of the counter-clockwise rotation of an [`Encoder`].
+pub const ENCODER_COUNTER_CLOCKWISE: KeyId = KeyId::from_raw(1);
+/// [`KeyId`] of the push button of an [`Encoder`].
+pub const ENCODER_BUTTON: KeyId = KeyId::from_raw(2);
+
+/// Quadrature rotary encoder physical layout.
+///
+/// Rotation of the encoder is reported as momentary key presses: every detent
+/// of clockwise rotation is reported as a press of [`ENCODER_CLOCKWISE`] in one
+/// poll and its release in the next one, same for counter-clockwise rotation
+/// and [`ENCODER_COUNTER_CLOCKWISE`]. This allows assigning meaning to rotation
+/// in the same way as to keys. If the encoder has a push button (see
+/// [`Encoder::with_button`]), it's reported as [`ENCODER_BUTTON`].
+///
+/// Since the encoder needs to be sampled on every step, polling it every
+/// several milliseconds may be too slow if it's rotated quickly. In this case
+/// [`Encoder::sample`] can be called more often (for example from a timer or a
+/// pin change interrupt), detents are accumulated until they are reported.
+///
+/// If the direction is reversed, swap `a` and `b` pins.
+///
+/// **Note**: by default the button is expected to be **pull up**, i.e. low =
+/// button is pressed, high = button is depressed. Use
+/// [`Encoder::active_level`] to change that. Levels of `a` and `b` don't
+/// matter.
+pub struct Encoder<A, B, Btn = NoPin> {
+    a: A,
+    b: B,
+    button: Option<Btn>,
+    active: ActiveLevel,
+    steps_per_detent: StepsPerDetent,
+    /// Last sampled state, `a` in the bit 1 and `b` in the bit 0.
+    state: Option<u8>,
+    /// Steps since the last detent, positive = clockwise.
+    steps: i8,
+    /// Detents which were not reported yet.
+    clockwise: u8,
+    counter_clockwise: u8,
+    /// `true` if the last poll reported a detent (and so this poll needs to
+    /// report a release).
+    pulsed: bool,
+}
+
+/// Error of the [`Encoder`] layout.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EncoderError<P, B> {
+    /// Reading the `a` or `b` pin failed.
+    Pin(P),
+    /// Reading the button pin failed.
+    Button(B),
+}
+
+/// Number of quadrature steps between two detents of an encoder.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum StepsPerDetent {
+    One = 1,
+    Two = 2,
+    Four = 4,
+}
+
+impl<A, B> Encoder<A, B> {
+    /// Creates new rotary encoder physical layout.
+    ///
+    /// `a` and `b` are pins connected to the quadrature outputs of the
+    /// encoder. By default an encoder is expected to make 4 steps per detent.
+    pub fn new(a: A, b: B) -> Self {
+        Self {
+            a,
+            b,
+            button: None,
+            active: ActiveLevel::Low,
+            steps_per_detent: StepsPerDetent::Four,
+            state: None,
+            steps: 0,
+            clockwise: 0,
+            counter_clockwise: 0,
+            pulsed: false,
+        }
+    }
+
+    /// Sets the pin connected to the push button of the encoder.
+    pub fn with_button<Btn>(self, button: Btn) -> Encoder<A, B, Btn> {
+        let Self {
+            a,
+            b,
+            button: _,
+            active,
+            steps_per_detent,
+            state,
+            steps,
+            clockwise,
+            counter_clockwise,
+            pulsed,
+        } = self;
+
+        Encoder {
+            a,
+            b,
+            button: Some(button),
+            active,
+            steps_per_detent,
+            state,
+            steps,
+            clockwise,
+            counter_clockwise,
+            pulsed,
+        }
+    }
+}
+
+impl<A, B, Btn> Encoder<A, B, Btn> {
+    /// Sets the number of steps the encoder makes per detent.
+    pub fn steps_per_detent(self, steps_per_detent: StepsPerDetent) -> Self {
+        Self {
+            steps_per_detent,
+            ..self
+        }
+    }
+
+    /// Sets the level of the button pin that means that it's pressed.
+    pub fn active_level(self, active: ActiveLevel) -> Self {
+        Self { active, ..self }
+    }
+
+    fn key_count(&self) -> u16 {
+        match self.button {
+            Some(_) => 3,
+            None => 2,
+        }
+    }
+}
+
+impl<A, B, Btn, E> Encoder<A, B, Btn>
+where
+    A: InputPin<Error = E>,
+    B: InputPin<Error = E>,
+{
+    /// Reads the state of the encoder, accumulating detents.
+    pub fn sample(&mut self) -> Result<(), E> {
+        // Change of the state -> direction, indexed by `prev << 2 | cur`
+        const STEPS: [i8; 16] = [0, -1, 1, 0, 1, 0, 0, -1, -1, 0, 0, 1, 0, 1, -1, 0];
+
+        let state = (self.a.is_high()? as u8) << 1 | self.b.is_high()? as u8;
+        let prev = self.state.replace(state).unwrap_or(state);
+
+        self.steps += STEPS[usize::from(prev << 2 | state)];
+
+        let steps_per_detent = self.steps_per_detent as i8;
+        if self.steps >= steps_per_detent {
+            self.steps -= steps_per_detent;
+            self.clockwise = self.clockwise.saturating_add(1);
+        } else if self.steps <= -steps_per_detent {
+            self.steps += steps_per_detent;
+            self.counter_clockwise = self.counter_clockwise.saturating_add(1);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the rotation key to report in this poll, if any.
+    fn pulse(&mut self) -> Option<KeyId> {
+        if self.pulsed {
+            // Release the key reported in the last poll
+            self.pulsed = false;
+            return None;
+        }
+
+        let key = if self.clockwise > 0 {
+            self.clockwise -= 1;
+            ENCODER_CLOCKWISE
+        } else if self.counter_clockwise > 0 {
+            self.counter_clockwise -= 1;
+            ENCODER_COUNTER_CLOCKWISE
+        } else {
+            return None;
+        };
+
+        self.pulsed = true;
+        Some(key)
+    }
+}
+
+impl<A, B, Btn, E> TryLayout for Encoder<A, B, Btn>
+where
+    A: InputPin<Error = E>,
+    B: InputPin<Error = E>,
+    Btn: InputPin,
+{
+    type Error = EncoderError<E, Btn::Error>;
+
+    fn try_poll(&mut self, f: &mut dyn FnMut(&mut KeyStates<'_>)) -> Result<(), Self::Error> {
+        let mut error = self.sample().err().map(EncoderError::Pin);
+
+        let pulse = self.pulse().map(|key| (key, KeyState::Pressed));
+
+        let button = match self.button.as_ref().map(|btn| self.active.is_active(btn)) {
+            None | Some(Ok(false)) => None,
+            Some(Ok(true)) => Some((ENCODER_BUTTON, KeyState::Pressed)),
+            Some(Err(err)) => {
+                error.get_or_insert(EncoderError::Button(err));
+                Some((ENCODER_BUTTON, KeyState::Unknown))
+            }
+        };
+
+        f(&mut pulse.into_iter().chain(button));
+
+        error.map_or(Ok(()), Err)
+    }
+
+    fn try_max_key_id(&self) -> KeyId {
+        KeyId::from_raw(self.key_count())
+    }
+}
+
+impl<A, B, Btn> Layout for Encoder<A, B, Btn>
+where
+    A: InputPin<Error = Infallible>,
+    B: InputPin<Error = Infallible>,
+    Btn: InputPin<Error = Infallible>,
+{
+    fn poll(&mut self, f: &mut dyn FnMut(&mut dyn Iterator<Item = KeyId>)) {
+        let res = self.try_poll(&mut |iter| {
+            f(&mut iter
+                .filter(|(_, state)| state.pressed())
+                .map(|(key, _)| key))
+        });
+
+        match res {
+            Ok(()) => {}
+            Err(EncoderError::Pin(never) | EncoderError::Button(never)) => match never {},
+        }
+    }
+
+    fn max_key_id(&self) -> KeyId {
+        KeyId::from_raw(self.key_count())
+    }
+}
+
+/// Both rotation and the button change levels of pins, so there is nothing to
+/// do.
+impl<A, B, Btn> Sleep for Encoder<A, B, Btn> {
+    type Error = Infallible;
+
+    fn sleep(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn wake(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{cell::Cell, convert::Infallible};
+    use std::vec::Vec;
+
+    use embedded_hal::digital::v2::InputPin;
+
+    use super::{
+        Encoder, StepsPerDetent, ENCODER_BUTTON, ENCODER_CLOCKWISE, ENCODER_COUNTER_CLOCKWISE,
+    };
+    use crate::phy::{KeyId, Layout};
+
+    struct Pin<'a>(&'a Cell<bool>);
+
+    impl InputPin for Pin<'_> {
+        type Error = Infallible;
+
+        fn is_high(&self) -> Result<bool, Self::Error> {
+            Ok(self.0.get())
+        }
+
+        fn is_low(&self) -> Result<bool, Self::Error> {
+            Ok(!self.0.get())
+        }
+    }
+
+    /// Levels of `a` and `b` pins of an encoder, starting at a detent.
+    struct Wires {
+        a: Cell<bool>,
+        b: Cell<bool>,
+        position: Cell<i32>,
+    }
+
+    impl Wires {
+        fn new() -> Self {
+            Self {
+                a: Cell::new(false),
+                b: Cell::new(false),
+                position: Cell::new(0),
+            }
+        }
+
+        fn encoder(&self) -> Encoder<Pin<'_>, Pin<'_>> {
+            Encoder::new(Pin(&self.a), Pin(&self.b))
+        }
+
+        /// Makes `steps` quadrature steps (positive = clockwise), sampling
+        /// `encoder` after every one of them.
+        fn turn<Btn>(&self, encoder: &mut Encoder<Pin<'_>, Pin<'_>, Btn>, steps: i32) {
+            // `a` leads `b` when rotating clockwise
+            const GRAY: [(bool, bool); 4] =
+                [(false, false), (true, false), (true, true), (false, true)];
+
+            for _ in 0..steps.abs() {
+                self.position.set(self.position.get() + steps.signum());
+                let (a, b) = GRAY[self.position.get().rem_euclid(4) as usize];
+                self.a.set(a);
+                self.b.set(b);
+
+                let Ok(()) = encoder.sample();
+            }
+        }
+    }
+
+    fn poll(layout: &mut impl Layout) -> Vec<KeyId> {
+        let mut keys = Vec::new();
+        layout.poll(&mut |iter| keys.extend(iter));
+        keys
+    }
+
+    #[test]
+    fn direction() {
+        let wires = Wires::new();
+        let mut encoder = wires.encoder();
+        assert_eq!(poll(&mut encoder), []);
+
+        wires.turn(&mut encoder, 4);
+        assert_eq!(poll(&mut encoder), [ENCODER_CLOCKWISE]);
+        assert_eq!(poll(&mut encoder), []);
+
+        wires.turn(&mut encoder, -4);
+        assert_eq!(poll(&mut encoder), [ENCODER_COUNTER_CLOCKWISE]);
+        assert_eq!(poll(&mut encoder), []);
+
+        // Turning back before the next detent doesn't count
+        wires.turn(&mut encoder, 3);
+        wires.turn(&mut encoder, -3);
+        assert_eq!(poll(&mut encoder), []);
+        assert_eq!(poll(&mut encoder), []);
+    }
+
+    #[test]
+    fn detents() {
+        let wires = Wires::new();
+        let mut encoder = wires.encoder();
+        poll(&mut encoder);
+
+        // Detents accumulated between polls are pressed and released one by
+        // one
+        wires.turn(&mut encoder, 8);
+        wires.turn(&mut encoder, -4);
+        let polls = (0..7).map(|_| poll(&mut encoder)).collect::<Vec<_>>();
+        #[rustfmt::skip]
+        assert_eq!(polls, [
+            &[ENCODER_CLOCKWISE][..], &[], &[ENCODER_CLOCKWISE], &[],
+            &[ENCODER_COUNTER_CLOCKWISE], &[], &[],
+        ]);
+    }
+
+    #[test]
+    fn steps_per_detent() {
+        for (steps_per_detent, steps) in [(StepsPerDetent::One, 1), (StepsPerDetent::Two, 2)] {
+            let wires = Wires::new();
+            let mut encoder = wires.encoder().steps_per_detent(steps_per_detent);
+            poll(&mut encoder);
+
+            wires.turn(&mut encoder, steps - 1);
+            assert_eq!(poll(&mut encoder), [], "{:?}", steps_per_detent);
+            wires.turn(&mut encoder, 1);
+            assert_eq!(
+                poll(&mut encoder),
+                [ENCODER_CLOCKWISE],
+                "{:?}",
+                steps_per_detent
+            );
+        }
+    }
+
+    #[test]
+    fn button() {
+        let wires = Wires::new();
+        let button = Cell::new(true);
+        let mut encoder = wires.encoder().with_button(Pin(&button));
+        assert_eq!(encoder.max_key_id(), KeyId::from_raw(3));
+        assert_eq!(poll(&mut encoder), []);
+
+        button.set(false);
+        wires.turn(&mut encoder, 4);
+        assert_eq!(poll(&mut encoder), [ENCODER_CLOCKWISE, ENCODER_BUTTON]);
+        assert_eq!(poll(&mut encoder), [ENCODER_BUTTON]);
+    }
+}
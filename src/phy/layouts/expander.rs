@@ -0,0 +1,618 @@
+use embedded_hal::{
+    blocking::i2c::{Write, WriteRead},
+    digital::v2::InputPin,
+};
+
+use crate::phy::{
+    layouts::{ActiveLevel, NoPin},
+    KeyId, KeyState, KeyStates, Sleep, TryLayout,
+};
+
+/// I2C GPIO expander physical layout - keys are connected to the pins of an
+/// MCP23017 or a PCA9555 expander.
+///
+/// Pins `GPA0..=GPA7`/`IO0_0..=IO0_7` are numbered `0..8` and pins
+/// `GPB0..=GPB7`/`IO1_0..=IO1_7` are numbered `8..16`. Keys can be either
+/// connected to the pins directly or form a matrix, see [`ExpanderMode`].
+///
+/// Expanders are configured on the first poll and after every error, so it's
+/// fine to (re)connect them while the layout is used (this is useful for
+/// split keyboards where one of the halves is behind an expander).
+///
+/// If an interrupt pin is provided (see [`Expander::with_interrupt`]), the
+/// layout doesn't talk to the expander unless it reports a change, which
+/// saves I2C traffic when nothing happens.
+///
+/// **Note**: by default this layout expects **pull up** inputs, i.e. low = key
+/// is pressed, high = key is depressed. Internal pull ups of the expanders are
+/// enabled. In the [`ExpanderMode::Matrix`] mode diodes should be placed so
+/// that a pressed key pulls its column low when its row is low (cathodes
+/// towards the rows). Use [`Expander::active_level`] to change that.
+pub struct Expander<I2C, Int = NoPin> {
+    i2c: I2C,
+    address: u8,
+    chip: Chip,
+    mode: ExpanderMode,
+    active: ActiveLevel,
+    int: Option<Int>,
+    configured: bool,
+    /// Bitset of pressed keys, as read by the last successful scan.
+    pressed: u64,
+}
+
+/// Model of an I2C GPIO expander.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Chip {
+    /// Microchip MCP23017.
+    Mcp23017,
+    /// NXP (or TI) PCA9555.
+    Pca9555,
+}
+
+/// The way keys are connected to an expander.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ExpanderMode {
+    /// Every key is connected to its own pin, [`KeyId`] of a key is the number
+    /// of its pin.
+    Direct,
+    /// Keys form a matrix, rows and columns of which are connected to the pins
+    /// set in the `rows` and `cols` bitsets.
+    ///
+    /// Rows and columns are numbered in the order of their pins, [`KeyId`] of
+    /// a key in the row `r` and column `c` is `r * cols.count_ones() + c`.
+    Matrix { rows: u16, cols: u16 },
+}
+
+impl<I2C> Expander<I2C> {
+    /// Creates new expander physical layout.
+    ///
+    /// `address` is the 7-bit I2C address of the expander (`0x20..=0x27` for
+    /// both supported chips, depending on the address pins).
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `mode` is [`ExpanderMode::Matrix`] and the same pin is used as
+    /// both a row and a column.
+    pub fn new(i2c: I2C, address: u8, chip: Chip, mode: ExpanderMode) -> Self {
+        if let ExpanderMode::Matrix { rows, cols } = mode {
+            assert_eq!(rows & cols, 0, "pins can't be both rows and columns");
+        }
+
+        Self {
+            i2c,
+            address,
+            chip,
+            mode,
+            active: ActiveLevel::Low,
+            int: None,
+            configured: false,
+            pressed: 0,
+        }
+    }
+
+    /// Sets the pin connected to the (active low) interrupt output of the
+    /// expander.
+    ///
+    /// For MCP23017 both `INTA` and `INTB` are mirrored, so any of them can be
+    /// used.
+    ///
+    /// In the [`ExpanderMode::Matrix`] mode the interrupt is only used to skip
+    /// scans when no keys are pressed, since pressing a key in a column where
+    /// another key is already pressed doesn't change the state of the column.
+    pub fn with_interrupt<Int>(self, int: Int) -> Expander<I2C, Int> {
+        let Self {
+            i2c,
+            address,
+            chip,
+            mode,
+            active,
+            int: _,
+            configured,
+            pressed,
+        } = self;
+
+        Expander {
+            i2c,
+            address,
+            chip,
+            mode,
+            active,
+            int: Some(int),
+            configured,
+            pressed,
+        }
+    }
+}
+
+impl<I2C, Int> Expander<I2C, Int> {
+    /// Sets the level of a pin that means that a key is pressed (in the
+    /// [`ExpanderMode::Matrix`] mode, the level of rows and columns of pressed
+    /// keys).
+    ///
+    /// With [`ActiveLevel::High`] internal pull ups of MCP23017 are disabled
+    /// and inputs need external pull downs. PCA9555 has internal pull ups which
+    /// can't be disabled, so the pull downs need to be strong enough to
+    /// overcome them.
+    pub fn active_level(self, active: ActiveLevel) -> Self {
+        Self { active, ..self }
+    }
+}
+
+impl<I2C, Int, E> Expander<I2C, Int>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+    Int: InputPin,
+{
+    /// Returns `true` if the expander may have changed state since the last
+    /// scan.
+    fn changed(&self) -> bool {
+        let skippable = match self.mode {
+            ExpanderMode::Direct => true,
+            ExpanderMode::Matrix { .. } => self.pressed == 0,
+        };
+
+        match &self.int {
+            // Unwrap: if we can't read the interrupt pin, just scan
+            Some(int) if self.configured && skippable => int.is_low().unwrap_or(true),
+            _ => true,
+        }
+    }
+
+    fn configure(&mut self) -> Result<(), E> {
+        let inputs = match self.mode {
+            ExpanderMode::Direct => 0xFFFF,
+            ExpanderMode::Matrix { cols, .. } => cols,
+        };
+
+        match self.chip {
+            Chip::Mcp23017 => {
+                // Mirror `INTA` and `INTB`
+                self.i2c.write(self.address, &[mcp23017::IOCON, 1 << 6])?;
+                self.write(mcp23017::GPPU, !levels(self.active))?;
+                self.write(mcp23017::OLAT, levels(self.active))?;
+                // Compare with the previous value
+                self.write(mcp23017::INTCON, 0)?;
+                self.write(mcp23017::GPINTEN, inputs)?;
+            }
+            // PCA9555 always generates interrupts on change of inputs
+            Chip::Pca9555 => self.write(pca9555::OUTPUT, levels(self.active))?,
+        }
+
+        self.set_idle()?;
+        self.configured = true;
+
+        Ok(())
+    }
+
+    /// Makes all rows active, so that pressing any key generates an interrupt,
+    /// and clears the interrupt.
+    fn set_idle(&mut self) -> Result<(), E> {
+        let outputs = match self.mode {
+            ExpanderMode::Direct => 0,
+            ExpanderMode::Matrix { rows, .. } => rows,
+        };
+
+        self.set_outputs(outputs)?;
+
+        if self.int.is_some() {
+            self.read_inputs()?;
+        }
+
+        Ok(())
+    }
+
+    fn scan(&mut self) -> Result<u64, E> {
+        match self.mode {
+            ExpanderMode::Direct => Ok(u64::from(self.read_active()?)),
+            ExpanderMode::Matrix { rows, cols } => {
+                let mut pressed = 0;
+                let ncols = cols.count_ones();
+
+                for (r, row) in pins(rows).enumerate() {
+                    self.set_outputs(1 << row)?;
+                    let inputs = self.read_active()?;
+
+                    for (c, col) in pins(cols).enumerate() {
+                        if inputs & (1 << col) != 0 {
+                            pressed |= 1 << (r as u32 * ncols + c as u32);
+                        }
+                    }
+                }
+
+                self.set_idle()?;
+
+                Ok(pressed)
+            }
+        }
+    }
+
+    /// Makes `pins` outputs (which are always active) and all the other pins
+    /// inputs.
+    fn set_outputs(&mut self, pins: u16) -> Result<(), E> {
+        let reg = match self.chip {
+            Chip::Mcp23017 => mcp23017::IODIR,
+            Chip::Pca9555 => pca9555::CONFIG,
+        };
+
+        self.write(reg, !pins)
+    }
+
+    fn read_inputs(&mut self) -> Result<u16, E> {
+        let reg = match self.chip {
+            Chip::Mcp23017 => mcp23017::GPIO,
+            Chip::Pca9555 => pca9555::INPUT,
+        };
+
+        let mut buf = [0; 2];
+        self.i2c.write_read(self.address, &[reg], &mut buf)?;
+
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    /// Returns a bitset of active inputs.
+    fn read_active(&mut self) -> Result<u16, E> {
+        let inputs = self.read_inputs()?;
+        Ok(!(inputs ^ levels(self.active)))
+    }
+
+    /// Writes `value` to a pair of registers, starting at `reg`.
+    fn write(&mut self, reg: u8, value: u16) -> Result<(), E> {
+        let [a, b] = value.to_le_bytes();
+        self.i2c.write(self.address, &[reg, a, b])
+    }
+
+    fn key_count(&self) -> u16 {
+        match self.mode {
+            ExpanderMode::Direct => 16,
+            ExpanderMode::Matrix { rows, cols } => (rows.count_ones() * cols.count_ones()) as u16,
+        }
+    }
+}
+
+impl<I2C, Int, E> TryLayout for Expander<I2C, Int>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+    Int: InputPin,
+{
+    type Error = E;
+
+    fn try_poll(&mut self, f: &mut dyn FnMut(&mut KeyStates<'_>)) -> Result<(), Self::Error> {
+        let res = if !self.configured {
+            self.configure().and_then(|()| self.scan())
+        } else if self.changed() {
+            self.scan()
+        } else {
+            Ok(self.pressed)
+        };
+
+        let count = self.key_count();
+
+        match res {
+            Ok(pressed) => {
+                self.pressed = pressed;
+
+                let mut iter = (0..count)
+                    .filter(|&k| pressed & (1 << k) != 0)
+                    .map(|k| (KeyId::from_raw(k), KeyState::Pressed));
+
+                f(iter.by_ref());
+
+                Ok(())
+            }
+            Err(err) => {
+                // The expander may have been reset or disconnected, configure
+                // it again the next time
+                self.configured = false;
+
+                f(&mut (0..count).map(|k| (KeyId::from_raw(k), KeyState::Unknown)));
+
+                Err(err)
+            }
+        }
+    }
+
+    fn try_max_key_id(&self) -> KeyId {
+        KeyId::from_raw(self.key_count())
+    }
+}
+
+/// While waiting, the interrupt output of the expander is asserted when any key
+/// is pressed (in the [`ExpanderMode::Matrix`] mode all rows are active),
+/// so the interrupt pin of the MCU should be configured instead of the key
+/// pins.
+impl<I2C, Int, E> Sleep for Expander<I2C, Int>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+    Int: InputPin,
+{
+    type Error = E;
+
+    fn sleep(&mut self) -> Result<(), Self::Error> {
+        let res = if self.configured {
+            self.set_idle()
+        } else {
+            self.configure()
+        };
+
+        if res.is_err() {
+            self.configured = false;
+        }
+
+        res
+    }
+
+    fn wake(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Returns the value of a pair of registers that sets all pins to `level`.
+fn levels(level: ActiveLevel) -> u16 {
+    match level {
+        ActiveLevel::Low => 0,
+        ActiveLevel::High => 0xFFFF,
+    }
+}
+
+/// Returns an iterator over numbers of set bits in `pins`.
+fn pins(pins: u16) -> impl Iterator<Item = u16> {
+    (0..16).filter(move |&pin| pins & (1 << pin) != 0)
+}
+
+/// MCP23017 registers (with `IOCON.BANK = 0`).
+mod mcp23017 {
+    pub const IODIR: u8 = 0x00;
+    pub const GPINTEN: u8 = 0x04;
+    pub const INTCON: u8 = 0x08;
+    pub const IOCON: u8 = 0x0A;
+    pub const GPPU: u8 = 0x0C;
+    pub const GPIO: u8 = 0x12;
+    pub const OLAT: u8 = 0x14;
+}
+
+/// PCA9555 registers.
+mod pca9555 {
+    pub const INPUT: u8 = 0x00;
+    pub const OUTPUT: u8 = 0x02;
+    pub const CONFIG: u8 = 0x06;
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{cell::Cell, convert::Infallible};
+    use std::{vec, vec::Vec};
+
+    use embedded_hal::{
+        blocking::i2c::{Write, WriteRead},
+        digital::v2::InputPin,
+    };
+
+    use super::{mcp23017, pca9555, Chip, Expander, ExpanderMode};
+    use crate::phy::{layouts::scan, KeyState};
+
+    const ADDRESS: u8 = 0x21;
+
+    /// An expander with pull ups and switches connected to its pins.
+    struct Bus {
+        chip: Chip,
+        regs: Cell<[u8; 0x16]>,
+        /// Pressed switches, between a pin and another pin (through a diode
+        /// from the second one) or ground.
+        pressed: Cell<&'static [(u16, Option<u16>)]>,
+        fail: Cell<bool>,
+        transactions: Cell<usize>,
+    }
+
+    struct I2c<'a>(&'a Bus);
+
+    /// Interrupt output of the expander, asserted if `true`.
+    struct Int<'a>(&'a Cell<bool>);
+
+    impl Bus {
+        fn new(chip: Chip) -> Self {
+            Self {
+                chip,
+                regs: Cell::new([0; 0x16]),
+                pressed: Cell::new(&[]),
+                fail: Cell::new(false),
+                transactions: Cell::new(0),
+            }
+        }
+
+        fn reg(&self, reg: u8) -> u16 {
+            let regs = self.regs.get();
+            u16::from_le_bytes([regs[usize::from(reg)], regs[usize::from(reg) + 1]])
+        }
+
+        /// Returns pins configured as outputs and levels of all pins.
+        fn levels(&self) -> (u16, u16) {
+            let (dir, out) = match self.chip {
+                Chip::Mcp23017 => (mcp23017::IODIR, mcp23017::OLAT),
+                Chip::Pca9555 => (pca9555::CONFIG, pca9555::OUTPUT),
+            };
+            let (outputs, out) = (!self.reg(dir), self.reg(out));
+            let low = |pin: u16| outputs & (1 << pin) != 0 && out & (1 << pin) == 0;
+
+            let mut levels = !outputs | (outputs & out);
+            for &(pin, from) in self.pressed.get() {
+                if outputs & (1 << pin) == 0 && from.is_none_or(low) {
+                    levels &= !(1 << pin);
+                }
+            }
+
+            (outputs, levels)
+        }
+
+        fn transaction(&self) -> Result<(), ()> {
+            self.transactions.set(self.transactions.get() + 1);
+
+            match self.fail.get() {
+                true => Err(()),
+                false => Ok(()),
+            }
+        }
+    }
+
+    impl Write for I2c<'_> {
+        type Error = ();
+
+        fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+            self.0.transaction()?;
+            assert_eq!(address, ADDRESS);
+
+            let mut regs = self.0.regs.get();
+            let start = usize::from(bytes[0]);
+            regs[start..start + bytes.len() - 1].copy_from_slice(&bytes[1..]);
+            self.0.regs.set(regs);
+
+            Ok(())
+        }
+    }
+
+    impl WriteRead for I2c<'_> {
+        type Error = ();
+
+        fn write_read(
+            &mut self,
+            address: u8,
+            bytes: &[u8],
+            buffer: &mut [u8],
+        ) -> Result<(), Self::Error> {
+            self.0.transaction()?;
+            assert_eq!(address, ADDRESS);
+
+            let input = match self.0.chip {
+                Chip::Mcp23017 => mcp23017::GPIO,
+                Chip::Pca9555 => pca9555::INPUT,
+            };
+            assert_eq!(bytes, [input]);
+
+            buffer.copy_from_slice(&self.0.levels().1.to_le_bytes());
+            Ok(())
+        }
+    }
+
+    impl InputPin for Int<'_> {
+        type Error = Infallible;
+
+        fn is_high(&self) -> Result<bool, Self::Error> {
+            Ok(!self.0.get())
+        }
+
+        fn is_low(&self) -> Result<bool, Self::Error> {
+            Ok(self.0.get())
+        }
+    }
+
+    fn pressed(keys: &[(u16, KeyState)]) -> Vec<u16> {
+        assert!(keys.iter().all(|&(_, state)| state == KeyState::Pressed));
+        keys.iter().map(|&(k, _)| k).collect()
+    }
+
+    #[test]
+    fn direct() {
+        for chip in [Chip::Mcp23017, Chip::Pca9555] {
+            let bus = Bus::new(chip);
+            let mut layout = Expander::new(I2c(&bus), ADDRESS, chip, ExpanderMode::Direct);
+
+            bus.pressed.set(&[(3, None), (12, None)]);
+            let (keys, res) = scan(&mut layout);
+            assert_eq!((pressed(&keys), res), (vec![3, 12], Ok(())), "{:?}", chip);
+            assert_eq!(bus.levels().0, 0, "{:?}", chip);
+        }
+    }
+
+    #[test]
+    fn matrix() {
+        // Rows are `IO1_0` and `IO1_1`, columns are `IO0_0`, `IO0_2` and `IO0_5`
+        let mode = ExpanderMode::Matrix {
+            rows: 0b11 << 8,
+            cols: 0b10_0101,
+        };
+
+        for chip in [Chip::Mcp23017, Chip::Pca9555] {
+            let bus = Bus::new(chip);
+            let mut layout = Expander::new(I2c(&bus), ADDRESS, chip, mode);
+
+            // Row 0 and column 1, row 1 and column 2
+            bus.pressed.set(&[(2, Some(8)), (5, Some(9))]);
+            let (keys, res) = scan(&mut layout);
+            assert_eq!((pressed(&keys), res), (vec![1, 5], Ok(())), "{:?}", chip);
+
+            // All rows are active between scans
+            assert_eq!(bus.levels().0, 0b11 << 8, "{:?}", chip);
+        }
+    }
+
+    #[test]
+    fn interrupt() {
+        let bus = Bus::new(Chip::Mcp23017);
+        let int = Cell::new(false);
+        let mut layout = Expander::new(I2c(&bus), ADDRESS, Chip::Mcp23017, ExpanderMode::Direct)
+            .with_interrupt(Int(&int));
+
+        // The first poll always configures and scans the expander
+        bus.pressed.set(&[(7, None)]);
+        assert_eq!(pressed(&scan(&mut layout).0), [7]);
+
+        // Without an interrupt the last state is reported
+        bus.transactions.set(0);
+        bus.pressed.set(&[(7, None), (8, None)]);
+        assert_eq!(pressed(&scan(&mut layout).0), [7]);
+        assert_eq!(bus.transactions.get(), 0);
+
+        int.set(true);
+        assert_eq!(pressed(&scan(&mut layout).0), [7, 8]);
+        assert_eq!(bus.transactions.get(), 1);
+    }
+
+    #[test]
+    fn matrix_interrupt() {
+        let mode = ExpanderMode::Matrix {
+            rows: 0b11,
+            cols: 0b1100,
+        };
+        let bus = Bus::new(Chip::Pca9555);
+        let int = Cell::new(false);
+        let mut layout =
+            Expander::new(I2c(&bus), ADDRESS, Chip::Pca9555, mode).with_interrupt(Int(&int));
+
+        assert_eq!(scan(&mut layout), (vec![], Ok(())));
+
+        // Scans are skipped only while no keys are pressed
+        bus.transactions.set(0);
+        assert_eq!(scan(&mut layout), (vec![], Ok(())));
+        assert_eq!(bus.transactions.get(), 0);
+
+        bus.pressed.set(&[(2, Some(0))]);
+        int.set(true);
+        assert_eq!(pressed(&scan(&mut layout).0), [0]);
+        int.set(false);
+
+        // Pressing a key in the same column doesn't trigger the interrupt
+        bus.pressed.set(&[(2, Some(0)), (2, Some(1))]);
+        assert_eq!(pressed(&scan(&mut layout).0), [0, 2]);
+    }
+
+    #[test]
+    fn errors() {
+        let bus = Bus::new(Chip::Mcp23017);
+        let mut layout = Expander::new(I2c(&bus), ADDRESS, Chip::Mcp23017, ExpanderMode::Direct);
+        bus.pressed.set(&[(0, None)]);
+        assert_eq!(pressed(&scan(&mut layout).0), [0]);
+
+        // All keys are unknown
+        bus.fail.set(true);
+        let (keys, res) = scan(&mut layout);
+        assert_eq!(res, Err(()));
+        assert_eq!(keys.len(), 16);
+        assert!(keys.iter().all(|&(_, state)| state == KeyState::Unknown));
+
+        // The expander is configured again after a reset
+        bus.fail.set(false);
+        bus.regs.set([0; 0x16]);
+        assert_eq!(pressed(&scan(&mut layout).0), [0]);
+        assert_eq!(bus.reg(mcp23017::IODIR), 0xFFFF);
+        assert_eq!(bus.reg(mcp23017::GPPU), 0xFFFF);
+    }
+}
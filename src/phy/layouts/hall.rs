@@ -0,0 +1,713 @@
+use core::{convert::Infallible, marker::PhantomData};
+
+use embedded_hal::{
+    adc::{self, OneShot},
+    blocking::delay::DelayUs,
+    digital::v2::OutputPin,
+};
+
+use crate::phy::{self, layouts::NoDelay, KeyId, KeyState, KeyStates, Layout, TryLayout};
+
+/// Analog hall-effect physical layout - every key has a hall-effect sensor
+/// which measures how far the key is pressed.
+///
+/// Raw readings of sensors are converted to key travel using per-key
+/// [`Calibration`]. Travel is a number in range `0..=255`, `0` means that the
+/// key is at rest and `255` means that it's pressed all the way down. Travel
+/// is then compared to per-key [`Actuation`] points to decide whether the key
+/// is pressed. Travel of keys (as of the last poll) can be inspected with
+/// [`Hall::travel`] and [`Hall::travels`], raw readings of sensors with
+/// [`Hall::raw`], for example to calibrate the bottom positions.
+///
+/// Sensors are read through an [`AnalogSource`], see [`Channel`] and [`Mux`].
+pub struct Hall<S, const N: usize> {
+    source: S,
+    keys: [KeyTravel; N],
+}
+
+/// Calibration of a hall-effect key, raw sensor readings at the extreme
+/// positions of the key.
+///
+/// `rest` may be both smaller or bigger than `bottom`, depending on the
+/// orientation of the magnet.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Calibration {
+    /// Raw reading when the key is not pressed.
+    pub rest: u16,
+    /// Raw reading when the key is pressed all the way down.
+    pub bottom: u16,
+}
+
+/// Actuation settings of a hall-effect key.
+///
+/// All the points are in units of travel (`0..=255`).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Actuation {
+    /// Travel at which the key is pressed.
+    pub actuation: u8,
+    /// Travel at which the key is released, should be smaller than
+    /// `actuation`.
+    pub release: u8,
+    /// Rapid trigger sensitivity.
+    ///
+    /// If set, after the key reaches the `actuation` point, it's released as
+    /// soon as it moves up by this much and is pressed again as soon as it
+    /// moves down by this much. This continues until the key goes above the
+    /// `release` point.
+    pub rapid_trigger: Option<u8>,
+}
+
+/// A source of raw analog readings of hall-effect sensors.
+pub trait AnalogSource {
+    /// Error which can happen while reading a sensor.
+    type Error;
+
+    /// Reads the sensor of the key with index `key`.
+    fn read(&mut self, key: usize) -> nb::Result<u16, Self::Error>;
+}
+
+/// A single ADC channel, [`AnalogSource`] with one sensor.
+///
+/// Multiple channels can be combined with a closure which matches on the key
+/// index and reads the corresponding channel, since
+/// `FnMut(usize) -> nb::Result<u16, E>` implements [`AnalogSource`].
+pub struct Channel<Adc, A, P> {
+    adc: A,
+    pin: P,
+    _adc: PhantomData<Adc>,
+}
+
+/// Analog multiplexer (like 74HC4067 or 74HC4051) with `B` select pins in
+/// front of another [`AnalogSource`].
+///
+/// Key `k` is read by selecting the input `k % 2^B` of the multiplexer
+/// connected to the sensor `k / 2^B` of the inner source. So multiple
+/// multiplexers with shared select pins can be put in front of multiple ADC
+/// channels.
+///
+/// The output of a multiplexer (and the ADC sample capacitor) needs some time
+/// to settle after the input is switched, otherwise readings are affected by
+/// the previous input. Use [`Mux::with_settle_delay`] if the inner source
+/// doesn't sample long enough by itself.
+pub struct Mux<S, Sel, const B: usize, D = NoDelay> {
+    inner: S,
+    select: [Sel; B],
+    settle: Option<(D, u32)>,
+    /// Currently selected input.
+    selected: Option<usize>,
+}
+
+/// Error of the [`Mux`] analog source.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MuxError<S, P> {
+    /// Reading the inner source failed.
+    Source(S),
+    /// Setting a select pin failed.
+    Pin(P),
+}
+
+#[derive(Debug, Copy, Clone)]
+struct KeyTravel {
+    calibration: Calibration,
+    actuation: Actuation,
+    /// Last raw reading of the sensor.
+    raw: u16,
+    travel: u8,
+    pressed: bool,
+    /// Travel at which the key last changed the direction, used for rapid
+    /// trigger.
+    extreme: u8,
+    /// `true` if the key was actuated and didn't go above the release point
+    /// since, i.e. if rapid trigger is active.
+    rapid: bool,
+}
+
+impl Actuation {
+    /// Default actuation settings, actuation at ~50% of travel, release at
+    /// ~40%, without rapid trigger.
+    pub const DEFAULT: Self = Self {
+        actuation: 128,
+        release: 102,
+        rapid_trigger: None,
+    };
+}
+
+impl Default for Actuation {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl Calibration {
+    /// Converts a raw reading to the travel of the key.
+    pub fn travel(self, raw: u16) -> u8 {
+        let (rest, bottom, raw) = (i32::from(self.rest), i32::from(self.bottom), i32::from(raw));
+
+        if rest == bottom {
+            return 0;
+        }
+
+        ((raw - rest) * 255 / (bottom - rest)).clamp(0, 255) as u8
+    }
+}
+
+impl<S, const N: usize> Hall<S, N> {
+    /// Creates new hall-effect physical layout.
+    ///
+    /// All keys initially use the same `calibration` and
+    /// [`Actuation::DEFAULT`].
+    pub fn new(source: S, calibration: Calibration) -> Self {
+        let key = KeyTravel {
+            calibration,
+            actuation: Actuation::DEFAULT,
+            raw: calibration.rest,
+            travel: 0,
+            pressed: false,
+            extreme: 0,
+            rapid: false,
+        };
+
+        Self {
+            source,
+            keys: [key; N],
+        }
+    }
+
+    /// Sets actuation settings of all keys.
+    pub fn actuation(mut self, actuation: Actuation) -> Self {
+        self.keys.iter_mut().for_each(|k| k.actuation = actuation);
+        self
+    }
+
+    /// Sets actuation settings of the key `key`.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `key` is out of range.
+    pub fn set_actuation(&mut self, key: KeyId, actuation: Actuation) {
+        self.keys[usize::from(key.into_raw())].actuation = actuation;
+    }
+
+    /// Sets calibration of the key `key`.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `key` is out of range.
+    pub fn set_calibration(&mut self, key: KeyId, calibration: Calibration) {
+        self.keys[usize::from(key.into_raw())].calibration = calibration;
+    }
+
+    /// Returns calibration of the key `key`.
+    pub fn calibration(&self, key: KeyId) -> Option<Calibration> {
+        self.keys
+            .get(usize::from(key.into_raw()))
+            .map(|k| k.calibration)
+    }
+
+    /// Returns travel of the key `key`, as of the last poll.
+    pub fn travel(&self, key: KeyId) -> Option<u8> {
+        self.keys.get(usize::from(key.into_raw())).map(|k| k.travel)
+    }
+
+    /// Returns an iterator over travels of all keys, as of the last poll.
+    pub fn travels(&self) -> impl Iterator<Item = (KeyId, u8)> + '_ {
+        self.keys
+            .iter()
+            .enumerate()
+            .map(|(k, key)| (KeyId::from_raw(k as u16), key.travel))
+    }
+
+    /// Returns the raw sensor reading of the key `key`, as of the last poll.
+    pub fn raw(&self, key: KeyId) -> Option<u16> {
+        self.keys.get(usize::from(key.into_raw())).map(|k| k.raw)
+    }
+}
+
+impl<S, const N: usize> Hall<S, N>
+where
+    S: AnalogSource,
+{
+    /// Reads all sensors and uses the readings as the rest positions of keys.
+    ///
+    /// This should be called when no keys are pressed, for example on startup.
+    pub fn calibrate_rest(&mut self) -> Result<(), S::Error> {
+        for (k, key) in self.keys.iter_mut().enumerate() {
+            key.raw = nb::block!(self.source.read(k))?;
+            key.calibration.rest = key.raw;
+        }
+
+        Ok(())
+    }
+}
+
+impl<S, const N: usize> TryLayout for Hall<S, N>
+where
+    S: AnalogSource,
+{
+    type Error = S::Error;
+
+    fn try_poll(&mut self, f: &mut dyn FnMut(&mut KeyStates<'_>)) -> Result<(), Self::Error> {
+        let mut states = [None; N];
+        let mut error = None;
+
+        for (k, (key, state)) in self.keys.iter_mut().zip(&mut states).enumerate() {
+            match nb::block!(self.source.read(k)) {
+                Ok(raw) => {
+                    key.update(raw);
+                    if key.pressed {
+                        *state = Some(KeyState::Pressed);
+                    }
+                }
+                Err(err) => {
+                    *state = Some(KeyState::Unknown);
+                    error.get_or_insert(err);
+                }
+            }
+        }
+
+        let mut iter = states
+            .iter()
+            .enumerate()
+            .filter_map(|(k, &state)| Some((KeyId::from_raw(k as u16), state?)));
+
+        f(&mut iter);
+
+        error.map_or(Ok(()), Err)
+    }
+
+    fn try_max_key_id(&self) -> KeyId {
+        KeyId::from_raw(N as _)
+    }
+}
+
+impl<S, const N: usize> Layout for Hall<S, N>
+where
+    S: AnalogSource<Error = Infallible>,
+{
+    fn poll(&mut self, f: &mut dyn FnMut(&mut dyn Iterator<Item = KeyId>)) {
+        phy::poll_infallible(self, f)
+    }
+
+    fn max_key_id(&self) -> KeyId {
+        KeyId::from_raw(N as _)
+    }
+}
+
+impl KeyTravel {
+    fn update(&mut self, raw: u16) {
+        let travel = self.calibration.travel(raw);
+        let Actuation {
+            actuation,
+            release,
+            rapid_trigger,
+        } = self.actuation;
+
+        self.raw = raw;
+        self.travel = travel;
+
+        if travel <= release {
+            self.rapid = false;
+        }
+
+        if self.pressed {
+            self.extreme = self.extreme.max(travel);
+
+            let rapid_release =
+                rapid_trigger.is_some_and(|s| travel <= self.extreme.saturating_sub(s));
+
+            if travel <= release || rapid_release {
+                self.pressed = false;
+                self.extreme = travel;
+            }
+        } else {
+            self.extreme = self.extreme.min(travel);
+
+            let rapid_press = self.rapid
+                && rapid_trigger.is_some_and(|s| travel >= self.extreme.saturating_add(s));
+
+            // While rapid trigger is active, only movement matters, not the
+            // actuation point
+            if (!self.rapid && travel >= actuation) || rapid_press {
+                self.pressed = true;
+                self.rapid = true;
+                self.extreme = travel;
+            }
+        }
+    }
+}
+
+impl<Adc, A, P> Channel<Adc, A, P> {
+    /// Creates a source which reads `pin` with `adc`.
+    pub fn new(adc: A, pin: P) -> Self {
+        Self {
+            adc,
+            pin,
+            _adc: PhantomData,
+        }
+    }
+
+    /// Returns the ADC and the pin.
+    pub fn into_inner(self) -> (A, P) {
+        (self.adc, self.pin)
+    }
+}
+
+impl<Adc, A, P> AnalogSource for Channel<Adc, A, P>
+where
+    A: OneShot<Adc, u16, P>,
+    P: adc::Channel<Adc>,
+{
+    type Error = A::Error;
+
+    fn read(&mut self, _key: usize) -> nb::Result<u16, Self::Error> {
+        self.adc.read(&mut self.pin)
+    }
+}
+
+impl<S, Sel, const B: usize> Mux<S, Sel, B> {
+    /// Creates a multiplexer in front of `inner` source, `select` are the
+    /// select pins, least significant first.
+    pub fn new(inner: S, select: [Sel; B]) -> Self {
+        Self {
+            inner,
+            select,
+            settle: None,
+            selected: None,
+        }
+    }
+
+    /// Makes the multiplexer wait `us` microseconds with `delay` after
+    /// switching the input, before reading the inner source.
+    pub fn with_settle_delay<D>(self, delay: D, us: u32) -> Mux<S, Sel, B, D> {
+        Mux {
+            inner: self.inner,
+            select: self.select,
+            settle: Some((delay, us)),
+            selected: self.selected,
+        }
+    }
+}
+
+impl<S, Sel, D, const B: usize> AnalogSource for Mux<S, Sel, B, D>
+where
+    S: AnalogSource,
+    Sel: OutputPin,
+    D: DelayUs<u32>,
+{
+    type Error = MuxError<S::Error, Sel::Error>;
+
+    fn read(&mut self, key: usize) -> nb::Result<u16, Self::Error> {
+        let input = key % (1 << B);
+
+        // `read` is called again while the inner source is busy, don't switch
+        // and wait again
+        if self.selected != Some(input) {
+            self.selected = None;
+
+            for (bit, pin) in self.select.iter_mut().enumerate() {
+                let res = match input & (1 << bit) {
+                    0 => pin.set_low(),
+                    _ => pin.set_high(),
+                };
+
+                res.map_err(|err| nb::Error::Other(MuxError::Pin(err)))?;
+            }
+
+            if let Some((delay, us)) = &mut self.settle {
+                delay.delay_us(*us);
+            }
+
+            self.selected = Some(input);
+        }
+
+        self.inner
+            .read(key >> B)
+            .map_err(|err| err.map(MuxError::Source))
+    }
+}
+
+impl<F, E> AnalogSource for F
+where
+    F: FnMut(usize) -> nb::Result<u16, E>,
+{
+    type Error = E;
+
+    fn read(&mut self, key: usize) -> nb::Result<u16, Self::Error> {
+        self(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        cell::{Cell, RefCell},
+        convert::Infallible,
+    };
+    use std::{vec, vec::Vec};
+
+    use embedded_hal::{blocking::delay::DelayUs, digital::v2::OutputPin};
+
+    use super::{Actuation, AnalogSource, Calibration, Hall, KeyTravel, Mux};
+    use crate::phy::{layouts::scan, KeyId, KeyState};
+
+    fn key(actuation: Actuation) -> KeyTravel {
+        KeyTravel {
+            // Raw readings are the same as travel
+            calibration: Calibration {
+                rest: 0,
+                bottom: 255,
+            },
+            actuation,
+            raw: 0,
+            travel: 0,
+            pressed: false,
+            extreme: 0,
+            rapid: false,
+        }
+    }
+
+    /// Feeds travels from `trace` to `key`, checking whether it's pressed.
+    fn walk(key: &mut KeyTravel, trace: &[(u8, bool)]) {
+        for &(travel, pressed) in trace {
+            key.update(u16::from(travel));
+            assert_eq!(key.pressed, pressed, "travel {}", travel);
+        }
+    }
+
+    #[test]
+    fn actuation() {
+        let mut key = key(Actuation {
+            actuation: 128,
+            release: 50,
+            rapid_trigger: None,
+        });
+
+        #[rustfmt::skip]
+        walk(&mut key, &[
+            (0, false), (127, false), (128, true), (255, true), (100, true),
+            (51, true), (50, false), (127, false), (200, true),
+        ]);
+    }
+
+    #[test]
+    fn rapid_trigger() {
+        let mut key = key(Actuation {
+            actuation: 128,
+            release: 50,
+            rapid_trigger: Some(20),
+        });
+
+        #[rustfmt::skip]
+        walk(&mut key, &[
+            // Actuation, then rapid release while going up
+            (0, false), (128, true), (224, true), (205, true), (204, false),
+            // Still going up above the actuation point
+            (191, false), (171, false), (140, false),
+            // Rapid press while going down, release while going up
+            (159, false), (160, true), (211, true), (192, true), (191, false),
+            // Above the release point rapid trigger is off
+            (50, false), (69, false), (70, false), (127, false), (128, true),
+        ]);
+    }
+
+    #[test]
+    fn calibration() {
+        let normal = Calibration {
+            rest: 1000,
+            bottom: 3000,
+        };
+        let inverted = Calibration {
+            rest: 3000,
+            bottom: 1000,
+        };
+
+        #[rustfmt::skip]
+        let cases = [
+            (normal, 1000, 0), (normal, 2000, 127), (normal, 3000, 255),
+            (normal, 500, 0), (normal, 3500, 255),
+            (inverted, 3000, 0), (inverted, 2000, 127), (inverted, 1000, 255),
+            (inverted, 3500, 0), (inverted, 500, 255),
+        ];
+
+        for (calibration, raw, travel) in cases {
+            assert_eq!(calibration.travel(raw), travel, "{:?} {}", calibration, raw);
+        }
+
+        // There is no travel to speak of
+        let degenerate = Calibration {
+            rest: 2000,
+            bottom: 2000,
+        };
+        for raw in [0, 1999, 2000, 2001, u16::MAX] {
+            assert_eq!(degenerate.travel(raw), 0);
+        }
+    }
+
+    #[test]
+    fn hall() {
+        let readings = Cell::new([1000, 2500, 3000, 1000]);
+        let busy = Cell::new(true);
+
+        // Key 1 is busy once, key 3 fails
+        let source = |key: usize| match key {
+            1 if busy.replace(false) => Err(nb::Error::WouldBlock),
+            3 => Err(nb::Error::Other(())),
+            _ => Ok(readings.get()[key]),
+        };
+        let mut hall = Hall::<_, 4>::new(
+            source,
+            Calibration {
+                rest: 1000,
+                bottom: 3000,
+            },
+        );
+
+        let (p, u) = (KeyState::Pressed, KeyState::Unknown);
+        assert_eq!(scan(&mut hall), (vec![(1, p), (2, p), (3, u)], Err(())));
+        assert_eq!(
+            hall.travels()
+                .map(|(k, t)| (k.into_raw(), t))
+                .collect::<Vec<_>>(),
+            [(0, 0), (1, 191), (2, 255), (3, 0)]
+        );
+        assert_eq!(hall.raw(KeyId::from_raw(1)), Some(2500));
+        assert_eq!(hall.raw(KeyId::from_raw(4)), None);
+
+        // Key 1 goes below the release point, key 2 is still above it
+        readings.set([1000, 1700, 2000, 1000]);
+        assert_eq!(scan(&mut hall), (vec![(2, p), (3, u)], Err(())));
+        assert_eq!(hall.travel(KeyId::from_raw(1)), Some(89));
+        assert_eq!(hall.raw(KeyId::from_raw(2)), Some(2000));
+    }
+
+    #[test]
+    fn calibrate_rest() {
+        let mut hall = Hall::<_, 3>::new(
+            |key: usize| Ok::<_, nb::Error<Infallible>>([900, 1000, 1100][key]),
+            Calibration {
+                rest: 1000,
+                bottom: 3000,
+            },
+        );
+
+        let Ok(()) = hall.calibrate_rest();
+
+        for (k, &rest) in [900, 1000, 1100].iter().enumerate() {
+            let key = KeyId::from_raw(k as u16);
+            assert_eq!(
+                hall.calibration(key),
+                Some(Calibration { rest, bottom: 3000 })
+            );
+            assert_eq!(hall.raw(key), Some(rest));
+        }
+        assert_eq!(hall.calibration(KeyId::from_raw(3)), None);
+    }
+
+    /// Select pins of a multiplexer, and reads and delays in front of it.
+    #[derive(Default)]
+    struct Wires {
+        select: Cell<usize>,
+        log: RefCell<Vec<Event>>,
+    }
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum Event {
+        /// The inner source read the sensor with this index through this
+        /// multiplexer input.
+        Read {
+            sensor: usize,
+            input: usize,
+        },
+        Delay(u32),
+    }
+
+    struct Select<'a>(&'a Wires, usize);
+
+    struct Inner<'a>(&'a Wires);
+
+    struct Delay<'a>(&'a Wires);
+
+    impl OutputPin for Select<'_> {
+        type Error = Infallible;
+
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            self.0.select.set(self.0.select.get() & !(1 << self.1));
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            self.0.select.set(self.0.select.get() | (1 << self.1));
+            Ok(())
+        }
+    }
+
+    impl AnalogSource for Inner<'_> {
+        type Error = Infallible;
+
+        fn read(&mut self, sensor: usize) -> nb::Result<u16, Self::Error> {
+            let input = self.0.select.get();
+            self.0.log.borrow_mut().push(Event::Read { sensor, input });
+            Ok((sensor * 100 + input) as u16)
+        }
+    }
+
+    impl DelayUs<u32> for Delay<'_> {
+        fn delay_us(&mut self, us: u32) {
+            self.0.log.borrow_mut().push(Event::Delay(us));
+        }
+    }
+
+    #[test]
+    fn mux() {
+        let wires = Wires::default();
+        let mut mux = Mux::new(Inner(&wires), [0, 1, 2].map(|bit| Select(&wires, bit)));
+
+        // Key 13 is the input 5 of the second multiplexer
+        assert_eq!(mux.read(13), Ok(105));
+        assert_eq!(mux.read(2), Ok(2));
+        assert_eq!(
+            wires.log.take(),
+            [
+                Event::Read {
+                    sensor: 1,
+                    input: 5
+                },
+                Event::Read {
+                    sensor: 0,
+                    input: 2
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn mux_settle_delay() {
+        let wires = Wires::default();
+        let mut mux = Mux::new(Inner(&wires), [0, 1].map(|bit| Select(&wires, bit)))
+            .with_settle_delay(Delay(&wires), 10);
+
+        assert_eq!(mux.read(1), Ok(1));
+        // Same input of another multiplexer, no need to wait again
+        assert_eq!(mux.read(5), Ok(101));
+        assert_eq!(mux.read(6), Ok(102));
+        assert_eq!(
+            wires.log.take(),
+            [
+                Event::Delay(10),
+                Event::Read {
+                    sensor: 0,
+                    input: 1
+                },
+                Event::Read {
+                    sensor: 1,
+                    input: 1
+                },
+                Event::Delay(10),
+                Event::Read {
+                    sensor: 1,
+                    input: 2
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,412 @@
+use core::convert::Infallible;
+
+use embedded_hal::{
+    blocking::delay::DelayUs,
+    digital::v2::{InputPin, OutputPin},
+};
+
+use crate::phy::{
+    self,
+    layouts::{ActiveLevel, NoDelay},
+    KeyId, KeyState, KeyStates, Layout, Sleep, TryLayout,
+};
+
+/// Matrix physical layout - keys are placed at intersections of `R` rows and
+/// `C` columns.
+///
+/// Rows are output pins which are activated one by one, columns are input
+/// pins. When a row is active, columns of the pressed keys in this row are
+/// active too. [`KeyId`] of a key in the row `r` and column `c` is `r * C + c`.
+///
+/// If the matrix doesn't have diodes (see [`Matrix::without_diodes`]), keys
+/// which can be ghosts are reported as [`KeyState::Blocked`] and are not
+/// reported by [`Layout::poll`]. See [`Matrix::blocked`].
+///
+/// **Note**: by default this layout expects **pull up** columns, i.e. low =
+/// active, high = inactive. In other words diodes must be placed so that a
+/// pressed key pulls its column low when its row is low (cathodes towards the
+/// rows). Use [`Matrix::active_level`] to change that.
+///
+/// Columns are read right after a row is activated. Matrices with long traces
+/// or weak pull ups may need some time for columns to settle, otherwise keys
+/// of the previous row are misread. Use [`Matrix::with_settle_delay`] in that
+/// case.
+pub struct Matrix<R, C, const NR: usize, const NC: usize, D = NoDelay> {
+    rows: [R; NR],
+    cols: [C; NC],
+    active: ActiveLevel,
+    diodes: bool,
+    settle: Option<(D, u32)>,
+    blocked: bool,
+}
+
+impl<R, C, const NR: usize, const NC: usize> Matrix<R, C, NR, NC> {
+    /// Creates new matrix physical layout.
+    pub fn new(rows: [R; NR], cols: [C; NC]) -> Self {
+        Self {
+            rows,
+            cols,
+            active: ActiveLevel::Low,
+            diodes: true,
+            settle: None,
+            blocked: false,
+        }
+    }
+
+    /// Makes the matrix wait `us` microseconds with `delay` after activating a
+    /// row, before reading the columns.
+    pub fn with_settle_delay<D>(self, delay: D, us: u32) -> Matrix<R, C, NR, NC, D> {
+        Matrix {
+            rows: self.rows,
+            cols: self.cols,
+            active: self.active,
+            diodes: self.diodes,
+            settle: Some((delay, us)),
+            blocked: self.blocked,
+        }
+    }
+}
+
+impl<R, C, D, const NR: usize, const NC: usize> Matrix<R, C, NR, NC, D> {
+    /// Sets the level of pins that means that a row is selected and a key in
+    /// it is pressed.
+    pub fn active_level(self, active: ActiveLevel) -> Self {
+        Self { active, ..self }
+    }
+
+    /// Marks the matrix as one without diodes, enabling ghost detection.
+    ///
+    /// In a matrix without diodes, if keys in three corners of a rectangle are
+    /// pressed, the key in the fourth corner looks pressed too. Since it's
+    /// impossible to tell which of the four keys is the ghost, all of them are
+    /// reported as [`KeyState::Blocked`].
+    pub fn without_diodes(self) -> Self {
+        Self {
+            diodes: false,
+            ..self
+        }
+    }
+
+    /// Returns a mutable reference to the column pins, for example to
+    /// configure interrupts on them.
+    pub fn cols_mut(&mut self) -> &mut [C; NC] {
+        &mut self.cols
+    }
+
+    /// Returns `true` if some keys were blocked (because of possible ghosting)
+    /// during the last scan.
+    pub fn blocked(&self) -> bool {
+        self.blocked
+    }
+}
+
+impl<R, C, D, E, const NR: usize, const NC: usize> TryLayout for Matrix<R, C, NR, NC, D>
+where
+    R: OutputPin<Error = E>,
+    C: InputPin<Error = E>,
+    D: DelayUs<u32>,
+{
+    type Error = E;
+
+    fn try_poll(&mut self, f: &mut dyn FnMut(&mut KeyStates<'_>)) -> Result<(), Self::Error> {
+        let active = self.active;
+        let mut states = [[None; NC]; NR];
+        let mut error = None;
+
+        // Rows may have been left active (or were never set)
+        for row in &mut self.rows {
+            if let Err(err) = active.set_inactive(row) {
+                error.get_or_insert(err);
+            }
+        }
+
+        for (row, states) in self.rows.iter_mut().zip(&mut states) {
+            if let Err(err) = active.set_active(row) {
+                *states = [Some(KeyState::Unknown); NC];
+                error.get_or_insert(err);
+                continue;
+            }
+
+            if let Some((delay, us)) = &mut self.settle {
+                delay.delay_us(*us);
+            }
+
+            for (col, state) in self.cols.iter().zip(&mut *states) {
+                match active.is_active(col) {
+                    Ok(true) => *state = Some(KeyState::Pressed),
+                    Ok(false) => {}
+                    Err(err) => {
+                        *state = Some(KeyState::Unknown);
+                        error.get_or_insert(err);
+                    }
+                }
+            }
+
+            if let Err(err) = active.set_inactive(row) {
+                error.get_or_insert(err);
+            }
+        }
+
+        self.blocked = !self.diodes && block_ghosts(&mut states);
+
+        let mut iter = states.iter().enumerate().flat_map(|(r, states)| {
+            states
+                .iter()
+                .enumerate()
+                .filter_map(move |(c, &state)| Some((KeyId::from_raw((r * NC + c) as u16), state?)))
+        });
+
+        f(&mut iter);
+
+        error.map_or(Ok(()), Err)
+    }
+
+    fn try_max_key_id(&self) -> KeyId {
+        KeyId::from_raw((NR * NC) as _)
+    }
+}
+
+impl<R, C, D, const NR: usize, const NC: usize> Layout for Matrix<R, C, NR, NC, D>
+where
+    R: OutputPin<Error = Infallible>,
+    C: InputPin<Error = Infallible>,
+    D: DelayUs<u32>,
+{
+    fn poll(&mut self, f: &mut dyn FnMut(&mut dyn Iterator<Item = KeyId>)) {
+        phy::poll_infallible(self, f)
+    }
+
+    fn max_key_id(&self) -> KeyId {
+        KeyId::from_raw((NR * NC) as _)
+    }
+}
+
+/// While waiting, all rows are active, so pressing any key activates its column.
+impl<R, C, D, const NR: usize, const NC: usize> Sleep for Matrix<R, C, NR, NC, D>
+where
+    R: OutputPin,
+{
+    type Error = R::Error;
+
+    fn sleep(&mut self) -> Result<(), Self::Error> {
+        let active = self.active;
+        self.rows
+            .iter_mut()
+            .try_for_each(|row| active.set_active(row))
+    }
+
+    fn wake(&mut self) -> Result<(), Self::Error> {
+        let active = self.active;
+        self.rows
+            .iter_mut()
+            .try_for_each(|row| active.set_inactive(row))
+    }
+}
+
+/// Marks keys in corners of rectangles of pressed keys as blocked, returns
+/// `true` if any keys were blocked.
+fn block_ghosts<const NR: usize, const NC: usize>(
+    states: &mut [[Option<KeyState>; NC]; NR],
+) -> bool {
+    let pressed =
+        |state: &Option<KeyState>| matches!(state, Some(KeyState::Pressed | KeyState::Blocked));
+    let mut blocked = false;
+    let mut rest = &mut states[..];
+
+    while let Some((a, tail)) = rest.split_first_mut() {
+        for b in tail.iter_mut() {
+            let common = a
+                .iter()
+                .zip(&*b)
+                .filter(|(x, y)| pressed(x) && pressed(y))
+                .count();
+
+            // Two rows with at least two common pressed columns form a
+            // rectangle
+            if common < 2 {
+                continue;
+            }
+
+            blocked = true;
+
+            for (x, y) in a.iter_mut().zip(b) {
+                if pressed(x) && pressed(y) {
+                    *x = Some(KeyState::Blocked);
+                    *y = Some(KeyState::Blocked);
+                }
+            }
+        }
+
+        rest = tail;
+    }
+
+    blocked
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{cell::Cell, convert::Infallible};
+    use std::vec::Vec;
+
+    use embedded_hal::{
+        blocking::delay::DelayUs,
+        digital::v2::{InputPin, OutputPin},
+    };
+
+    use super::{block_ghosts, Matrix};
+    use crate::phy::{KeyId, KeyState, Layout, TryLayout};
+
+    /// Wiring of a 3x3 matrix without diodes, rows and columns are active low.
+    struct Wires {
+        pressed: Cell<[[bool; 3]; 3]>,
+        row: Cell<Option<usize>>,
+    }
+
+    struct Row<'a>(&'a Wires, usize);
+
+    struct Col<'a>(&'a Wires, usize);
+
+    impl OutputPin for Row<'_> {
+        type Error = Infallible;
+
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            self.0.row.set(Some(self.1));
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            if self.0.row.get() == Some(self.1) {
+                self.0.row.set(None);
+            }
+            Ok(())
+        }
+    }
+
+    impl InputPin for Col<'_> {
+        type Error = Infallible;
+
+        fn is_high(&self) -> Result<bool, Self::Error> {
+            self.is_low().map(|low| !low)
+        }
+
+        fn is_low(&self) -> Result<bool, Self::Error> {
+            let (pressed, c) = (self.0.pressed.get(), self.1);
+
+            // Without diodes current also flows backwards through other keys,
+            // so three corners of a rectangle connect the fourth one
+            Ok(self.0.row.get().is_some_and(|r| {
+                pressed[r][c]
+                    || (0..3).any(|c2| {
+                        (0..3).any(|r2| pressed[r][c2] && pressed[r2][c2] && pressed[r2][c])
+                    })
+            }))
+        }
+    }
+
+    /// Delay which records the active row and the duration of every wait.
+    struct Delay<'a>(&'a Wires, Vec<(Option<usize>, u32)>);
+
+    impl DelayUs<u32> for Delay<'_> {
+        fn delay_us(&mut self, us: u32) {
+            self.1.push((self.0.row.get(), us));
+        }
+    }
+
+    fn scan<D>(matrix: &mut Matrix<Row<'_>, Col<'_>, 3, 3, D>) -> Vec<(u16, KeyState)>
+    where
+        D: DelayUs<u32>,
+    {
+        let mut keys = Vec::new();
+        let Ok(()) = matrix.try_poll(&mut |iter| keys.extend(iter.map(|(k, s)| (k.into_raw(), s))));
+        keys
+    }
+
+    #[test]
+    fn ghosts() {
+        let (p, b) = (Some(KeyState::Pressed), Some(KeyState::Blocked));
+
+        // Three corners and the ghost
+        let mut states = [[p, None, p], [None, None, None], [p, None, p]];
+        assert!(block_ghosts(&mut states));
+        assert_eq!(states, [[b, None, b], [None, None, None], [b, None, b]]);
+
+        // Keys of other rows and columns are not blocked
+        let mut states = [[p, p, None], [p, p, p], [None, None, p]];
+        assert!(block_ghosts(&mut states));
+        assert_eq!(states, [[b, b, None], [b, b, p], [None, None, p]]);
+    }
+
+    #[test]
+    fn no_ghosts() {
+        let (p, u) = (Some(KeyState::Pressed), Some(KeyState::Unknown));
+
+        #[rustfmt::skip]
+        let patterns = [
+            [[p, p, p], [None, None, None], [None, None, None]],
+            [[p, None, None], [p, None, None], [p, None, None]],
+            [[p, p, None], [None, p, p], [None, None, p]],
+            [[p, None, None], [None, p, None], [None, None, p]],
+            // Unknown keys are not pressed
+            [[p, u, None], [p, p, None], [None, None, None]],
+        ];
+
+        for pattern in patterns {
+            let mut states = pattern;
+            assert!(!block_ghosts(&mut states));
+            assert_eq!(states, pattern);
+        }
+    }
+
+    #[test]
+    fn matrix() {
+        let wires = Wires {
+            pressed: Cell::new([[true, false, true], [false; 3], [true, false, false]]),
+            row: Cell::new(None),
+        };
+        let mut matrix = Matrix::new(
+            [0, 1, 2].map(|r| Row(&wires, r)),
+            [0, 1, 2].map(|c| Col(&wires, c)),
+        )
+        .without_diodes();
+
+        let b = KeyState::Blocked;
+        assert_eq!(scan(&mut matrix), [(0, b), (2, b), (6, b), (8, b)]);
+        assert!(matrix.blocked());
+
+        // Blocked keys are not pressed
+        let mut pressed = Vec::new();
+        matrix.poll(&mut |iter| pressed.extend(iter.map(KeyId::into_raw)));
+        assert!(pressed.is_empty());
+
+        wires
+            .pressed
+            .set([[true, false, true], [false; 3], [false; 3]]);
+        assert_eq!(
+            scan(&mut matrix),
+            [(0, KeyState::Pressed), (2, KeyState::Pressed)]
+        );
+        assert!(!matrix.blocked());
+        assert_eq!(wires.row.get(), None);
+    }
+
+    #[test]
+    fn settle_delay() {
+        let wires = Wires {
+            pressed: Cell::new([[false; 3], [false, true, false], [false; 3]]),
+            row: Cell::new(None),
+        };
+        let mut matrix = Matrix::new(
+            [0, 1, 2].map(|r| Row(&wires, r)),
+            [0, 1, 2].map(|c| Col(&wires, c)),
+        )
+        .with_settle_delay(Delay(&wires, Vec::new()), 5);
+
+        assert_eq!(scan(&mut matrix), [(4, KeyState::Pressed)]);
+
+        // The delay is used once per row, after the row is activated
+        let Some((Delay(_, waits), _)) = &matrix.settle else {
+            unreachable!()
+        };
+        assert_eq!(waits, &[(Some(0), 5), (Some(1), 5), (Some(2), 5)]);
+    }
+}
@@ -0,0 +1,462 @@
+use core::cell::OnceCell;
+
+use crate::phy::{
+    top::{self, fixed, KeyPos, ReprBuf},
+    KeyId, KeyStates, Layout, Sleep, TryLayout,
+};
+
+/// Layout adapter which renumbers, reorders or removes [`KeyId`]s of another
+/// layout.
+///
+/// `table` is indexed by raw [`KeyId`]s of the inner layout: a key `k` of the
+/// inner layout is reported as `table[k]` or, if it's `None` (or `k` is out of
+/// the table), not reported at all. This allows exposing keys in a logical
+/// order regardless of the wiring, skipping unpopulated positions of a matrix
+/// and keeping [`KeyId`]s stable when the wiring changes between PCB
+/// revisions. Different keys should be mapped to different [`KeyId`]s.
+///
+/// [`max_key_id`] of the adapter is one more than the biggest [`KeyId`] in
+/// the table.
+///
+/// This implements [`Layout`] if the inner layout implements [`Layout`] and
+/// [`TryLayout`] if the inner layout implements [`TryLayout`].
+///
+/// `K` is the maximum number of keys in the remapped topological (or
+/// fixed-point) representation, see [`Chain`].
+///
+/// [`max_key_id`]: Layout::max_key_id
+/// [`Chain`]: super::Chain
+pub struct Remap<'a, L, const K: usize = 0> {
+    inner: L,
+    table: &'a [Option<KeyId>],
+    max_key_id: KeyId,
+    repr: OnceCell<Option<ReprBuf<K>>>,
+    fixed_repr: OnceCell<Option<fixed::ReprBuf<K>>>,
+}
+
+impl<'a, L> Remap<'a, L> {
+    /// Remaps keys of `inner` according to `table`, without a topological
+    /// representation.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `table` contains `KeyId::from_raw(u16::MAX)`, see
+    /// [`Remap::with_topology`].
+    pub const fn new(inner: L, table: &'a [Option<KeyId>]) -> Self {
+        Self::with_topology(inner, table)
+    }
+}
+
+impl<'a, L, const K: usize> Remap<'a, L, K> {
+    /// Remaps keys of `inner` according to `table`, with a topological
+    /// representation of up to `K` keys.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `table` contains `KeyId::from_raw(u16::MAX)`, since
+    /// [`max_key_id`] of the adapter, which is one more than that, can't be
+    /// represented. If the adapter is constructed in a `const`, this is a
+    /// compile time error.
+    ///
+    /// [`max_key_id`]: Layout::max_key_id
+    pub const fn with_topology(inner: L, table: &'a [Option<KeyId>]) -> Self {
+        let mut max_key_id = 0;
+        let mut i = 0;
+
+        while i < table.len() {
+            if let Some(id) = table[i] {
+                if id.into_raw() >= max_key_id {
+                    max_key_id = match id.into_raw().checked_add(1) {
+                        Some(max) => max,
+                        None => panic!("`KeyId::from_raw(u16::MAX)` can't be remapped to"),
+                    };
+                }
+            }
+
+            i += 1;
+        }
+
+        Self {
+            inner,
+            table,
+            max_key_id: KeyId::from_raw(max_key_id),
+            repr: OnceCell::new(),
+            fixed_repr: OnceCell::new(),
+        }
+    }
+
+    /// Returns a reference to the inner layout.
+    pub fn inner(&self) -> &L {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner layout.
+    ///
+    /// The remapped representations are computed again after that, since the
+    /// inner layout may change.
+    pub fn inner_mut(&mut self) -> &mut L {
+        self.repr = OnceCell::new();
+        self.fixed_repr = OnceCell::new();
+        &mut self.inner
+    }
+
+    /// Returns the inner layout.
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+
+    /// Returns [`KeyId`] that the key `key` of the inner layout is reported
+    /// as, if any.
+    pub fn map(&self, key: KeyId) -> Option<KeyId> {
+        map(self.table, key)
+    }
+}
+
+fn map(table: &[Option<KeyId>], key: KeyId) -> Option<KeyId> {
+    table.get(usize::from(key.into_raw())).copied().flatten()
+}
+
+/// Remaps [`KeyId`]s of a representation, dropping removed keys.
+fn remap<const K: usize>(table: &[Option<KeyId>], repr: top::Repr<'_>) -> Option<ReprBuf<K>> {
+    let mut buf = ReprBuf::new(repr.centre);
+
+    for key in repr.keys {
+        if let Some(id) = map(table, key.id) {
+            buf.push(KeyPos { id, ..*key }).ok()?;
+        }
+    }
+
+    Some(buf)
+}
+
+/// Same as [`remap`], but for fixed-point representations.
+fn remap_fixed<const K: usize>(
+    table: &[Option<KeyId>],
+    repr: fixed::Repr<'_>,
+) -> Option<fixed::ReprBuf<K>> {
+    let mut buf = fixed::ReprBuf::new(repr.centre);
+
+    for key in repr.keys {
+        if let Some(id) = map(table, key.id) {
+            buf.push(fixed::KeyPos { id, ..*key }).ok()?;
+        }
+    }
+
+    Some(buf)
+}
+
+impl<L, const K: usize> TryLayout for Remap<'_, L, K>
+where
+    L: TryLayout,
+{
+    type Error = L::Error;
+
+    fn try_poll(&mut self, f: &mut dyn FnMut(&mut KeyStates<'_>)) -> Result<(), Self::Error> {
+        let table = self.table;
+
+        self.inner.try_poll(&mut |iter| {
+            f(&mut iter.filter_map(|(k, state)| Some((map(table, k)?, state))))
+        })
+    }
+
+    fn try_max_key_id(&self) -> KeyId {
+        self.max_key_id
+    }
+
+    fn try_topological_repr(&self) -> Option<top::Repr<'_>> {
+        self.repr
+            .get_or_init(|| remap(self.table, self.inner.try_topological_repr()?))
+            .as_ref()
+            .map(ReprBuf::as_repr)
+    }
+
+    fn try_fixed_repr(&self) -> Option<fixed::Repr<'_>> {
+        self.fixed_repr
+            .get_or_init(|| remap_fixed(self.table, self.inner.try_fixed_repr()?))
+            .as_ref()
+            .map(fixed::ReprBuf::as_repr)
+    }
+}
+
+impl<L, const K: usize> Layout for Remap<'_, L, K>
+where
+    L: Layout,
+{
+    fn poll(&mut self, f: &mut dyn FnMut(&mut dyn Iterator<Item = KeyId>)) {
+        let table = self.table;
+
+        self.inner
+            .poll(&mut |iter| f(&mut iter.filter_map(|k| map(table, k))))
+    }
+
+    fn max_key_id(&self) -> KeyId {
+        self.max_key_id
+    }
+
+    fn topological_repr(&self) -> Option<top::Repr<'_>> {
+        self.repr
+            .get_or_init(|| remap(self.table, self.inner.topological_repr()?))
+            .as_ref()
+            .map(ReprBuf::as_repr)
+    }
+
+    fn fixed_repr(&self) -> Option<fixed::Repr<'_>> {
+        self.fixed_repr
+            .get_or_init(|| remap_fixed(self.table, self.inner.fixed_repr()?))
+            .as_ref()
+            .map(fixed::ReprBuf::as_repr)
+    }
+}
+
+impl<L, const K: usize> Sleep for Remap<'_, L, K>
+where
+    L: Sleep,
+{
+    type Error = L::Error;
+
+    fn sleep(&mut self) -> Result<(), Self::Error> {
+        self.inner.sleep()
+    }
+
+    fn wake(&mut self) -> Result<(), Self::Error> {
+        self.inner.wake()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::convert::Infallible;
+    use std::{vec, vec::Vec};
+
+    use super::Remap;
+    use crate::phy::{
+        layouts::scan,
+        top::{
+            self,
+            fixed::{self, Units},
+            KeyPos,
+        },
+        KeyId, KeyState, KeyStates, Layout, TryLayout,
+    };
+
+    const fn id(raw: u16) -> Option<KeyId> {
+        Some(KeyId::from_raw(raw))
+    }
+
+    /// Inner layout with 5 keys, all of them pressed but the unknown third
+    /// one.
+    struct Inner;
+
+    const KEYS: [KeyPos; 5] = [
+        KeyPos::new(KeyId::from_raw(0), 0.5, 0.5),
+        KeyPos::new(KeyId::from_raw(1), 1.5, 0.5),
+        KeyPos::new(KeyId::from_raw(2), 2.5, 0.5),
+        KeyPos::new(KeyId::from_raw(3), 3.5, 0.5),
+        KeyPos::new(KeyId::from_raw(4), 4.5, 0.5),
+    ];
+
+    impl TryLayout for Inner {
+        type Error = Infallible;
+
+        fn try_poll(&mut self, f: &mut dyn FnMut(&mut KeyStates<'_>)) -> Result<(), Self::Error> {
+            f(&mut (0..5).map(|k| {
+                let state = if k == 2 {
+                    KeyState::Unknown
+                } else {
+                    KeyState::Pressed
+                };
+                (KeyId::from_raw(k), state)
+            }));
+            Ok(())
+        }
+
+        fn try_max_key_id(&self) -> KeyId {
+            KeyId::from_raw(5)
+        }
+
+        fn try_topological_repr(&self) -> Option<top::Repr<'_>> {
+            self.topological_repr()
+        }
+    }
+
+    impl Layout for Inner {
+        fn poll(&mut self, f: &mut dyn FnMut(&mut dyn Iterator<Item = KeyId>)) {
+            f(&mut (0..5).map(KeyId::from_raw))
+        }
+
+        fn max_key_id(&self) -> KeyId {
+            KeyId::from_raw(5)
+        }
+
+        fn topological_repr(&self) -> Option<top::Repr<'_>> {
+            Some(top::Repr {
+                keys: &KEYS,
+                centre: (2.5, 0.5),
+            })
+        }
+    }
+
+    /// Reverses the first three keys, removes the fourth one and doesn't
+    /// mention the fifth one.
+    static TABLE: [Option<KeyId>; 4] = [id(4), id(1), id(0), None];
+
+    #[test]
+    fn map() {
+        let remap = Remap::new(Inner, &TABLE);
+
+        // One more than the biggest id, even though `KeyId(2)` and `KeyId(3)`
+        // are not used
+        assert_eq!(remap.max_key_id(), KeyId::from_raw(5));
+        assert_eq!(remap.try_max_key_id(), KeyId::from_raw(5));
+        assert_eq!(
+            Remap::new(Inner, &[None, None]).max_key_id(),
+            KeyId::from_raw(0)
+        );
+        assert_eq!(Remap::new(Inner, &[id(0)]).max_key_id(), KeyId::from_raw(1));
+
+        let expected = [id(4), id(1), id(0), None, None, None];
+        for (k, &expected) in expected.iter().enumerate() {
+            assert_eq!(remap.map(KeyId::from_raw(k as u16)), expected, "{}", k);
+        }
+    }
+
+    #[test]
+    fn max_key_id() {
+        assert_eq!(
+            Remap::new(Inner, &[id(u16::MAX - 1), None]).max_key_id(),
+            KeyId::from_raw(u16::MAX)
+        );
+    }
+
+    #[test]
+    #[should_panic = "can't be remapped to"]
+    fn max_key_id_overflow() {
+        Remap::new(Inner, &[id(0), id(u16::MAX)]);
+    }
+
+    #[test]
+    fn poll() {
+        let mut remap = Remap::new(Inner, &TABLE);
+
+        let mut keys = Vec::new();
+        remap.poll(&mut |iter| keys.extend(iter.map(KeyId::into_raw)));
+        assert_eq!(keys, [4, 1, 0]);
+
+        // States are kept
+        let (p, u) = (KeyState::Pressed, KeyState::Unknown);
+        assert_eq!(scan(&mut remap), (vec![(4, p), (1, p), (0, u)], Ok(())));
+    }
+
+    #[test]
+    fn topology() {
+        assert_eq!(Remap::new(Inner, &TABLE).topological_repr(), None);
+
+        let remap = Remap::<_, 3>::with_topology(Inner, &TABLE);
+        let repr = remap.topological_repr().unwrap();
+        let keys = repr
+            .keys
+            .iter()
+            .map(|k| (k.id.into_raw(), k.x))
+            .collect::<Vec<_>>();
+        assert_eq!(keys, [(4, 0.5), (1, 1.5), (0, 2.5)]);
+        assert_eq!(repr.centre, (2.5, 0.5));
+        assert_eq!(remap.try_topological_repr(), Some(repr));
+
+        // Too many keys
+        let remap = Remap::<_, 2>::with_topology(Inner, &TABLE);
+        assert_eq!(remap.topological_repr(), None);
+    }
+
+    /// [`Inner`] which has a topological representation only if `.0` is
+    /// `true`.
+    struct Switch(bool);
+
+    impl Layout for Switch {
+        fn poll(&mut self, _: &mut dyn FnMut(&mut dyn Iterator<Item = KeyId>)) {}
+
+        fn max_key_id(&self) -> KeyId {
+            Inner.max_key_id()
+        }
+
+        fn topological_repr(&self) -> Option<top::Repr<'_>> {
+            Inner.topological_repr().filter(|_| self.0)
+        }
+    }
+
+    #[test]
+    fn inner_mut() {
+        let mut remap = Remap::<_, 3>::with_topology(Switch(false), &TABLE);
+        assert_eq!(remap.topological_repr(), None);
+
+        // The representation is remapped again after the inner layout changes
+        remap.inner_mut().0 = true;
+        assert_eq!(remap.topological_repr().unwrap().keys.len(), 3);
+
+        remap.inner_mut().0 = false;
+        assert_eq!(remap.topological_repr(), None);
+    }
+
+    /// Inner layout with the same keys as [`Inner`], but only a fixed-point
+    /// representation.
+    struct Fixed;
+
+    impl TryLayout for Fixed {
+        type Error = Infallible;
+
+        fn try_poll(&mut self, _: &mut dyn FnMut(&mut KeyStates<'_>)) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn try_max_key_id(&self) -> KeyId {
+            KeyId::from_raw(5)
+        }
+
+        fn try_fixed_repr(&self) -> Option<fixed::Repr<'_>> {
+            self.fixed_repr()
+        }
+    }
+
+    impl Layout for Fixed {
+        fn poll(&mut self, _: &mut dyn FnMut(&mut dyn Iterator<Item = KeyId>)) {}
+
+        fn max_key_id(&self) -> KeyId {
+            KeyId::from_raw(5)
+        }
+
+        fn fixed_repr(&self) -> Option<fixed::Repr<'_>> {
+            const FIXED: [fixed::KeyPos; 5] = [
+                fixed::KeyPos::new(KeyId::from_raw(0), Units(128), Units(128)),
+                fixed::KeyPos::new(KeyId::from_raw(1), Units(384), Units(128)),
+                fixed::KeyPos::new(KeyId::from_raw(2), Units(640), Units(128)),
+                fixed::KeyPos::new(KeyId::from_raw(3), Units(896), Units(128)),
+                fixed::KeyPos::new(KeyId::from_raw(4), Units(1152), Units(128)),
+            ];
+
+            Some(fixed::Repr {
+                keys: &FIXED,
+                centre: (Units(640), Units(128)),
+            })
+        }
+    }
+
+    #[test]
+    fn fixed_topology() {
+        assert_eq!(Remap::new(Fixed, &TABLE).fixed_repr(), None);
+
+        let remap = Remap::<_, 3>::with_topology(Fixed, &TABLE);
+        let repr = remap.fixed_repr().unwrap();
+        let keys = repr
+            .keys
+            .iter()
+            .map(|k| (k.id.into_raw(), k.x))
+            .collect::<Vec<_>>();
+        assert_eq!(keys, [(4, Units(128)), (1, Units(384)), (0, Units(640))]);
+        assert_eq!(repr.centre, (Units(640), Units(128)));
+        assert_eq!(remap.try_fixed_repr(), Some(repr));
+        assert_eq!(remap.topological_repr(), None);
+
+        // Too many keys
+        let remap = Remap::<_, 2>::with_topology(Fixed, &TABLE);
+        assert_eq!(remap.fixed_repr(), None);
+    }
+}
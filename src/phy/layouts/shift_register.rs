@@ -0,0 +1,402 @@
+use core::convert::Infallible;
+
+use embedded_hal::{
+    blocking::spi::Transfer,
+    digital::v2::{InputPin, OutputPin},
+};
+
+use crate::phy::{self, layouts::ActiveLevel, KeyId, KeyState, KeyStates, Layout, TryLayout};
+
+/// Shift register physical layout - every key is connected to an input of one
+/// of `N` daisy-chained parallel-in/serial-out shift registers (like 74HC165).
+///
+/// Each register has 8 inputs, so this layout has `8 * N` keys. Key connected
+/// to the input `Dp` of the register `r` (counting from the one closest to the
+/// MCU) has [`KeyId`] `8 * r + p`.
+///
+/// Registers can be read either by bit-banging pins (see
+/// [`ShiftRegister::new`]) or by an SPI peripheral (see
+/// [`ShiftRegister::new_spi`]).
+///
+/// **Note**: by default this layout expects inputs to be **pulled up**, i.e.
+/// low = key is pressed, high = key is depressed, and registers to shift out
+/// the most significant input (`D7`) first, like 74HC165 does. Use
+/// [`ShiftRegister::active_level`] and [`ShiftRegister::bit_order`] to change
+/// that.
+pub struct ShiftRegister<I, const N: usize> {
+    input: I,
+    order: BitOrder,
+    active: ActiveLevel,
+}
+
+/// Order in which a shift register shifts out its inputs.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum BitOrder {
+    /// `D7` is shifted out first, `D0` last.
+    MsbFirst,
+    /// `D0` is shifted out first, `D7` last.
+    LsbFirst,
+}
+
+/// A way to read bits out of a shift register chain.
+pub trait ShiftIn {
+    /// Error which can happen while reading the registers.
+    type Error;
+
+    /// Latches the inputs of the registers and shifts them into `buf`.
+    ///
+    /// Bits are stored most significant bit first, i.e. the first bit shifted
+    /// out goes to the bit 7 of `buf[0]`, the ninth goes to the bit 7 of
+    /// `buf[1]`, etc.
+    fn shift_in(&mut self, buf: &mut [u8]) -> Result<(), Self::Error>;
+}
+
+/// Bit-banged shift register interface.
+///
+/// - `load` is connected to the parallel load input (`SH/LD` on 74HC165),
+///   registers latch their inputs when it's low
+/// - `clock` is connected to the clock input (`CLK` on 74HC165)
+/// - `data` is connected to the serial output of the register closest to the
+///   MCU (`QH` on 74HC165)
+pub struct ShiftPins<Ld, Clk, Data> {
+    load: Ld,
+    clock: Clk,
+    data: Data,
+}
+
+/// SPI shift register interface.
+///
+/// `load` is connected to the parallel load input (`SH/LD` on 74HC165) and
+/// registers latch their inputs when it's low. The SPI peripheral must be
+/// configured to read the most significant bit first, with the clock idling
+/// low and data sampled on the rising edge (SPI mode 0).
+pub struct ShiftSpi<S, Ld> {
+    spi: S,
+    load: Ld,
+}
+
+/// Error of the [`ShiftSpi`] shift register interface.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ShiftSpiError<S, P> {
+    /// The SPI transfer failed.
+    Spi(S),
+    /// Setting the `load` pin failed.
+    Pin(P),
+}
+
+impl<Ld, Clk, Data, const N: usize> ShiftRegister<ShiftPins<Ld, Clk, Data>, N> {
+    /// Creates new shift register physical layout, read by bit-banging pins.
+    ///
+    /// See [`ShiftPins`] for how pins should be connected.
+    pub fn new(load: Ld, clock: Clk, data: Data) -> Self {
+        Self::with_input(ShiftPins { load, clock, data })
+    }
+}
+
+impl<S, Ld, const N: usize> ShiftRegister<ShiftSpi<S, Ld>, N> {
+    /// Creates new shift register physical layout, read by an SPI peripheral.
+    ///
+    /// See [`ShiftSpi`] for how the peripheral should be configured.
+    pub fn new_spi(spi: S, load: Ld) -> Self {
+        Self::with_input(ShiftSpi { spi, load })
+    }
+}
+
+impl<I, const N: usize> ShiftRegister<I, N> {
+    /// Creates new shift register physical layout, read by a custom
+    /// [`ShiftIn`] implementation.
+    pub fn with_input(input: I) -> Self {
+        Self {
+            input,
+            order: BitOrder::MsbFirst,
+            active: ActiveLevel::Low,
+        }
+    }
+
+    /// Sets the order in which registers shift out their inputs.
+    pub fn bit_order(self, order: BitOrder) -> Self {
+        Self { order, ..self }
+    }
+
+    /// Sets the level of inputs that means that a key is pressed.
+    pub fn active_level(self, active: ActiveLevel) -> Self {
+        Self { active, ..self }
+    }
+}
+
+impl<I, const N: usize> TryLayout for ShiftRegister<I, N>
+where
+    I: ShiftIn,
+{
+    type Error = I::Error;
+
+    fn try_poll(&mut self, f: &mut dyn FnMut(&mut KeyStates<'_>)) -> Result<(), Self::Error> {
+        let mut bytes = [0; N];
+
+        if let Err(err) = self.input.shift_in(&mut bytes) {
+            f(&mut (0..N as u16 * 8).map(|k| (KeyId::from_raw(k), KeyState::Unknown)));
+            return Err(err);
+        }
+
+        let (order, active) = (self.order, self.active);
+        let mut iter = (0..N * 8)
+            .filter(|&k| {
+                let bit = match order {
+                    BitOrder::MsbFirst => k % 8,
+                    BitOrder::LsbFirst => 7 - k % 8,
+                };
+                let high = bytes[k / 8] & (1 << bit) != 0;
+
+                high == (active == ActiveLevel::High)
+            })
+            .map(|k| (KeyId::from_raw(k as u16), KeyState::Pressed));
+
+        f(iter.by_ref());
+
+        Ok(())
+    }
+
+    fn try_max_key_id(&self) -> KeyId {
+        KeyId::from_raw((N * 8) as _)
+    }
+}
+
+impl<I, const N: usize> Layout for ShiftRegister<I, N>
+where
+    I: ShiftIn<Error = Infallible>,
+{
+    fn poll(&mut self, f: &mut dyn FnMut(&mut dyn Iterator<Item = KeyId>)) {
+        phy::poll_infallible(self, f)
+    }
+
+    fn max_key_id(&self) -> KeyId {
+        KeyId::from_raw((N * 8) as _)
+    }
+}
+
+impl<Ld, Clk, Data, E> ShiftIn for ShiftPins<Ld, Clk, Data>
+where
+    Ld: OutputPin<Error = E>,
+    Clk: OutputPin<Error = E>,
+    Data: InputPin<Error = E>,
+{
+    type Error = E;
+
+    fn shift_in(&mut self, buf: &mut [u8]) -> Result<(), Self::Error> {
+        self.clock.set_low()?;
+
+        // Latch inputs
+        self.load.set_low()?;
+        self.load.set_high()?;
+
+        for byte in buf {
+            *byte = 0;
+
+            for bit in (0..8).rev() {
+                if self.data.is_high()? {
+                    *byte |= 1 << bit;
+                }
+
+                self.clock.set_high()?;
+                self.clock.set_low()?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<S, Ld> ShiftIn for ShiftSpi<S, Ld>
+where
+    S: Transfer<u8>,
+    Ld: OutputPin,
+{
+    type Error = ShiftSpiError<S::Error, Ld::Error>;
+
+    fn shift_in(&mut self, buf: &mut [u8]) -> Result<(), Self::Error> {
+        // Latch inputs
+        self.load.set_low().map_err(ShiftSpiError::Pin)?;
+        self.load.set_high().map_err(ShiftSpiError::Pin)?;
+
+        buf.iter_mut().for_each(|byte| *byte = 0);
+        self.spi.transfer(buf).map_err(ShiftSpiError::Spi)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{cell::Cell, convert::Infallible};
+    use std::vec::Vec;
+
+    use embedded_hal::{
+        blocking::spi::Transfer,
+        digital::v2::{InputPin, OutputPin},
+    };
+
+    use super::{BitOrder, ShiftIn, ShiftRegister};
+    use crate::phy::{
+        layouts::{scan, ActiveLevel},
+        KeyState, TryLayout,
+    };
+
+    /// Two daisy-chained registers.
+    struct Chip {
+        /// Levels of the inputs, bit `p` is `Dp`.
+        inputs: [u8; 2],
+        /// Whether `D0` is shifted out first.
+        lsb_first: bool,
+        /// Latched bits, the top one is on the serial output.
+        shifted: Cell<u16>,
+        clock: Cell<bool>,
+    }
+
+    struct Load<'a>(&'a Chip);
+
+    struct Clock<'a>(&'a Chip);
+
+    struct Data<'a>(&'a Chip);
+
+    impl Chip {
+        fn new(inputs: [u8; 2], lsb_first: bool) -> Self {
+            Self {
+                inputs,
+                lsb_first,
+                shifted: Cell::new(0),
+                clock: Cell::new(false),
+            }
+        }
+
+        fn layout(&self) -> ShiftRegister<impl ShiftIn<Error = Infallible> + '_, 2> {
+            ShiftRegister::new(Load(self), Clock(self), Data(self))
+        }
+    }
+
+    impl OutputPin for Load<'_> {
+        type Error = Infallible;
+
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            let [a, b] = match self.0.lsb_first {
+                false => self.0.inputs,
+                true => self.0.inputs.map(u8::reverse_bits),
+            };
+
+            // The register closest to the MCU is shifted out first
+            self.0.shifted.set(u16::from_be_bytes([a, b]));
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    impl OutputPin for Clock<'_> {
+        type Error = Infallible;
+
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            self.0.clock.set(false);
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            // Shift on the rising edge
+            if !self.0.clock.replace(true) {
+                self.0.shifted.set(self.0.shifted.get() << 1);
+            }
+            Ok(())
+        }
+    }
+
+    impl InputPin for Data<'_> {
+        type Error = Infallible;
+
+        fn is_high(&self) -> Result<bool, Self::Error> {
+            Ok(self.0.shifted.get() & 0x8000 != 0)
+        }
+
+        fn is_low(&self) -> Result<bool, Self::Error> {
+            self.is_high().map(|high| !high)
+        }
+    }
+
+    /// Returns raw ids of the pressed keys.
+    fn pressed<L>(layout: &mut L) -> Vec<u16>
+    where
+        L: TryLayout<Error = Infallible>,
+    {
+        let (keys, Ok(())) = scan(layout);
+        assert!(keys.iter().all(|&(_, state)| state == KeyState::Pressed));
+        keys.into_iter().map(|(k, _)| k).collect()
+    }
+
+    #[test]
+    fn bit_order() {
+        // `D0` and `D2` of the first register and `D7` of the second one are
+        // pulled low
+        let inputs = [!0b0000_0101, !0b1000_0000];
+
+        let chip = Chip::new(inputs, false);
+        assert_eq!(pressed(&mut chip.layout()), [0, 2, 15]);
+        assert_eq!(
+            pressed(&mut chip.layout().bit_order(BitOrder::LsbFirst)),
+            [5, 7, 8]
+        );
+
+        let chip = Chip::new(inputs, true);
+        assert_eq!(
+            pressed(&mut chip.layout().bit_order(BitOrder::LsbFirst)),
+            [0, 2, 15]
+        );
+        assert_eq!(pressed(&mut chip.layout()), [5, 7, 8]);
+    }
+
+    #[test]
+    fn active_level() {
+        let chip = Chip::new([0b0000_0101, 0b1000_0000], false);
+        let mut layout = chip.layout().active_level(ActiveLevel::High);
+        assert_eq!(pressed(&mut layout), [0, 2, 15]);
+    }
+
+    /// SPI peripheral which reads `bytes` or fails if there are none.
+    struct Spi(Option<[u8; 2]>);
+
+    struct Pin;
+
+    impl Transfer<u8> for Spi {
+        type Error = ();
+
+        fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Self::Error> {
+            words.copy_from_slice(&self.0.ok_or(())?);
+            Ok(words)
+        }
+    }
+
+    impl OutputPin for Pin {
+        type Error = Infallible;
+
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn spi() {
+        let mut layout = ShiftRegister::<_, 2>::new_spi(Spi(Some([!0b0100_0000, !1])), Pin);
+        let (keys, res) = scan(&mut layout);
+        assert_eq!(keys, [(6, KeyState::Pressed), (8, KeyState::Pressed)]);
+        assert_eq!(res, Ok(()));
+
+        // All keys are unknown if the registers can't be read
+        let mut layout = ShiftRegister::<_, 2>::new_spi(Spi(None), Pin);
+        let (keys, res) = scan(&mut layout);
+        assert_eq!(keys.len(), 16);
+        assert!(keys.iter().all(|&(_, state)| state == KeyState::Unknown));
+        assert!(res.is_err());
+    }
+}
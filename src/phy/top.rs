@@ -1,13 +1,471 @@
 use crate::phy::KeyId;
 
+/// Compile-time parsing of ASCII-art drawings of boards, see
+/// [`ascii_layout`].
+///
+/// [`ascii_layout`]: crate::ascii_layout
+pub mod ascii;
+/// Fixed-point topological representation, for MCUs without an FPU.
+///
+/// Using it instead of the floating point one allows to store geometry in the
+/// firmware without pulling in soft-float code, conversion to floating point
+/// can be done on the host.
+pub mod fixed;
+/// Importer of layouts made in [keyboard-layout-editor.com].
+///
+/// [keyboard-layout-editor.com]: http://www.keyboard-layout-editor.com
+#[cfg(feature = "kle")]
+pub mod kle;
+/// SVG rendering of topological representations, for example to make cheat
+/// sheets of keymap layers.
+#[cfg(any(test, feature = "std"))]
+pub mod svg;
+/// Validation of topological representations, for example in tests.
+pub mod validate;
+
+/// Topological representation of a keyboard, positions of its keys.
+///
+/// Coordinates are in key units (`1u` = the size of a normal key, usually
+/// 19.05 mm), `x` grows to the right and `y` grows down.
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Repr<'a> {
     pub keys: &'a [KeyPos],
+    /// Centre of the keyboard, for example the point between the halves of a
+    /// split keyboard.
     pub centre: (f32, f32),
 }
 
+/// Position, size and shape of a key.
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct KeyPos {
     pub id: KeyId,
+    /// Centre of the key before rotation, see [`KeyPos::centre`].
     pub x: f32,
     pub y: f32,
+    /// Clockwise rotation of the key around `origin`.
     pub rotation_rad: f32,
+    /// Point around which the key is rotated.
+    pub origin: (f32, f32),
+    /// Width of the bounding box of the key.
+    pub width: f32,
+    /// Height of the bounding box of the key.
+    pub height: f32,
+    pub shape: Shape,
+}
+
+/// Shape of a key.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Shape {
+    /// Rectangular key.
+    Rect,
+    /// ISO enter, the bottom half of the key is narrower than the top one by
+    /// `width / 6` (0.25u for a standard 1.5u key).
+    IsoEnter,
+    /// Stepped key (like stepped caps lock), the outline is a rectangle, but
+    /// only the left part of the key, `step` wide, is raised.
+    Stepped { step: f32 },
+}
+
+/// Hand which presses a key, see [`Repr::hand`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Hand {
+    Left,
+    Right,
+}
+
+/// Outline of a key, a polygon with up to 6 vertices.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Polygon {
+    points: [(f32, f32); 6],
+    len: usize,
+}
+
+/// Owned [`Repr`] with space for up to `N` keys.
+///
+/// This is useful for layouts that need to compute their representation, for
+/// example from the representations of other layouts.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ReprBuf<const N: usize> {
+    keys: [KeyPos; N],
+    len: usize,
+    pub centre: (f32, f32),
+}
+
+impl Repr<'_> {
+    /// Returns the top left and bottom right corners of the smallest
+    /// rectangle containing outlines of all keys, if there are any keys.
+    pub fn bounds(&self) -> Option<((f32, f32), (f32, f32))> {
+        self.keys
+            .iter()
+            .flat_map(|key| {
+                let outline = key.outline();
+                (0..outline.len).map(move |i| outline.points[i])
+            })
+            .fold(None, |bounds, (x, y)| match bounds {
+                None => Some(((x, y), (x, y))),
+                Some(((x0, y0), (x1, y1))) => {
+                    Some(((x0.min(x), y0.min(y)), (x1.max(x), y1.max(y))))
+                }
+            })
+    }
+
+    /// Returns the position of the key `id`.
+    pub fn key(&self, id: KeyId) -> Option<&KeyPos> {
+        self.keys.iter().find(|key| key.id == id)
+    }
+
+    /// Finds keys nearest to the key `id`, by the distance between centres.
+    ///
+    /// Returns the nearest keys with distances to them, closest first. At most
+    /// `buf.len()` keys are returned.
+    pub fn nearest<'b>(&self, id: KeyId, buf: &'b mut [(KeyId, f32)]) -> &'b [(KeyId, f32)] {
+        let key = match self.key(id) {
+            Some(key) => key,
+            None => return &[],
+        };
+        let mut len = 0;
+
+        for other in self.keys.iter().filter(|other| other.id != id) {
+            let distance = key.distance(other);
+
+            // Insertion sort, dropping the farthest key if the buffer is full
+            let i = buf[..len].partition_point(|&(_, d)| d <= distance);
+            if i == buf.len() {
+                continue;
+            }
+
+            len = (len + 1).min(buf.len());
+            buf[i..len].rotate_right(1);
+            buf[i] = (other.id, distance);
+        }
+
+        &buf[..len]
+    }
+
+    /// Returns the hand which presses the key `id`, based on the position of
+    /// the key relative to [`Repr::centre`].
+    ///
+    /// Returns `None` if there is no such key or if it's exactly on the centre
+    /// line.
+    pub fn hand(&self, id: KeyId) -> Option<Hand> {
+        let (x, _) = self.key(id)?.centre();
+
+        if x < self.centre.0 {
+            Some(Hand::Left)
+        } else if x > self.centre.0 {
+            Some(Hand::Right)
+        } else {
+            None
+        }
+    }
+
+    /// Infers the logical row of the key `id`, `0` being the top row.
+    ///
+    /// Rows are assumed to be `1u` apart. Centres of keys are rotated into the
+    /// frame of the key `id` (by the opposite of its rotation), so rows follow
+    /// the rotation of a rotated board or half. Only keys with the same
+    /// rotation as the key `id` are considered, so halves rotated in opposite
+    /// directions (or rotated thumb clusters) have their own rows.
+    pub fn row(&self, id: KeyId) -> Option<u16> {
+        let frame = Frame::of(self, self.key(id)?);
+        Some(frame.row(frame.key))
+    }
+
+    /// Infers the logical column of the key `id`, `0` being the leftmost
+    /// column.
+    ///
+    /// Keys are counted within the [row](Repr::row) of the key, in the same
+    /// rotated frame, so row stagger and wide keys (like tab or shift) don't
+    /// matter. Gaps between keys are not counted as columns.
+    pub fn column(&self, id: KeyId) -> Option<u16> {
+        let frame = Frame::of(self, self.key(id)?);
+        let row = frame.row(frame.key);
+
+        // Keys in a row are at least ~1u apart
+        let left = frame
+            .centres()
+            .filter(|&(x, y)| frame.row((x, y)) == row && x < frame.key.0 - 0.5)
+            .count();
+
+        Some(left as u16)
+    }
+}
+
+/// Keys of a [`Repr`] in the frame of one of them, see [`Repr::row`].
+///
+/// Only keys rotated by the same angle as that key are in the frame, so that
+/// halves or clusters rotated differently don't affect each other.
+struct Frame<'a> {
+    keys: &'a [KeyPos],
+    rotation_rad: f32,
+    sin: f32,
+    cos: f32,
+    /// Topmost `y` of keys, in this frame.
+    top: f32,
+    /// Centre of the key, in this frame.
+    key: (f32, f32),
+}
+
+impl<'a> Frame<'a> {
+    fn of(repr: &Repr<'a>, key: &KeyPos) -> Self {
+        let (sin, cos) = (libm::sinf(-key.rotation_rad), libm::cosf(-key.rotation_rad));
+        let mut frame = Self {
+            keys: repr.keys,
+            rotation_rad: key.rotation_rad,
+            sin,
+            cos,
+            top: 0.,
+            key: (0., 0.),
+        };
+
+        frame.top = frame
+            .centres()
+            .map(|(_, y)| y)
+            .fold(f32::INFINITY, f32::min);
+        frame.key = frame.project(key.centre());
+        frame
+    }
+
+    /// Returns centres of keys in this frame.
+    fn centres(&self) -> impl Iterator<Item = (f32, f32)> + '_ {
+        self.keys
+            .iter()
+            // Angles of keys of the same group are usually exactly equal
+            .filter(move |key| (key.rotation_rad - self.rotation_rad).abs() < 1e-3)
+            .map(move |key| self.project(key.centre()))
+    }
+
+    fn project(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        (x * self.cos - y * self.sin, x * self.sin + y * self.cos)
+    }
+
+    fn row(&self, (_, y): (f32, f32)) -> u16 {
+        libm::roundf(y - self.top) as u16
+    }
+}
+
+impl KeyPos {
+    /// Creates a 1u rectangular key, not rotated, with centre at `(x, y)`.
+    pub const fn new(id: KeyId, x: f32, y: f32) -> Self {
+        Self {
+            id,
+            x,
+            y,
+            rotation_rad: 0.,
+            origin: (x, y),
+            width: 1.,
+            height: 1.,
+            shape: Shape::Rect,
+        }
+    }
+
+    /// Sets the size of the bounding box of the key.
+    pub const fn size(self, width: f32, height: f32) -> Self {
+        Self {
+            width,
+            height,
+            ..self
+        }
+    }
+
+    /// Sets the shape of the key.
+    pub const fn shape(self, shape: Shape) -> Self {
+        Self { shape, ..self }
+    }
+
+    /// Rotates the key clockwise by `rotation_rad` around `origin`.
+    pub const fn rotate(self, rotation_rad: f32, origin: (f32, f32)) -> Self {
+        Self {
+            rotation_rad,
+            origin,
+            ..self
+        }
+    }
+
+    /// Returns the centre of the key, after rotation.
+    pub fn centre(&self) -> (f32, f32) {
+        self.transform((self.x, self.y))
+    }
+
+    /// Returns the distance between centres of two keys.
+    pub fn distance(&self, other: &KeyPos) -> f32 {
+        let ((x0, y0), (x1, y1)) = (self.centre(), other.centre());
+        libm::hypotf(x1 - x0, y1 - y0)
+    }
+
+    /// Returns the outline of the key, after rotation.
+    pub fn outline(&self) -> Polygon {
+        let (w, h) = (self.width / 2., self.height / 2.);
+        let (x0, y0, x1, y1) = (self.x - w, self.y - h, self.x + w, self.y + h);
+
+        let mut polygon = match self.shape {
+            Shape::Rect | Shape::Stepped { .. } => Polygon {
+                points: [(x0, y0), (x1, y0), (x1, y1), (x0, y1), (0., 0.), (0., 0.)],
+                len: 4,
+            },
+            Shape::IsoEnter => {
+                let notch = x0 + self.width / 6.;
+
+                Polygon {
+                    points: [
+                        (x0, y0),
+                        (x1, y0),
+                        (x1, y1),
+                        (notch, y1),
+                        (notch, self.y),
+                        (x0, self.y),
+                    ],
+                    len: 6,
+                }
+            }
+        };
+
+        polygon.points[..polygon.len]
+            .iter_mut()
+            .for_each(|p| *p = self.transform(*p));
+
+        polygon
+    }
+
+    /// Rotates a point around the rotation origin of the key.
+    fn transform(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        let (ox, oy) = self.origin;
+        let (x, y) = (x - ox, y - oy);
+        let (sin, cos) = (libm::sinf(self.rotation_rad), libm::cosf(self.rotation_rad));
+
+        (ox + x * cos - y * sin, oy + x * sin + y * cos)
+    }
+}
+
+impl Polygon {
+    /// Returns vertices of the polygon, clockwise.
+    pub fn points(&self) -> &[(f32, f32)] {
+        &self.points[..self.len]
+    }
+}
+
+impl<const N: usize> ReprBuf<N> {
+    /// Creates an empty representation.
+    pub const fn new(centre: (f32, f32)) -> Self {
+        const PLACEHOLDER: KeyPos = KeyPos::new(KeyId::from_raw(0), 0., 0.);
+
+        Self {
+            keys: [PLACEHOLDER; N],
+            len: 0,
+            centre,
+        }
+    }
+
+    /// Adds a key to the representation.
+    ///
+    /// Returns the key back if there is no space left.
+    pub fn push(&mut self, key: KeyPos) -> Result<(), KeyPos> {
+        match self.keys.get_mut(self.len) {
+            Some(slot) => {
+                *slot = key;
+                self.len += 1;
+                Ok(())
+            }
+            None => Err(key),
+        }
+    }
+
+    /// Returns the keys added so far.
+    pub fn keys(&self) -> &[KeyPos] {
+        &self.keys[..self.len]
+    }
+
+    /// Borrows the buffer as a [`Repr`].
+    pub fn as_repr(&self) -> Repr<'_> {
+        Repr {
+            keys: self.keys(),
+            centre: self.centre,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KeyPos, Repr};
+    use crate::phy::KeyId;
+
+    /// Checks `(row, column)` of every key, `expected` is indexed by raw ids.
+    fn check(keys: &[KeyPos], expected: &[(u16, u16)]) {
+        let repr = Repr {
+            keys,
+            centre: (0., 0.),
+        };
+
+        for (id, &(row, column)) in expected.iter().enumerate() {
+            let id = KeyId::from_raw(id as u16);
+            assert_eq!(repr.row(id), Some(row), "row of {:?}", id);
+            assert_eq!(repr.column(id), Some(column), "column of {:?}", id);
+        }
+    }
+
+    #[test]
+    fn row_stagger() {
+        let key = |id, x, y, width| KeyPos::new(KeyId::from_raw(id), x, y).size(width, 1.);
+
+        // Tab, caps lock and shift rows of an ANSI board
+        #[rustfmt::skip]
+        let keys = [
+            key(0, 0.75, 0., 1.5), key(1, 2., 0., 1.), key(2, 3., 0., 1.),
+            key(3, 0.875, 1., 1.75), key(4, 2.25, 1., 1.), key(5, 3.25, 1., 1.),
+            key(6, 1.125, 2., 2.25), key(7, 2.75, 2., 1.), key(8, 3.75, 2., 1.),
+        ];
+
+        #[rustfmt::skip]
+        check(&keys, &[
+            (0, 0), (0, 1), (0, 2),
+            (1, 0), (1, 1), (1, 2),
+            (2, 0), (2, 1), (2, 2),
+        ]);
+    }
+
+    #[test]
+    fn rotation() {
+        // 2x3 grid, rotated by 30 degrees around its top left corner, with the
+        // centres of the keys already rotated (as if they were measured on a
+        // real board) and rotation only set to orient the keys
+        let (sin, cos) = (0.5, 0.866_025_4);
+        let key = |id, x: f32, y: f32| {
+            let (x, y) = (x * cos - y * sin, x * sin + y * cos);
+            KeyPos::new(KeyId::from_raw(id), x, y).rotate(30_f32.to_radians(), (x, y))
+        };
+
+        #[rustfmt::skip]
+        let keys = [
+            key(0, 0.5, 0.5), key(1, 1.5, 0.5), key(2, 2.5, 0.5),
+            key(3, 0.5, 1.5), key(4, 1.5, 1.5), key(5, 2.5, 1.5),
+        ];
+
+        check(&keys, &[(0, 0), (0, 1), (0, 2), (1, 0), (1, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn split_rotation() {
+        // 2x3 halves, the left one rotated by 20 degrees clockwise and the
+        // right one mirrored, rotated counter-clockwise
+        let (sin, cos) = (20_f32.to_radians().sin(), 20_f32.to_radians().cos());
+        let key = |id, x: f32, y: f32, mirror: bool| {
+            let (x, y) = (x * cos - y * sin, x * sin + y * cos);
+            let (x, rotation) = if mirror { (10. - x, -20_f32) } else { (x, 20.) };
+            KeyPos::new(KeyId::from_raw(id), x, y).rotate(rotation.to_radians(), (x, y))
+        };
+
+        #[rustfmt::skip]
+        let keys = [
+            key(0, 0.5, 0.5, false), key(1, 1.5, 0.5, false), key(2, 2.5, 0.5, false),
+            key(3, 0.5, 1.5, false), key(4, 1.5, 1.5, false), key(5, 2.5, 1.5, false),
+            key(6, 2.5, 0.5, true), key(7, 1.5, 0.5, true), key(8, 0.5, 0.5, true),
+            key(9, 2.5, 1.5, true), key(10, 1.5, 1.5, true), key(11, 0.5, 1.5, true),
+        ];
+
+        #[rustfmt::skip]
+        check(&keys, &[
+            (0, 0), (0, 1), (0, 2), (1, 0), (1, 1), (1, 2),
+            (0, 0), (0, 1), (0, 2), (1, 0), (1, 1), (1, 2),
+        ]);
+    }
 }
@@ -0,0 +1,345 @@
+use crate::{
+    phy::{top::KeyPos, KeyId},
+    proto::KeyCode,
+};
+
+/// Defines a topological representation and a keymap from ASCII-art drawings
+/// of a board.
+///
+/// The macro takes the names of three statics, the width of `1u` in
+/// characters and one or more drawings, one per layer. Every key is drawn as
+/// `[...]` with the name of its [`KeyCode`] inside (see
+/// [`KeyCode::from_name`]), keys with nothing but spaces inside are
+/// [`KeyCode::No`]. For example, with `unit = 4`, `[Q ][W ]` are two 1u keys,
+/// `[LShift]` is a 2u key and `  [A ]` is a 1u key offset by 0.5u.
+///
+/// The first drawing defines the geometry, see [`keys`], the other ones only
+/// need to have the same number of keys.
+///
+/// It expands to:
+/// - `static $keys: [KeyPos; N]`, positions of the keys, [`KeyId`]s are
+///   assigned in reading order (see [`Remap`] for adapting the wiring to
+///   them)
+/// - `static $repr: Repr<'static>`, representation using `$keys`
+/// - `static $keymap: [[KeyCode; N]; L]`, key codes of every layer, indexed
+///   by raw [`KeyId`]s
+///
+/// All of the parsing is done at compile time, mistakes in drawings are
+/// compile errors.
+///
+/// [`Remap`]: crate::phy::layouts::Remap
+#[macro_export]
+macro_rules! ascii_layout {
+    (
+        $vis:vis static $keys:ident, $repr:ident, $keymap:ident;
+        unit = $unit:expr;
+        $first:expr $(, $rest:expr)* $(,)?
+    ) => {
+        $vis static $keys: [$crate::phy::top::KeyPos; $crate::phy::top::ascii::count($first)] =
+            $crate::phy::top::ascii::keys($first, $unit);
+
+        $vis static $repr: $crate::phy::top::Repr<'static> = $crate::phy::top::Repr {
+            keys: &$keys,
+            centre: $crate::phy::top::ascii::centre(
+                &$crate::phy::top::ascii::keys::<{ $crate::phy::top::ascii::count($first) }>(
+                    $first, $unit,
+                ),
+            ),
+        };
+
+        $vis static $keymap: [
+            [$crate::proto::KeyCode; $crate::phy::top::ascii::count($first)];
+            [$first $(, $rest)*].len()
+        ] = [
+            $crate::phy::top::ascii::layer($first),
+            $($crate::phy::top::ascii::layer($rest),)*
+        ];
+    };
+}
+
+/// Returns the number of keys in a drawing.
+pub const fn count(art: &str) -> usize {
+    let art = art.as_bytes();
+    let mut count = 0;
+    let mut i = 0;
+
+    while i < art.len() {
+        if art[i] == b'[' {
+            count += 1;
+        }
+
+        i += 1;
+    }
+
+    count
+}
+
+/// Parses positions of keys from a drawing, `unit` is the width of `1u` in
+/// characters.
+///
+/// Every line of the drawing is a `1u` tall row, starting at the first line
+/// with keys (so empty lines between rows are gaps). Horizontal positions and
+/// widths of keys are the positions and widths of `[...]` divided by `unit`,
+/// starting at the leftmost key. [`KeyId`]s are assigned in reading order.
+///
+/// Only ASCII is supported.
+///
+/// ## Panics
+///
+/// Panics if there are not exactly `N` keys or if a key is not closed.
+pub const fn keys<const N: usize>(art: &str, unit: usize) -> [KeyPos; N] {
+    let art = art.as_bytes();
+    let indent = indent(art);
+    let unit = unit as f32;
+    let mut keys = [KeyPos::new(KeyId::from_raw(0), 0., 0.); N];
+    let mut n = 0;
+    let mut row = 0;
+    let mut started = false;
+    let mut col = 0;
+    let mut i = 0;
+
+    while i < art.len() {
+        match art[i] {
+            b'\n' => {
+                if started {
+                    row += 1;
+                }
+                col = 0;
+            }
+            b'[' => {
+                let end = close(art, i);
+                let width = (end - i + 1) as f32;
+
+                if n == N {
+                    panic!("more keys than expected");
+                }
+
+                let x = ((col - indent) as f32 + width / 2.) / unit;
+                keys[n] = KeyPos::new(KeyId::from_raw(n as u16), x, row as f32 + 0.5)
+                    .size(width / unit, 1.);
+
+                started = true;
+                n += 1;
+                col += end - i;
+                i = end;
+            }
+            _ => {}
+        }
+
+        if art[i] != b'\n' {
+            col += 1;
+        }
+        i += 1;
+    }
+
+    if n != N {
+        panic!("less keys than expected");
+    }
+
+    keys
+}
+
+/// Parses key codes from a drawing, see [`ascii_layout`].
+///
+/// ## Panics
+///
+/// Panics if there are not exactly `N` keys, if a key is not closed or if a
+/// key code is unknown.
+///
+/// [`ascii_layout`]: crate::ascii_layout
+pub const fn layer<const N: usize>(art: &str) -> [KeyCode; N] {
+    let art = art.as_bytes();
+    let mut layer = [KeyCode::No; N];
+    let mut n = 0;
+    let mut i = 0;
+
+    while i < art.len() {
+        if art[i] == b'[' {
+            let end = close(art, i);
+
+            if n == N {
+                panic!("more keys than expected");
+            }
+
+            let (start, end) = trim(art, i + 1, end);
+            let (name, _) = art.split_at(end);
+            let (_, name) = name.split_at(start);
+
+            layer[n] = if name.is_empty() {
+                KeyCode::No
+            } else {
+                match core::str::from_utf8(name) {
+                    Ok(name) => match KeyCode::from_name(name) {
+                        Some(kc) => kc,
+                        None => panic!("unknown key code"),
+                    },
+                    Err(_) => panic!("key code is not valid UTF-8"),
+                }
+            };
+
+            n += 1;
+            i = end;
+        }
+
+        i += 1;
+    }
+
+    if n != N {
+        panic!("less keys than expected");
+    }
+
+    layer
+}
+
+/// Returns the centre of the bounding box of keys (ignoring rotation).
+pub const fn centre(keys: &[KeyPos]) -> (f32, f32) {
+    if keys.is_empty() {
+        return (0., 0.);
+    }
+
+    let (mut x0, mut y0) = (f32::INFINITY, f32::INFINITY);
+    let (mut x1, mut y1) = (f32::NEG_INFINITY, f32::NEG_INFINITY);
+    let mut i = 0;
+
+    while i < keys.len() {
+        let key = &keys[i];
+        let (w, h) = (key.width / 2., key.height / 2.);
+
+        if key.x - w < x0 {
+            x0 = key.x - w;
+        }
+        if key.y - h < y0 {
+            y0 = key.y - h;
+        }
+        if key.x + w > x1 {
+            x1 = key.x + w;
+        }
+        if key.y + h > y1 {
+            y1 = key.y + h;
+        }
+
+        i += 1;
+    }
+
+    ((x0 + x1) / 2., (y0 + y1) / 2.)
+}
+
+/// Returns the smallest number of characters before the first key of a line.
+const fn indent(art: &[u8]) -> usize {
+    let mut indent = usize::MAX;
+    let mut col = 0;
+    let mut i = 0;
+
+    while i < art.len() {
+        match art[i] {
+            b'\n' => col = 0,
+            b'[' => {
+                if col < indent {
+                    indent = col;
+                }
+
+                // Skip the rest of the line
+                while i < art.len() && art[i] != b'\n' {
+                    i += 1;
+                }
+                col = 0;
+            }
+            _ => col += 1,
+        }
+
+        i += 1;
+    }
+
+    indent
+}
+
+/// Returns the index of `]` closing `[` at `open`.
+const fn close(art: &[u8], open: usize) -> usize {
+    let mut i = open + 1;
+
+    while i < art.len() {
+        match art[i] {
+            b']' => return i,
+            b'\n' | b'[' => break,
+            _ => i += 1,
+        }
+    }
+
+    panic!("key is not closed")
+}
+
+/// Returns the range of `art[start..end]` without leading and trailing
+/// spaces.
+const fn trim(art: &[u8], mut start: usize, mut end: usize) -> (usize, usize) {
+    while start < end && art[start] == b' ' {
+        start += 1;
+    }
+
+    while end > start && art[end - 1] == b' ' {
+        end -= 1;
+    }
+
+    (start, end)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{phy::KeyId, proto::KeyCode};
+
+    crate::ascii_layout! {
+        static KEYS, REPR, KEYMAP;
+        unit = 4;
+        "
+            [Q ][W ][E ]
+              [A ][S ]
+
+            [LShift][  ]
+        ",
+        "
+            [Kb1][Kb2][Kb3]
+              [  ][  ]
+
+            [  ][Space]
+        ",
+    }
+
+    #[test]
+    fn ascii_layout() {
+        assert_eq!(KEYS.len(), 7);
+
+        #[rustfmt::skip]
+        let expected = [
+            (0.5, 0.5, 1.),
+            (1.5, 0.5, 1.),
+            (2.5, 0.5, 1.),
+            // Staggered by 0.5u
+            (1., 1.5, 1.),
+            (2., 1.5, 1.),
+            // After an empty line
+            (1., 3.5, 2.),
+            (2.5, 3.5, 1.),
+        ];
+
+        for (i, (key, (x, y, width))) in KEYS.iter().zip(expected).enumerate() {
+            assert_eq!(key.id, KeyId::from_raw(i as u16));
+            assert_eq!(
+                (key.x, key.y, key.width, key.height),
+                (x, y, width, 1.),
+                "{}",
+                i
+            );
+        }
+
+        assert_eq!(REPR.keys, &KEYS);
+        assert_eq!(REPR.centre, (1.5, 2.));
+
+        use KeyCode::*;
+        assert_eq!(
+            KEYMAP,
+            [
+                [Q, W, E, A, S, LShift, No],
+                [Kb1, Kb2, Kb3, No, No, No, Space],
+            ]
+        );
+    }
+}
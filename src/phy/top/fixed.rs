@@ -0,0 +1,352 @@
+use crate::phy::{top, KeyId};
+
+/// Distance in key units, a fixed-point number in the Q8.8 format (i.e. the
+/// raw value is the distance multiplied by 256).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Units(pub i16);
+
+/// Angle in millidegrees, clockwise.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Angle(pub i32);
+
+/// Fixed-point version of [`top::Repr`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Repr<'a> {
+    pub keys: &'a [KeyPos],
+    pub centre: (Units, Units),
+}
+
+/// Fixed-point version of [`top::ReprBuf`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ReprBuf<const N: usize> {
+    keys: [KeyPos; N],
+    len: usize,
+    pub centre: (Units, Units),
+}
+
+/// Fixed-point version of [`top::KeyPos`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct KeyPos {
+    pub id: KeyId,
+    pub x: Units,
+    pub y: Units,
+    pub rotation: Angle,
+    pub origin: (Units, Units),
+    pub width: Units,
+    pub height: Units,
+    pub shape: Shape,
+}
+
+/// Fixed-point version of [`top::Shape`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Shape {
+    Rect,
+    IsoEnter,
+    Stepped { step: Units },
+}
+
+impl Units {
+    /// `1u`.
+    pub const ONE: Self = Self(256);
+
+    /// Converts a distance to fixed-point, rounding to the nearest
+    /// representable value.
+    ///
+    /// This is a `const fn`, so distances can be written in a readable way
+    /// without pulling floating point code into the firmware.
+    pub const fn from_f32(units: f32) -> Self {
+        Self(round(units * 256.) as i16)
+    }
+
+    /// Converts the distance to floating point, this is always exact.
+    pub fn to_f32(self) -> f32 {
+        f32::from(self.0) / 256.
+    }
+}
+
+impl Angle {
+    /// Converts an angle in degrees to fixed-point, see [`Units::from_f32`].
+    pub const fn from_deg(deg: f32) -> Self {
+        Self(round(deg * 1000.) as i32)
+    }
+
+    /// Converts an angle in radians to fixed-point, rounding to the nearest
+    /// millidegree.
+    pub fn from_rad(rad: f32) -> Self {
+        Self::from_deg(rad.to_degrees())
+    }
+
+    /// Converts the angle to radians.
+    pub fn to_rad(self) -> f32 {
+        (self.0 as f32 / 1000.).to_radians()
+    }
+}
+
+const fn round(x: f32) -> f32 {
+    if x < 0. {
+        x - 0.5
+    } else {
+        x + 0.5
+    }
+}
+
+impl Repr<'_> {
+    /// Converts the representation to floating point, returns `None` if it
+    /// has more than `N` keys.
+    pub fn to_f32<const N: usize>(&self) -> Option<top::ReprBuf<N>> {
+        let mut buf = top::ReprBuf::new((self.centre.0.to_f32(), self.centre.1.to_f32()));
+
+        for key in self.keys {
+            buf.push(key.to_f32()).ok()?;
+        }
+
+        Some(buf)
+    }
+}
+
+impl<const N: usize> ReprBuf<N> {
+    /// Creates an empty representation.
+    pub const fn new(centre: (Units, Units)) -> Self {
+        const PLACEHOLDER: KeyPos = KeyPos::new(KeyId::from_raw(0), Units(0), Units(0));
+
+        Self {
+            keys: [PLACEHOLDER; N],
+            len: 0,
+            centre,
+        }
+    }
+
+    /// Adds a key to the representation.
+    ///
+    /// Returns the key back if there is no space left.
+    pub fn push(&mut self, key: KeyPos) -> Result<(), KeyPos> {
+        match self.keys.get_mut(self.len) {
+            Some(slot) => {
+                *slot = key;
+                self.len += 1;
+                Ok(())
+            }
+            None => Err(key),
+        }
+    }
+
+    /// Returns the keys added so far.
+    pub fn keys(&self) -> &[KeyPos] {
+        &self.keys[..self.len]
+    }
+
+    /// Borrows the buffer as a [`Repr`].
+    pub fn as_repr(&self) -> Repr<'_> {
+        Repr {
+            keys: self.keys(),
+            centre: self.centre,
+        }
+    }
+}
+
+impl KeyPos {
+    /// Creates a 1u rectangular key, not rotated, with centre at `(x, y)`.
+    pub const fn new(id: KeyId, x: Units, y: Units) -> Self {
+        Self {
+            id,
+            x,
+            y,
+            rotation: Angle(0),
+            origin: (x, y),
+            width: Units::ONE,
+            height: Units::ONE,
+            shape: Shape::Rect,
+        }
+    }
+
+    /// Sets the size of the bounding box of the key.
+    pub const fn size(self, width: Units, height: Units) -> Self {
+        Self {
+            width,
+            height,
+            ..self
+        }
+    }
+
+    /// Sets the shape of the key.
+    pub const fn shape(self, shape: Shape) -> Self {
+        Self { shape, ..self }
+    }
+
+    /// Rotates the key clockwise by `rotation` around `origin`.
+    pub const fn rotate(self, rotation: Angle, origin: (Units, Units)) -> Self {
+        Self {
+            rotation,
+            origin,
+            ..self
+        }
+    }
+
+    /// Converts a floating point key position to fixed-point, rounding to the
+    /// nearest representable values.
+    pub fn from_f32(pos: &top::KeyPos) -> Self {
+        let shape = match pos.shape {
+            top::Shape::Rect => Shape::Rect,
+            top::Shape::IsoEnter => Shape::IsoEnter,
+            top::Shape::Stepped { step } => Shape::Stepped {
+                step: Units::from_f32(step),
+            },
+        };
+
+        Self {
+            id: pos.id,
+            x: Units::from_f32(pos.x),
+            y: Units::from_f32(pos.y),
+            rotation: Angle::from_rad(pos.rotation_rad),
+            origin: (Units::from_f32(pos.origin.0), Units::from_f32(pos.origin.1)),
+            width: Units::from_f32(pos.width),
+            height: Units::from_f32(pos.height),
+            shape,
+        }
+    }
+
+    /// Converts the key position to floating point.
+    pub fn to_f32(&self) -> top::KeyPos {
+        let shape = match self.shape {
+            Shape::Rect => top::Shape::Rect,
+            Shape::IsoEnter => top::Shape::IsoEnter,
+            Shape::Stepped { step } => top::Shape::Stepped {
+                step: step.to_f32(),
+            },
+        };
+
+        top::KeyPos {
+            id: self.id,
+            x: self.x.to_f32(),
+            y: self.y.to_f32(),
+            rotation_rad: self.rotation.to_rad(),
+            origin: (self.origin.0.to_f32(), self.origin.1.to_f32()),
+            width: self.width.to_f32(),
+            height: self.height.to_f32(),
+            shape,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Angle, KeyPos, Repr, Shape, Units};
+    use crate::{
+        keymap::fingerprint,
+        phy::{top, KeyId, Layout},
+    };
+
+    #[test]
+    fn units() {
+        assert_eq!(Units::from_f32(1.), Units::ONE);
+        assert_eq!(Units::from_f32(-2.25), Units(-576));
+        assert_eq!(Units::ONE.to_f32(), 1.);
+
+        #[rustfmt::skip]
+        let rounding = [
+            // Halves of the last bit are rounded away from zero
+            (0.5 / 256., 1),
+            (-0.5 / 256., -1),
+            (0.49 / 256., 0),
+            (-0.49 / 256., 0),
+            (1.7 / 256., 2),
+            (-1.7 / 256., -2),
+        ];
+
+        for (units, raw) in rounding {
+            assert_eq!(Units::from_f32(units), Units(raw), "{}", units);
+        }
+
+        for raw in [i16::MIN, -257, -1, 0, 1, 255, i16::MAX] {
+            assert_eq!(Units::from_f32(Units(raw).to_f32()), Units(raw));
+        }
+    }
+
+    #[test]
+    fn angle() {
+        assert_eq!(Angle::from_deg(90.), Angle(90_000));
+        assert_eq!(Angle::from_deg(-15.0004), Angle(-15_000));
+        assert_eq!(Angle::from_deg(-15.0006), Angle(-15_001));
+        assert_eq!(Angle::from_rad(core::f32::consts::FRAC_PI_2), Angle(90_000));
+
+        for raw in [-359_999, -20_000, -1, 0, 1, 7_500, 359_999] {
+            assert_eq!(Angle::from_rad(Angle(raw).to_rad()), Angle(raw));
+        }
+    }
+
+    #[test]
+    fn key_pos() {
+        let key = KeyPos::new(KeyId::from_raw(3), Units(640), Units(-128))
+            .size(Units(448), Units::ONE)
+            .shape(Shape::Stepped { step: Units(320) })
+            .rotate(Angle(-20_000), (Units(0), Units(512)));
+
+        let pos = key.to_f32();
+        assert_eq!((pos.x, pos.y, pos.width), (2.5, -0.5, 1.75));
+        assert_eq!(pos.shape, top::Shape::Stepped { step: 1.25 });
+        assert!((pos.rotation_rad + 20f32.to_radians()).abs() < 1e-6);
+        assert_eq!(KeyPos::from_f32(&pos), key);
+    }
+
+    const FIXED: [KeyPos; 2] = [
+        KeyPos::new(
+            KeyId::from_raw(0),
+            Units::from_f32(0.5),
+            Units::from_f32(0.5),
+        ),
+        KeyPos::new(
+            KeyId::from_raw(1),
+            Units::from_f32(1.75),
+            Units::from_f32(0.5),
+        ),
+    ];
+
+    struct Board {
+        fixed: bool,
+    }
+
+    impl Layout for Board {
+        fn poll(&mut self, _: &mut dyn FnMut(&mut dyn Iterator<Item = KeyId>)) {}
+
+        fn max_key_id(&self) -> KeyId {
+            KeyId::from_raw(2)
+        }
+
+        fn topological_repr(&self) -> Option<top::Repr<'_>> {
+            const KEYS: [top::KeyPos; 2] = [
+                top::KeyPos::new(KeyId::from_raw(0), 0.5, 0.5),
+                top::KeyPos::new(KeyId::from_raw(1), 1.75, 0.5),
+            ];
+
+            (!self.fixed).then_some(top::Repr {
+                keys: &KEYS,
+                centre: (1.125, 0.5),
+            })
+        }
+
+        fn fixed_repr(&self) -> Option<Repr<'_>> {
+            self.fixed.then_some(Repr {
+                keys: &FIXED,
+                centre: (Units(288), Units(128)),
+            })
+        }
+    }
+
+    #[test]
+    fn fixed_repr() {
+        let repr = Board { fixed: true }.fixed_repr().unwrap();
+        let buf = repr.to_f32::<2>().unwrap();
+        assert_eq!(
+            buf.as_repr(),
+            Board { fixed: false }.topological_repr().unwrap()
+        );
+        assert!(repr.to_f32::<1>().is_none());
+
+        // Keymaps made on the host for the floating point representation
+        // match the firmware with the fixed-point one
+        assert_eq!(
+            fingerprint(&Board { fixed: true }),
+            fingerprint(&Board { fixed: false })
+        );
+    }
+}
@@ -0,0 +1,531 @@
+use core::fmt;
+use std::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use serde_json::{Map, Value};
+
+use crate::phy::{
+    top::{
+        fixed::{self, Units},
+        KeyPos, Shape,
+    },
+    KeyId,
+};
+
+/// A key of a KLE layout.
+///
+/// Coordinates are in key units (`1u` = the size of a normal key), `x` grows to
+/// the right and `y` grows down.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Key {
+    /// Legends of the key, separated by `\n`, as stored by KLE.
+    pub labels: String,
+    /// Left edge of the bounding box of the key, before rotation.
+    pub x: f32,
+    /// Top edge of the bounding box of the key, before rotation.
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub shape: Shape,
+    /// Clockwise rotation of the key, in degrees.
+    pub rotation_deg: f32,
+    /// Point around which the key is rotated.
+    pub rotation_origin: (f32, f32),
+}
+
+/// Error of the KLE importer.
+#[derive(Debug)]
+pub enum Error {
+    /// The input is not valid JSON.
+    Json(serde_json::Error),
+    /// The input is valid JSON, but not a KLE layout.
+    Format(&'static str),
+    /// A key doesn't have a legend which is a [`KeyId`].
+    MissingId { key: usize },
+    /// Several keys have the same [`KeyId`].
+    DuplicateId(KeyId),
+}
+
+impl Key {
+    /// Returns [`KeyId`] of the key, the first legend which is a number.
+    pub fn id(&self) -> Option<KeyId> {
+        self.labels
+            .split('\n')
+            .find_map(|l| l.trim().parse().ok())
+            .map(KeyId::from_raw)
+    }
+
+    /// Returns the centre of the key, after rotation.
+    pub fn centre(&self) -> (f32, f32) {
+        let (x, y) = self.unrotated_centre();
+        let (ox, oy) = self.rotation_origin;
+        let (sin, cos) = self.rotation_deg.to_radians().sin_cos();
+        let (x, y) = (x - ox, y - oy);
+
+        (ox + x * cos - y * sin, oy + x * sin + y * cos)
+    }
+
+    /// Converts the key to [`KeyPos`], see [`Key::id`].
+    pub fn pos(&self) -> Option<KeyPos> {
+        let (x, y) = self.unrotated_centre();
+
+        let pos = KeyPos::new(self.id()?, x, y)
+            .size(self.width, self.height)
+            .shape(self.shape)
+            .rotate(self.rotation_deg.to_radians(), self.rotation_origin);
+
+        Some(pos)
+    }
+
+    /// Returns the centre of the bounding box of the key, before rotation.
+    fn unrotated_centre(&self) -> (f32, f32) {
+        (self.x + self.width / 2., self.y + self.height / 2.)
+    }
+}
+
+/// Second rectangle of a key (`x2`, `y2`, `w2`, `h2` in KLE), which is used
+/// for ISO enter, big-ass enter and stepped keys.
+#[derive(Default)]
+struct Second {
+    x: Option<f32>,
+    y: Option<f32>,
+    width: Option<f32>,
+    height: Option<f32>,
+    stepped: bool,
+}
+
+/// Parses a layout in the KLE JSON format (the one produced by "Download
+/// JSON").
+pub fn parse(json: &str) -> Result<Vec<Key>, Error> {
+    let rows = match serde_json::from_str(json).map_err(Error::Json)? {
+        Value::Array(rows) => rows,
+        _ => return Err(Error::Format("layout is not an array")),
+    };
+
+    let mut keys = Vec::new();
+    let mut current = Key {
+        labels: String::new(),
+        x: 0.,
+        y: 0.,
+        width: 1.,
+        height: 1.,
+        shape: Shape::Rect,
+        rotation_deg: 0.,
+        rotation_origin: (0., 0.),
+    };
+    let mut second = Second::default();
+
+    for (r, row) in rows.iter().enumerate() {
+        let row = match row {
+            Value::Array(row) => row,
+            // Keyboard metadata (name, author, etc)
+            Value::Object(_) if r == 0 => continue,
+            _ => return Err(Error::Format("row is not an array")),
+        };
+
+        for (k, item) in row.iter().enumerate() {
+            match item {
+                Value::String(labels) => {
+                    keys.push(with_second(
+                        Key {
+                            labels: labels.clone(),
+                            ..current.clone()
+                        },
+                        &second,
+                    ));
+
+                    current.x += current.width;
+                    current.width = 1.;
+                    current.height = 1.;
+                    second = Second::default();
+                }
+                Value::Object(props) => apply(&mut current, &mut second, props, k == 0)?,
+                _ => return Err(Error::Format("key is not a string or an object")),
+            }
+        }
+
+        current.y += 1.;
+        current.x = current.rotation_origin.0;
+    }
+
+    Ok(keys)
+}
+
+/// Applies the second rectangle to a key, changing its shape.
+///
+/// Only stepped keys and ISO enter are supported, for other shapes (like
+/// big-ass enter) the first rectangle is used as a rectangular key.
+fn with_second(key: Key, second: &Second) -> Key {
+    let x = key.x + second.x.unwrap_or(0.);
+    let y = key.y + second.y.unwrap_or(0.);
+    let width = second.width.unwrap_or(key.width);
+    let height = second.height.unwrap_or(key.height);
+
+    if second.stepped {
+        // The key itself is the raised step
+        Key {
+            width: key.width.max(width),
+            shape: Shape::Stepped {
+                step: key.width.min(width),
+            },
+            ..key
+        }
+    } else if is_iso_enter(&key, (x, y, width, height)) {
+        // The second rectangle is the wider top half
+        Key {
+            x,
+            width,
+            shape: Shape::IsoEnter,
+            ..key
+        }
+    } else {
+        key
+    }
+}
+
+/// Returns `true` if the key with the `second` rectangle is an ISO enter: the
+/// second rectangle is the top half of the key, aligned to the right and wider
+/// by a fifth of the key (1.5u and 1.25u for a standard one).
+fn is_iso_enter(key: &Key, (x, y, width, height): (f32, f32, f32, f32)) -> bool {
+    let eq = |a: f32, b: f32| (a - b).abs() < 1e-3;
+
+    eq(y, key.y)
+        && eq(height * 2., key.height)
+        && eq(x + width, key.x + key.width)
+        && eq(width - key.width, key.width / 5.)
+}
+
+/// Applies properties of the next key to `current` and `second`.
+fn apply(
+    current: &mut Key,
+    second: &mut Second,
+    props: &Map<String, Value>,
+    first: bool,
+) -> Result<(), Error> {
+    let prop = |name| match props.get(name) {
+        None => Ok(None),
+        Some(v) => v
+            .as_f64()
+            .map(|v| Some(v as f32))
+            .ok_or(Error::Format("key property is not a number")),
+    };
+
+    let (r, rx, ry) = (prop("r")?, prop("rx")?, prop("ry")?);
+
+    if !first && (r.is_some() || rx.is_some() || ry.is_some()) {
+        return Err(Error::Format(
+            "rotation can only be changed by the first key in a row",
+        ));
+    }
+
+    if let Some(r) = r {
+        current.rotation_deg = r;
+    }
+
+    // Changing the rotation origin starts a new cluster of keys at the origin
+    if let Some(rx) = rx {
+        current.rotation_origin.0 = rx;
+        (current.x, current.y) = current.rotation_origin;
+    }
+
+    if let Some(ry) = ry {
+        current.rotation_origin.1 = ry;
+        (current.x, current.y) = current.rotation_origin;
+    }
+
+    current.x += prop("x")?.unwrap_or(0.);
+    current.y += prop("y")?.unwrap_or(0.);
+
+    if let Some(w) = prop("w")? {
+        current.width = w;
+    }
+
+    if let Some(h) = prop("h")? {
+        current.height = h;
+    }
+
+    second.x = prop("x2")?.or(second.x);
+    second.y = prop("y2")?.or(second.y);
+    second.width = prop("w2")?.or(second.width);
+    second.height = prop("h2")?.or(second.height);
+    second.stepped |= props.get("l").and_then(Value::as_bool).unwrap_or(false);
+
+    Ok(())
+}
+
+/// Converts keys to [`KeyPos`]s sorted by [`KeyId`].
+///
+/// Returns an error if some key doesn't have a [`KeyId`] or if several keys
+/// have the same one.
+pub fn key_positions(keys: &[Key]) -> Result<Vec<KeyPos>, Error> {
+    let mut positions = keys
+        .iter()
+        .enumerate()
+        .map(|(k, key)| key.pos().ok_or(Error::MissingId { key: k }))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    positions.sort_by_key(|pos| pos.id);
+
+    match positions.windows(2).find(|w| w[0].id == w[1].id) {
+        Some(w) => Err(Error::DuplicateId(w[0].id)),
+        None => Ok(positions),
+    }
+}
+
+/// Generates a Rust expression of type `&[KeyPos]` with the given positions.
+///
+/// This is meant to be used from build scripts, the output can be written to
+/// a file in `OUT_DIR` and then `include!`d.
+pub fn to_rust(positions: &[KeyPos]) -> String {
+    let mut out = "&[\n".to_string();
+
+    for pos in positions {
+        let shape = match pos.shape {
+            Shape::Rect => "Rect".to_string(),
+            Shape::IsoEnter => "IsoEnter".to_string(),
+            Shape::Stepped { step } => format!("Stepped {{ step: {:?} }}", step),
+        };
+
+        out += &format!(
+            "    ::mbkb::phy::top::KeyPos::new(::mbkb::phy::KeyId::from_raw({}), {:?}, {:?})\
+             .size({:?}, {:?}).shape(::mbkb::phy::top::Shape::{}).rotate({:?}, {:?}),\n",
+            pos.id.into_raw(),
+            pos.x,
+            pos.y,
+            pos.width,
+            pos.height,
+            shape,
+            pos.rotation_rad,
+            pos.origin,
+        );
+    }
+
+    out += "]";
+    out
+}
+
+/// Same as [`to_rust`], but generates an expression of type
+/// `&[fixed::KeyPos]`, see [`fixed`].
+///
+/// [`fixed`]: crate::phy::top::fixed
+pub fn to_rust_fixed(positions: &[KeyPos]) -> String {
+    let mut out = "&[\n".to_string();
+    let units = |u: Units| format!("::mbkb::phy::top::fixed::Units({})", u.0);
+
+    for pos in positions {
+        let pos = fixed::KeyPos::from_f32(pos);
+        let shape = match pos.shape {
+            fixed::Shape::Rect => "Rect".to_string(),
+            fixed::Shape::IsoEnter => "IsoEnter".to_string(),
+            fixed::Shape::Stepped { step } => {
+                format!("Stepped {{ step: {} }}", units(step))
+            }
+        };
+
+        out += &format!(
+            "    ::mbkb::phy::top::fixed::KeyPos::new(::mbkb::phy::KeyId::from_raw({}), {}, {})\
+             .size({}, {}).shape(::mbkb::phy::top::fixed::Shape::{})\
+             .rotate(::mbkb::phy::top::fixed::Angle({}), ({}, {})),\n",
+            pos.id.into_raw(),
+            units(pos.x),
+            units(pos.y),
+            units(pos.width),
+            units(pos.height),
+            shape,
+            pos.rotation.0,
+            units(pos.origin.0),
+            units(pos.origin.1),
+        );
+    }
+
+    out += "]";
+    out
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Json(err) => write!(f, "invalid JSON: {}", err),
+            Self::Format(msg) => write!(f, "invalid KLE layout: {}", msg),
+            Self::MissingId { key } => write!(f, "key #{} doesn't have a numeric legend", key),
+            Self::DuplicateId(id) => write!(f, "several keys have id {}", id.into_raw()),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Json(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{string::ToString, vec::Vec};
+
+    use super::{key_positions, parse, Error, Key};
+    use crate::phy::{top::Shape, KeyId};
+
+    fn key(labels: &str, x: f32, y: f32) -> Key {
+        Key {
+            labels: labels.to_string(),
+            x,
+            y,
+            width: 1.,
+            height: 1.,
+            shape: Shape::Rect,
+            rotation_deg: 0.,
+            rotation_origin: (0., 0.),
+        }
+    }
+
+    fn size(key: Key, width: f32, height: f32) -> Key {
+        Key {
+            width,
+            height,
+            ..key
+        }
+    }
+
+    fn rotate(key: Key, rotation_deg: f32, rotation_origin: (f32, f32)) -> Key {
+        Key {
+            rotation_deg,
+            rotation_origin,
+            ..key
+        }
+    }
+
+    #[test]
+    fn rows() {
+        let json = r#"[
+            {"name": "test", "author": "someone"},
+            ["0", "1", {"x": 0.5}, "2"],
+            [{"w": 1.5}, "3", "4"],
+            [{"y": 0.5, "h": 2}, "5", "6"]
+        ]"#;
+
+        assert_eq!(
+            parse(json).unwrap(),
+            [
+                key("0", 0., 0.),
+                key("1", 1., 0.),
+                key("2", 2.5, 0.),
+                // Size is only set for one key
+                size(key("3", 0., 1.), 1.5, 1.),
+                key("4", 1.5, 1.),
+                size(key("5", 0., 2.5), 1., 2.),
+                key("6", 1., 2.5),
+            ]
+        );
+    }
+
+    #[test]
+    fn rotated_clusters() {
+        // Thumb clusters of a split keyboard
+        let json = r#"[
+            ["0"],
+            [{"r": 15, "rx": 4, "ry": 2}, "1", "2"],
+            ["3"],
+            [{"r": -15, "rx": 10, "y": -1, "x": -2}, "4", "5"],
+            ["6"]
+        ]"#;
+
+        let rotated = |labels, x, y| rotate(key(labels, x, y), 15., (4., 2.));
+        let mirrored = |labels, x, y| rotate(key(labels, x, y), -15., (10., 2.));
+
+        assert_eq!(
+            parse(json).unwrap(),
+            [
+                key("0", 0., 0.),
+                // Changing the origin moves to it
+                rotated("1", 4., 2.),
+                rotated("2", 5., 2.),
+                // Rows start at the origin
+                rotated("3", 4., 3.),
+                // `ry` is kept, offsets are relative to the origin
+                mirrored("4", 8., 1.),
+                mirrored("5", 9., 1.),
+                mirrored("6", 10., 2.),
+            ]
+        );
+
+        let key = &parse(json).unwrap()[1];
+        let pos = key.pos().unwrap();
+        assert_eq!(pos.rotation_rad, 15_f32.to_radians());
+        assert_eq!(pos.origin, (4., 2.));
+
+        let ((x0, y0), (x1, y1)) = (key.centre(), pos.centre());
+        assert!((x0 - x1).abs() < 1e-6 && (y0 - y1).abs() < 1e-6);
+        assert!((x0 - 4.353).abs() < 1e-3 && (y0 - 2.612).abs() < 1e-3);
+    }
+
+    #[test]
+    fn second_rectangle() {
+        let json = r#"[
+            [{"w": 1.25, "w2": 1.75, "l": true}, "0", {"x": 0.5}, "1"],
+            [{"x": 0.25, "w": 1.25, "h": 2, "w2": 1.5, "h2": 1, "x2": -0.25}, "2", "3"],
+            [{"x": 0.75, "w": 1.5, "h": 2, "w2": 2.25, "h2": 1, "x2": -0.75, "y2": 1}, "4"]
+        ]"#;
+
+        let keys = parse(json).unwrap();
+
+        // Stepped caps lock
+        assert_eq!(keys[0].shape, Shape::Stepped { step: 1.25 });
+        assert_eq!((keys[0].x, keys[0].width), (0., 1.75));
+        assert_eq!(keys[1], key("1", 1.75, 0.));
+
+        // ISO enter, the second rectangle only applies to one key
+        let enter = Key {
+            shape: Shape::IsoEnter,
+            ..size(key("2", 0., 1.), 1.5, 2.)
+        };
+        assert_eq!(keys[2], enter);
+        assert_eq!(keys[3], key("3", 1.5, 1.));
+
+        // Big-ass enter is not ISO enter, only the first rectangle is used
+        assert_eq!(keys[4], size(key("4", 0.75, 2.), 1.5, 2.));
+    }
+
+    #[test]
+    fn positions() {
+        let json = r#"[["Esc\n\n\n2", "a\n0"], ["1"]]"#;
+        let positions = key_positions(&parse(json).unwrap()).unwrap();
+
+        let ids: Vec<_> = positions.iter().map(|pos| pos.id.into_raw()).collect();
+        assert_eq!(ids, [0, 1, 2]);
+        assert_eq!(positions[2].centre(), (0.5, 0.5));
+
+        let missing = key_positions(&parse(r#"[["0", "Esc"]]"#).unwrap());
+        assert!(matches!(missing, Err(Error::MissingId { key: 1 })));
+
+        let duplicate = key_positions(&parse(r#"[["3", "1"], ["3"]]"#).unwrap());
+        assert!(matches!(duplicate, Err(Error::DuplicateId(id)) if id == KeyId::from_raw(3)));
+    }
+
+    #[test]
+    fn format_errors() {
+        let format = |json| match parse(json) {
+            Err(Error::Format(msg)) => msg,
+            res => panic!("unexpected result: {:?}", res),
+        };
+
+        assert_eq!(format("{}"), "layout is not an array");
+        assert_eq!(format(r#"[["0"], {}]"#), "row is not an array");
+        assert_eq!(format("[[1]]"), "key is not a string or an object");
+        assert_eq!(
+            format(r#"[[{"w": "2"}, "0"]]"#),
+            "key property is not a number"
+        );
+        assert_eq!(
+            format(r#"[["0", {"r": 10}, "1"]]"#),
+            "rotation can only be changed by the first key in a row"
+        );
+        assert!(matches!(parse("[["), Err(Error::Json(_))));
+    }
+}
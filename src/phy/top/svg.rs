@@ -0,0 +1,239 @@
+use std::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use crate::{
+    keymap::Action,
+    phy::{
+        top::{KeyPos, Polygon, Repr, Shape},
+        KeyId,
+    },
+    proto::KeyCode,
+};
+
+/// Size of `1u` in the drawing, in pixels.
+pub const UNIT: f32 = 54.;
+
+/// Space around keys, in key units.
+const MARGIN: f32 = 0.25;
+
+/// Renders keys of `repr` as SVG, labeling them with legends returned by
+/// `legend`.
+///
+/// Lines of legends are separated by `\n`. Keys for which `legend` returns
+/// `None` are drawn without a legend.
+pub fn render(repr: &Repr<'_>, mut legend: impl FnMut(KeyId) -> Option<String>) -> String {
+    let ((x0, y0), (x1, y1)) = repr.bounds().unwrap_or(((0., 0.), (0., 0.)));
+    let (x0, y0) = (x0 - MARGIN, y0 - MARGIN);
+    let (width, height) = (x1 - x0 + MARGIN, y1 - y0 + MARGIN);
+
+    let mut out = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\" width=\"{}\" \
+         height=\"{}\">\n",
+        x0 * UNIT,
+        y0 * UNIT,
+        width * UNIT,
+        height * UNIT,
+        width * UNIT,
+        height * UNIT,
+    );
+
+    for key in repr.keys {
+        out += &polygon(&key.outline(), "#eeeeee");
+
+        if let Shape::Stepped { step } = key.shape {
+            let step = KeyPos {
+                x: key.x - key.width / 2. + step / 2.,
+                width: step,
+                shape: Shape::Rect,
+                ..*key
+            };
+
+            out += &polygon(&step.outline(), "#ffffff");
+        }
+
+        if let Some(legend) = legend(key.id) {
+            out += &text(key, &legend);
+        }
+    }
+
+    out += "</svg>\n";
+    out
+}
+
+/// Renders keys of `repr` as SVG, labeling them with actions of a keymap
+/// layer.
+///
+/// `layer` is indexed by raw [`KeyId`]s. Key codes are labeled with their
+/// [legends](KeyCode::legend), [`Action::Transparent`] with `▽` and layer
+/// actions with the index of the layer, e.g. `MO(1)` or `TG(2)`. Keys with
+/// both a tap and a hold action have two lines: the legend of the tap key
+/// code, then the legend of the hold one or `LT(1)` for [`Action::LayerTap`].
+/// Keys outside of `layer` and keys with [`Action::No`] or [`KeyCode::No`] are
+/// drawn without a legend.
+pub fn render_layer(repr: &Repr<'_>, layer: &[Action]) -> String {
+    render(repr, |id| {
+        layer
+            .get(usize::from(id.into_raw()))
+            .and_then(|&action| legend(action))
+    })
+}
+
+fn legend(action: Action) -> Option<String> {
+    let legend = match action {
+        Action::No | Action::Key(KeyCode::No) => return None,
+        Action::Transparent => "\u{25bd}".to_string(),
+        Action::Key(kc) => kc.legend().to_string(),
+        Action::Momentary(layer) => format!("MO({})", layer),
+        Action::Toggle(layer) => format!("TG({})", layer),
+        Action::LayerTap { layer, tap } => format!("{}\nLT({})", tap.legend(), layer),
+        Action::TapHold { tap, hold } => format!("{}\n{}", tap.legend(), hold.legend()),
+    };
+
+    Some(legend)
+}
+
+fn polygon(polygon: &Polygon, fill: &str) -> String {
+    let points = polygon
+        .points()
+        .iter()
+        .map(|(x, y)| format!("{},{}", x * UNIT, y * UNIT))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    format!(
+        "  <polygon points=\"{}\" fill=\"{}\" stroke=\"#333333\" stroke-width=\"2\" />\n",
+        points, fill,
+    )
+}
+
+fn text(key: &KeyPos, legend: &str) -> String {
+    let (x, y) = key.centre();
+    let (x, y) = (x * UNIT, y * UNIT);
+    let lines = legend.lines().count() as f32;
+
+    let mut out = format!(
+        "  <text x=\"{}\" y=\"{}\" transform=\"rotate({} {} {})\" font-family=\"sans-serif\" \
+         font-size=\"12\" text-anchor=\"middle\" dominant-baseline=\"middle\">\n",
+        x,
+        y,
+        key.rotation_rad.to_degrees(),
+        x,
+        y,
+    );
+
+    for (i, line) in legend.lines().enumerate() {
+        // Centre the lines vertically
+        let dy = if i == 0 { (1. - lines) / 2. } else { 1. };
+
+        out += &format!(
+            "    <tspan x=\"{}\" dy=\"{}em\">{}</tspan>\n",
+            x,
+            dy,
+            escape(line)
+        );
+    }
+
+    out += "  </text>\n";
+    out
+}
+
+/// Escapes characters that have special meaning in XML.
+fn escape(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '&' => "&amp;".to_string(),
+            '<' => "&lt;".to_string(),
+            '>' => "&gt;".to_string(),
+            '"' => "&quot;".to_string(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use core::f32::consts::FRAC_PI_2;
+    use std::string::ToString;
+
+    use super::{render, render_layer};
+    use crate::{
+        keymap::Action,
+        phy::{
+            top::{KeyPos, Repr},
+            KeyId,
+        },
+        proto::KeyCode,
+    };
+
+    const KEYS: [KeyPos; 2] = [
+        KeyPos::new(KeyId::from_raw(0), 0.5, 0.5),
+        KeyPos::new(KeyId::from_raw(1), 1.5, 0.5).rotate(FRAC_PI_2, (1.5, 0.5)),
+    ];
+
+    const REPR: Repr<'static> = Repr {
+        keys: &KEYS,
+        centre: (1., 0.5),
+    };
+
+    #[test]
+    fn keys() {
+        let svg = render(&REPR, |id| Some(id.into_raw().to_string()));
+
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("<polygon").count(), 2);
+
+        // The legend of the rotated key is rotated around its centre
+        assert!(svg.contains("<text x=\"27\" y=\"27\" transform=\"rotate(0 27 27)\""));
+        assert!(svg.contains("<text x=\"81\" y=\"27\" transform=\"rotate(90 81 27)\""));
+        assert!(svg.contains("<tspan x=\"81\" dy=\"0em\">1</tspan>"));
+    }
+
+    #[test]
+    fn layer() {
+        let layer = [
+            Action::LayerTap {
+                layer: 1,
+                tap: KeyCode::Space,
+            },
+            Action::TapHold {
+                tap: KeyCode::A,
+                hold: KeyCode::LCtrl,
+            },
+        ];
+        let svg = render_layer(&REPR, &layer);
+
+        // Two lines, centred vertically
+        assert!(svg.contains(concat!(
+            "<tspan x=\"27\" dy=\"-0.5em\">Space</tspan>\n",
+            "    <tspan x=\"27\" dy=\"1em\">LT(1)</tspan>\n",
+        )));
+        assert!(svg.contains(concat!(
+            "<tspan x=\"81\" dy=\"-0.5em\">A</tspan>\n",
+            "    <tspan x=\"81\" dy=\"1em\">Ctrl</tspan>\n",
+        )));
+
+        // Keys without actions and keys outside of the layer have no legend
+        let svg = render_layer(&REPR, &[Action::No]);
+        assert_eq!(svg.matches("<text").count(), 0);
+        let svg = render_layer(&REPR, &[Action::Key(KeyCode::No), Action::Momentary(2)]);
+        assert_eq!(svg.matches("<text").count(), 1);
+        assert!(svg.contains(">MO(2)</tspan>"));
+    }
+
+    #[test]
+    fn escape() {
+        let legends = ["<", "&\n\""];
+        let svg = render(&REPR, |id| {
+            Some(legends[usize::from(id.into_raw())].to_string())
+        });
+
+        assert!(svg.contains(">&lt;</tspan>"));
+        assert!(svg.contains(">&amp;</tspan>"));
+        assert!(svg.contains(">&quot;</tspan>"));
+        assert!(!svg.contains("><</tspan>"));
+    }
+}
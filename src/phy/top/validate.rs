@@ -0,0 +1,391 @@
+use crate::phy::{
+    top::{fixed, KeyPos, Polygon, Repr, Shape},
+    KeyId, Layout,
+};
+
+/// How much outlines of keys are shrunk before checking for overlaps, so that
+/// keys which only touch (and rounding errors) are not reported.
+const TOLERANCE: f32 = 0.02;
+
+/// A problem of a topological representation.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Problem {
+    /// Several keys have the same id.
+    DuplicateId(KeyId),
+    /// Id of a key is not less than `max_key_id`.
+    OutOfRange(KeyId),
+    /// There is no key with this id, although it's less than `max_key_id`.
+    MissingId(KeyId),
+    /// Footprints of two keys overlap.
+    Overlap(KeyId, KeyId),
+}
+
+/// Body of [`check_ids`] and [`check_fixed_ids`], `const fn`s can't be generic
+/// over the representation.
+macro_rules! check_ids_impl {
+    ($keys:expr, $max_key_id:expr) => {{
+        let keys = $keys;
+        let max = $max_key_id.into_raw();
+        let mut i = 0;
+
+        while i < keys.len() {
+            let id = keys[i].id;
+
+            if id.into_raw() >= max {
+                return Err(Problem::OutOfRange(id));
+            }
+
+            let mut j = i + 1;
+            while j < keys.len() {
+                if keys[j].id.into_raw() == id.into_raw() {
+                    return Err(Problem::DuplicateId(id));
+                }
+
+                j += 1;
+            }
+
+            i += 1;
+        }
+
+        // All ids are unique and in range, so there are no missing ones if
+        // there are enough of them
+        if keys.len() < max as usize {
+            let mut id = 0;
+            while id < max {
+                let mut k = 0;
+                while k < keys.len() && keys[k].id.into_raw() != id {
+                    k += 1;
+                }
+
+                if k == keys.len() {
+                    return Err(Problem::MissingId(KeyId::from_raw(id)));
+                }
+
+                id += 1;
+            }
+        }
+
+        Ok(())
+    }};
+}
+
+/// Checks [`KeyId`]s of `keys` against `max_key_id`, returns the first
+/// problem found.
+///
+/// Unlike [`validate`], this is a `const fn`, so it can be used in a `const`
+/// assertion for a static representation. Overlaps are not checked. See
+/// [`check_fixed_ids`] for the fixed-point representation.
+pub const fn check_ids(keys: &[KeyPos], max_key_id: KeyId) -> Result<(), Problem> {
+    check_ids_impl!(keys, max_key_id)
+}
+
+/// Same as [`check_ids`], but for the [fixed-point](fixed) representation.
+pub const fn check_fixed_ids(keys: &[fixed::KeyPos], max_key_id: KeyId) -> Result<(), Problem> {
+    check_ids_impl!(keys, max_key_id)
+}
+
+/// Checks `repr` against `max_key_id`, calling `f` with every problem found.
+pub fn validate(repr: &Repr<'_>, max_key_id: KeyId, f: &mut dyn FnMut(Problem)) {
+    validate_keys(repr.keys.len(), &|i| repr.keys[i], max_key_id, f)
+}
+
+/// Same as [`validate`], but for the [fixed-point](fixed) representation.
+///
+/// Keys are converted to floating point to check for overlaps.
+pub fn validate_fixed(repr: &fixed::Repr<'_>, max_key_id: KeyId, f: &mut dyn FnMut(Problem)) {
+    validate_keys(repr.keys.len(), &|i| repr.keys[i].to_f32(), max_key_id, f)
+}
+
+/// Body of [`validate`] and [`validate_fixed`], `key(i)` returns the `i`th of
+/// `len` keys.
+fn validate_keys(
+    len: usize,
+    key: &dyn Fn(usize) -> KeyPos,
+    max_key_id: KeyId,
+    f: &mut dyn FnMut(Problem),
+) {
+    for i in 0..len {
+        let a = key(i);
+
+        if a.id >= max_key_id {
+            f(Problem::OutOfRange(a.id));
+        }
+
+        // Report every duplicate id once, at its first key
+        let first = !(0..i).any(|j| key(j).id == a.id);
+        if first && (i + 1..len).any(|j| key(j).id == a.id) {
+            f(Problem::DuplicateId(a.id));
+        }
+
+        for b in (i + 1..len).map(key) {
+            if overlap(&a, &b) {
+                f(Problem::Overlap(a.id, b.id));
+            }
+        }
+    }
+
+    (0..max_key_id.into_raw())
+        .map(KeyId::from_raw)
+        .filter(|&id| !(0..len).any(|i| key(i).id == id))
+        .for_each(|id| f(Problem::MissingId(id)));
+}
+
+/// Checks [`Layout::topological_repr`] of `layout` against its
+/// [`Layout::max_key_id`], calling `f` with every problem found.
+///
+/// If the layout only has a [`Layout::fixed_repr`], that is checked instead.
+/// Does nothing if the layout doesn't have either representation.
+pub fn validate_layout<L>(layout: &L, f: &mut dyn FnMut(Problem))
+where
+    L: Layout + ?Sized,
+{
+    if let Some(repr) = layout.topological_repr() {
+        validate(&repr, layout.max_key_id(), f);
+    } else if let Some(repr) = layout.fixed_repr() {
+        validate_fixed(&repr, layout.max_key_id(), f);
+    }
+}
+
+/// Returns `true` if footprints of two keys overlap.
+fn overlap(a: &KeyPos, b: &KeyPos) -> bool {
+    let (a, b) = (parts(a), parts(b));
+
+    a.iter().flatten().any(|a| {
+        b.iter()
+            .flatten()
+            .any(|b| !separated(&a.outline(), &b.outline()))
+    })
+}
+
+/// Splits the footprint of a key into (up to two) rectangles, shrunk by
+/// [`TOLERANCE`].
+fn parts(key: &KeyPos) -> [Option<KeyPos>; 2] {
+    let rect = KeyPos {
+        width: key.width - TOLERANCE,
+        height: key.height - TOLERANCE,
+        shape: Shape::Rect,
+        ..*key
+    };
+
+    match key.shape {
+        Shape::Rect | Shape::Stepped { .. } => [Some(rect), None],
+        Shape::IsoEnter => {
+            let top = KeyPos {
+                y: rect.y - rect.height / 4.,
+                height: rect.height / 2.,
+                ..rect
+            };
+            let bottom = KeyPos {
+                x: rect.x + rect.width / 12.,
+                y: rect.y + rect.height / 4.,
+                width: rect.width * 5. / 6.,
+                ..top
+            };
+
+            [Some(top), Some(bottom)]
+        }
+    }
+}
+
+/// Returns `true` if there is a line separating two convex polygons.
+fn separated(a: &Polygon, b: &Polygon) -> bool {
+    let project = |polygon: &Polygon, (nx, ny): (f32, f32)| {
+        polygon
+            .points()
+            .iter()
+            .map(|(x, y)| x * nx + y * ny)
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), p| {
+                (min.min(p), max.max(p))
+            })
+    };
+
+    // Normals of edges of both polygons are the only candidate axes
+    [a, b].iter().any(|polygon| {
+        let points = polygon.points();
+
+        points
+            .iter()
+            .zip(points.iter().cycle().skip(1))
+            .any(|(p, q)| {
+                let normal = (q.1 - p.1, p.0 - q.0);
+                let ((min_a, max_a), (min_b, max_b)) = (project(a, normal), project(b, normal));
+
+                max_a <= min_b || max_b <= min_a
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use core::f32::consts::FRAC_PI_4;
+    use std::{vec, vec::Vec};
+
+    use super::{check_fixed_ids, check_ids, validate, validate_layout, Problem};
+    use crate::phy::{
+        top::{
+            fixed::{self, Units},
+            KeyPos, Repr, Shape,
+        },
+        KeyId, Layout,
+    };
+
+    fn key(id: u16, x: f32) -> KeyPos {
+        KeyPos::new(KeyId::from_raw(id), x, 0.)
+    }
+
+    fn fixed_key(id: u16, x: f32) -> fixed::KeyPos {
+        fixed::KeyPos::new(KeyId::from_raw(id), Units::from_f32(x), Units(0))
+    }
+
+    #[test]
+    fn duplicate_reported_once() {
+        let keys = [key(0, 0.), key(0, 1.), key(1, 2.), key(0, 3.)];
+        let repr = Repr {
+            keys: &keys,
+            centre: (0., 0.),
+        };
+
+        let mut duplicates = 0;
+        validate(&repr, KeyId::from_raw(2), &mut |problem| match problem {
+            Problem::DuplicateId(id) if id == KeyId::from_raw(0) => duplicates += 1,
+            problem => panic!("unexpected problem: {:?}", problem),
+        });
+
+        assert_eq!(duplicates, 1);
+    }
+
+    #[test]
+    fn ids() {
+        let max = KeyId::from_raw(3);
+
+        for (keys, fixed, expected) in [
+            (
+                [key(0, 0.), key(1, 1.), key(2, 2.)],
+                [fixed_key(0, 0.), fixed_key(1, 1.), fixed_key(2, 2.)],
+                Ok(()),
+            ),
+            (
+                [key(0, 0.), key(2, 1.), key(2, 2.)],
+                [fixed_key(0, 0.), fixed_key(2, 1.), fixed_key(2, 2.)],
+                Err(Problem::DuplicateId(KeyId::from_raw(2))),
+            ),
+            (
+                [key(0, 0.), key(1, 1.), key(3, 2.)],
+                [fixed_key(0, 0.), fixed_key(1, 1.), fixed_key(3, 2.)],
+                Err(Problem::OutOfRange(KeyId::from_raw(3))),
+            ),
+        ] {
+            assert_eq!(check_ids(&keys, max), expected);
+            assert_eq!(check_fixed_ids(&fixed, max), expected);
+        }
+
+        assert_eq!(
+            check_fixed_ids(&[fixed_key(0, 0.), fixed_key(2, 1.)], max),
+            Err(Problem::MissingId(KeyId::from_raw(1))),
+        );
+    }
+
+    /// Returns pairs of overlapping keys of `keys`, panicking on other
+    /// problems.
+    fn overlaps(keys: &[KeyPos]) -> Vec<(u16, u16)> {
+        let repr = Repr {
+            keys,
+            centre: (0., 0.),
+        };
+
+        let mut overlaps = Vec::new();
+        validate(
+            &repr,
+            KeyId::from_raw(keys.len() as _),
+            &mut |problem| match problem {
+                Problem::Overlap(a, b) => overlaps.push((a.into_raw(), b.into_raw())),
+                problem => panic!("unexpected problem: {:?}", problem),
+            },
+        );
+
+        overlaps
+    }
+
+    #[test]
+    fn overlap() {
+        // Keys which only touch (up to rounding errors) don't overlap
+        assert_eq!(overlaps(&[key(0, 0.), key(1, 1.), key(2, 2.)]), []);
+        assert_eq!(overlaps(&[key(0, 0.), key(1, 0.99), key(2, 2.)]), []);
+        assert_eq!(overlaps(&[key(0, 0.), key(1, 0.5), key(2, 2.)]), [(0, 1)]);
+        assert_eq!(
+            overlaps(&[key(0, 0.), key(1, 0.), key(2, 0.9)]),
+            [(0, 1), (0, 2), (1, 2)]
+        );
+    }
+
+    #[test]
+    fn rotated_overlap() {
+        // Corners of a key rotated by 45° stick out by `sqrt(2)/2 - 1/2`
+        let rotated = |x: f32| key(1, x).rotate(FRAC_PI_4, (x, 0.));
+
+        assert_eq!(
+            overlaps(&[key(0, 0.), key(1, 1.).rotate(FRAC_PI_4, (0., 0.))]),
+            [(0, 1)]
+        );
+        assert_eq!(overlaps(&[key(0, 0.), rotated(1.)]), [(0, 1)]);
+        assert_eq!(overlaps(&[key(0, 0.), rotated(1.15)]), [(0, 1)]);
+        assert_eq!(overlaps(&[key(0, 0.), rotated(1.25)]), []);
+    }
+
+    #[test]
+    fn iso_enter_overlap() {
+        // Spans `x` from 1 to 2.5, its bottom half starts at 1.25
+        let enter = KeyPos::new(KeyId::from_raw(0), 1.75, 1.)
+            .size(1.5, 2.)
+            .shape(Shape::IsoEnter);
+        let key = |x: f32, y: f32| KeyPos::new(KeyId::from_raw(1), x, y);
+
+        // Next to the bottom half, in the notch
+        assert_eq!(overlaps(&[enter, key(0.75, 1.5)]), []);
+        assert_eq!(overlaps(&[enter, key(0.75, 1.5).size(0.5, 1.)]), []);
+        assert_eq!(overlaps(&[enter, key(1.125, 1.5).size(0.25, 1.)]), []);
+
+        // Sticking out of the notch
+        assert_eq!(overlaps(&[enter, key(0.9, 1.5)]), [(0, 1)]);
+        assert_eq!(overlaps(&[enter, key(0.75, 1.35)]), [(0, 1)]);
+        assert_eq!(overlaps(&[enter, key(1.125, 1.5).size(0.5, 1.)]), [(0, 1)]);
+    }
+
+    /// Layout with only a fixed-point representation.
+    struct Fixed;
+
+    impl Layout for Fixed {
+        fn poll(&mut self, _: &mut dyn FnMut(&mut dyn Iterator<Item = KeyId>)) {}
+
+        fn max_key_id(&self) -> KeyId {
+            KeyId::from_raw(4)
+        }
+
+        fn fixed_repr(&self) -> Option<fixed::Repr<'_>> {
+            const KEYS: [fixed::KeyPos; 3] = [
+                fixed::KeyPos::new(KeyId::from_raw(0), Units(0), Units(0)),
+                fixed::KeyPos::new(KeyId::from_raw(1), Units(128), Units(0)),
+                fixed::KeyPos::new(KeyId::from_raw(2), Units(512), Units(0)),
+            ];
+
+            Some(fixed::Repr {
+                keys: &KEYS,
+                centre: (Units(256), Units(0)),
+            })
+        }
+    }
+
+    #[test]
+    fn fixed_layout() {
+        let mut problems = Vec::new();
+        validate_layout(&Fixed, &mut |problem| problems.push(problem));
+
+        assert_eq!(
+            problems,
+            vec![
+                Problem::Overlap(KeyId::from_raw(0), KeyId::from_raw(1)),
+                Problem::MissingId(KeyId::from_raw(3)),
+            ]
+        );
+    }
+}
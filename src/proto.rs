@@ -2,7 +2,7 @@ mod kc;
 mod leds;
 pub mod usb;
 
-pub use kc::KeyCode;
+pub use kc::{KeyCode, UnknownKeyCode, UsagePage};
 pub use leds::{LedState, LedStates};
 
 /// A protocol that sends information about pressed keys to the host (computer).
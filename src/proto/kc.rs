@@ -1,3 +1,5 @@
+use core::{fmt, str::FromStr};
+
 /// Define a key code according to the HID specification.  Their names
 /// correspond to the american QWERTY layout.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, enumn::N)]
@@ -255,6 +257,20 @@ pub enum KeyCode {
     MediaCalc, // 0xFB
 }
 
+/// HID usage page of a [`KeyCode`], see [`KeyCode::usage_page`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[repr(u16)]
+pub enum UsagePage {
+    /// The keyboard/keypad page.
+    Keyboard = 0x07,
+    /// The consumer page, media keys.
+    Consumer = 0x0C,
+}
+
+/// Error of parsing a [`KeyCode`], the name is unknown.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct UnknownKeyCode;
+
 impl KeyCode {
     /// Testing utility: given an ASCII character code returns a key code you
     /// need to press to type this character (assuming QWERTY layout) alongside
@@ -272,4 +288,527 @@ impl KeyCode {
 
         Some((code, false))
     }
+
+    /// Returns the key code with the given name, either the canonical one
+    /// (see [`KeyCode::name`]) or a common alias (e.g. `"Backspace"` for
+    /// [`KeyCode::BSpace`] or `"Semicolon"` for [`KeyCode::SColon`]).
+    pub const fn from_name(name: &str) -> Option<Self> {
+        match find(&Self::NAMES, name) {
+            Some(kc) => Some(kc),
+            None => find(&Self::ALIASES, name),
+        }
+    }
+
+    /// Returns the canonical name of the key code, the same as the name of
+    /// the variant (e.g. `"A"`, `"Kb1"` or `"LShift"`).
+    pub const fn name(self) -> &'static str {
+        let mut i = 0;
+        while i < Self::NAMES.len() {
+            let (name, kc) = Self::NAMES[i];
+            if kc as u8 == self as u8 {
+                return name;
+            }
+
+            i += 1;
+        }
+
+        // All variants are in `NAMES`
+        unreachable!()
+    }
+
+    /// Returns the HID usage page of the key code.
+    ///
+    /// Media keys are reported as unofficial keyboard usages (which some hosts
+    /// understand), but correspond to the consumer page.
+    pub const fn usage_page(self) -> UsagePage {
+        if self as u8 >= Self::MediaPlayPause as u8 {
+            UsagePage::Consumer
+        } else {
+            UsagePage::Keyboard
+        }
+    }
+
+    /// Returns the HID usage ID of the key code on its
+    /// [usage page](KeyCode::usage_page).
+    pub const fn usage_id(self) -> u16 {
+        match self {
+            Self::MediaPlayPause => 0xCD,
+            Self::MediaStopCD => 0xB7,
+            Self::MediaPreviousSong => 0xB6,
+            Self::MediaNextSong => 0xB5,
+            Self::MediaEjectCD => 0xB8,
+            Self::MediaVolUp => 0xE9,
+            Self::MediaVolDown => 0xEA,
+            Self::MediaMute => 0xE2,
+            Self::MediaWWW => 0x196,
+            Self::MediaBack => 0x224,
+            Self::MediaForward => 0x225,
+            Self::MediaStop => 0x226,
+            Self::MediaFind => 0x221,
+            Self::MediaScrollUp => 0x233,
+            Self::MediaScrollDown => 0x234,
+            Self::MediaEdit => 0x185,
+            Self::MediaSleep => 0x32,
+            Self::MediaCoffee => 0x19E,
+            Self::MediaRefresh => 0x227,
+            Self::MediaCalc => 0x192,
+            kc => kc as u16,
+        }
+    }
+
+    /// Returns `true` for the modifiers: ctrl, shift, alt and gui, both left
+    /// and right.
+    pub const fn is_modifier(self) -> bool {
+        self as u8 >= Self::LCtrl as u8 && self as u8 <= Self::RGui as u8
+    }
+
+    /// Returns a short legend of the key code, for drawing it on a key (e.g.
+    /// `"1"` for [`KeyCode::Kb1`] or `"Bksp"` for [`KeyCode::BSpace`]).
+    ///
+    /// The legend of [`KeyCode::No`] is empty.
+    pub const fn legend(self) -> &'static str {
+        match self {
+            Self::No => "",
+            Self::Kb1 | Self::Kp1 => "1",
+            Self::Kb2 | Self::Kp2 => "2",
+            Self::Kb3 | Self::Kp3 => "3",
+            Self::Kb4 | Self::Kp4 => "4",
+            Self::Kb5 | Self::Kp5 => "5",
+            Self::Kb6 | Self::Kp6 => "6",
+            Self::Kb7 | Self::Kp7 => "7",
+            Self::Kb8 | Self::Kp8 => "8",
+            Self::Kb9 | Self::Kp9 => "9",
+            Self::Kb0 | Self::Kp0 => "0",
+            Self::Enter | Self::KpEnter => "Enter",
+            Self::Escape => "Esc",
+            Self::BSpace => "Bksp",
+            Self::Space => "Space",
+            Self::Minus | Self::KpMinus => "-",
+            Self::Equal | Self::KpEqual | Self::KpEqualSign => "=",
+            Self::LBracket => "[",
+            Self::RBracket => "]",
+            Self::Bslash | Self::NonUsBslash => "\\",
+            Self::NonUsHash => "#",
+            Self::SColon => ";",
+            Self::Quote => "'",
+            Self::Grave => "`",
+            Self::Comma | Self::KpComma => ",",
+            Self::Dot | Self::KpDot => ".",
+            Self::Slash | Self::KpSlash => "/",
+            Self::KpAsterisk => "*",
+            Self::KpPlus => "+",
+            Self::CapsLock | Self::LockingCapsLock => "Caps",
+            Self::PScreen => "PrtSc",
+            Self::ScrollLock | Self::LockingScrollLock => "ScrLk",
+            Self::NumLock | Self::LockingNumLock => "NumLk",
+            Self::Insert => "Ins",
+            Self::Delete => "Del",
+            Self::PgDown => "PgDn",
+            Self::Right => "\u{2192}",
+            Self::Left => "\u{2190}",
+            Self::Down => "\u{2193}",
+            Self::Up => "\u{2191}",
+            Self::Application => "Menu",
+            Self::LCtrl | Self::RCtrl => "Ctrl",
+            Self::LShift | Self::RShift => "Shift",
+            Self::LAlt | Self::RAlt => "Alt",
+            Self::LGui | Self::RGui => "Gui",
+            Self::VolUp | Self::MediaVolUp => "Vol+",
+            Self::VolDown | Self::MediaVolDown => "Vol-",
+            Self::Mute | Self::MediaMute => "Mute",
+            Self::MediaPlayPause => "Play",
+            Self::MediaStopCD | Self::MediaStop => "Stop",
+            Self::MediaPreviousSong => "Prev",
+            Self::MediaNextSong => "Next",
+            Self::MediaEjectCD => "Eject",
+            Self::MediaWWW => "WWW",
+            Self::MediaBack => "Back",
+            Self::MediaForward => "Fwd",
+            Self::MediaFind => "Find",
+            Self::MediaScrollUp => "ScrUp",
+            Self::MediaScrollDown => "ScrDn",
+            Self::MediaEdit => "Edit",
+            Self::MediaSleep => "Sleep",
+            Self::MediaCoffee => "Lock",
+            Self::MediaRefresh => "Rfrsh",
+            Self::MediaCalc => "Calc",
+            kc => kc.name(),
+        }
+    }
+
+    /// Common alternative names of key codes, accepted by
+    /// [`KeyCode::from_name`].
+    const ALIASES: [(&'static str, Self); 17] = [
+        ("Esc", Self::Escape),
+        ("Backspace", Self::BSpace),
+        ("Backslash", Self::Bslash),
+        ("Semicolon", Self::SColon),
+        ("Apostrophe", Self::Quote),
+        ("Period", Self::Dot),
+        ("Caps", Self::CapsLock),
+        ("PrintScreen", Self::PScreen),
+        ("Ins", Self::Insert),
+        ("Del", Self::Delete),
+        ("PageUp", Self::PgUp),
+        ("PageDown", Self::PgDown),
+        ("LControl", Self::LCtrl),
+        ("RControl", Self::RCtrl),
+        ("LWin", Self::LGui),
+        ("RWin", Self::RGui),
+        ("Backtick", Self::Grave),
+    ];
+
+    const NAMES: [(&'static str, Self); 193] = [
+        ("No", Self::No),
+        ("ErrorRollOver", Self::ErrorRollOver),
+        ("PostFail", Self::PostFail),
+        ("ErrorUndefined", Self::ErrorUndefined),
+        ("A", Self::A),
+        ("B", Self::B),
+        ("C", Self::C),
+        ("D", Self::D),
+        ("E", Self::E),
+        ("F", Self::F),
+        ("G", Self::G),
+        ("H", Self::H),
+        ("I", Self::I),
+        ("J", Self::J),
+        ("K", Self::K),
+        ("L", Self::L),
+        ("M", Self::M),
+        ("N", Self::N),
+        ("O", Self::O),
+        ("P", Self::P),
+        ("Q", Self::Q),
+        ("R", Self::R),
+        ("S", Self::S),
+        ("T", Self::T),
+        ("U", Self::U),
+        ("V", Self::V),
+        ("W", Self::W),
+        ("X", Self::X),
+        ("Y", Self::Y),
+        ("Z", Self::Z),
+        ("Kb1", Self::Kb1),
+        ("Kb2", Self::Kb2),
+        ("Kb3", Self::Kb3),
+        ("Kb4", Self::Kb4),
+        ("Kb5", Self::Kb5),
+        ("Kb6", Self::Kb6),
+        ("Kb7", Self::Kb7),
+        ("Kb8", Self::Kb8),
+        ("Kb9", Self::Kb9),
+        ("Kb0", Self::Kb0),
+        ("Enter", Self::Enter),
+        ("Escape", Self::Escape),
+        ("BSpace", Self::BSpace),
+        ("Tab", Self::Tab),
+        ("Space", Self::Space),
+        ("Minus", Self::Minus),
+        ("Equal", Self::Equal),
+        ("LBracket", Self::LBracket),
+        ("RBracket", Self::RBracket),
+        ("Bslash", Self::Bslash),
+        ("NonUsHash", Self::NonUsHash),
+        ("SColon", Self::SColon),
+        ("Quote", Self::Quote),
+        ("Grave", Self::Grave),
+        ("Comma", Self::Comma),
+        ("Dot", Self::Dot),
+        ("Slash", Self::Slash),
+        ("CapsLock", Self::CapsLock),
+        ("F1", Self::F1),
+        ("F2", Self::F2),
+        ("F3", Self::F3),
+        ("F4", Self::F4),
+        ("F5", Self::F5),
+        ("F6", Self::F6),
+        ("F7", Self::F7),
+        ("F8", Self::F8),
+        ("F9", Self::F9),
+        ("F10", Self::F10),
+        ("F11", Self::F11),
+        ("F12", Self::F12),
+        ("PScreen", Self::PScreen),
+        ("ScrollLock", Self::ScrollLock),
+        ("Pause", Self::Pause),
+        ("Insert", Self::Insert),
+        ("Home", Self::Home),
+        ("PgUp", Self::PgUp),
+        ("Delete", Self::Delete),
+        ("End", Self::End),
+        ("PgDown", Self::PgDown),
+        ("Right", Self::Right),
+        ("Left", Self::Left),
+        ("Down", Self::Down),
+        ("Up", Self::Up),
+        ("NumLock", Self::NumLock),
+        ("KpSlash", Self::KpSlash),
+        ("KpAsterisk", Self::KpAsterisk),
+        ("KpMinus", Self::KpMinus),
+        ("KpPlus", Self::KpPlus),
+        ("KpEnter", Self::KpEnter),
+        ("Kp1", Self::Kp1),
+        ("Kp2", Self::Kp2),
+        ("Kp3", Self::Kp3),
+        ("Kp4", Self::Kp4),
+        ("Kp5", Self::Kp5),
+        ("Kp6", Self::Kp6),
+        ("Kp7", Self::Kp7),
+        ("Kp8", Self::Kp8),
+        ("Kp9", Self::Kp9),
+        ("Kp0", Self::Kp0),
+        ("KpDot", Self::KpDot),
+        ("NonUsBslash", Self::NonUsBslash),
+        ("Application", Self::Application),
+        ("Power", Self::Power),
+        ("KpEqual", Self::KpEqual),
+        ("F13", Self::F13),
+        ("F14", Self::F14),
+        ("F15", Self::F15),
+        ("F16", Self::F16),
+        ("F17", Self::F17),
+        ("F18", Self::F18),
+        ("F19", Self::F19),
+        ("F20", Self::F20),
+        ("F21", Self::F21),
+        ("F22", Self::F22),
+        ("F23", Self::F23),
+        ("F24", Self::F24),
+        ("Execute", Self::Execute),
+        ("Help", Self::Help),
+        ("Menu", Self::Menu),
+        ("Select", Self::Select),
+        ("Stop", Self::Stop),
+        ("Again", Self::Again),
+        ("Undo", Self::Undo),
+        ("Cut", Self::Cut),
+        ("Copy", Self::Copy),
+        ("Paste", Self::Paste),
+        ("Find", Self::Find),
+        ("Mute", Self::Mute),
+        ("VolUp", Self::VolUp),
+        ("VolDown", Self::VolDown),
+        ("LockingCapsLock", Self::LockingCapsLock),
+        ("LockingNumLock", Self::LockingNumLock),
+        ("LockingScrollLock", Self::LockingScrollLock),
+        ("KpComma", Self::KpComma),
+        ("KpEqualSign", Self::KpEqualSign),
+        ("Intl1", Self::Intl1),
+        ("Intl2", Self::Intl2),
+        ("Intl3", Self::Intl3),
+        ("Intl4", Self::Intl4),
+        ("Intl5", Self::Intl5),
+        ("Intl6", Self::Intl6),
+        ("Intl7", Self::Intl7),
+        ("Intl8", Self::Intl8),
+        ("Intl9", Self::Intl9),
+        ("Lang1", Self::Lang1),
+        ("Lang2", Self::Lang2),
+        ("Lang3", Self::Lang3),
+        ("Lang4", Self::Lang4),
+        ("Lang5", Self::Lang5),
+        ("Lang6", Self::Lang6),
+        ("Lang7", Self::Lang7),
+        ("Lang8", Self::Lang8),
+        ("Lang9", Self::Lang9),
+        ("AltErase", Self::AltErase),
+        ("SysReq", Self::SysReq),
+        ("Cancel", Self::Cancel),
+        ("Clear", Self::Clear),
+        ("Prior", Self::Prior),
+        ("Return", Self::Return),
+        ("Separator", Self::Separator),
+        ("Out", Self::Out),
+        ("Oper", Self::Oper),
+        ("ClearAgain", Self::ClearAgain),
+        ("CrSel", Self::CrSel),
+        ("ExSel", Self::ExSel),
+        ("LCtrl", Self::LCtrl),
+        ("LShift", Self::LShift),
+        ("LAlt", Self::LAlt),
+        ("LGui", Self::LGui),
+        ("RCtrl", Self::RCtrl),
+        ("RShift", Self::RShift),
+        ("RAlt", Self::RAlt),
+        ("RGui", Self::RGui),
+        ("MediaPlayPause", Self::MediaPlayPause),
+        ("MediaStopCD", Self::MediaStopCD),
+        ("MediaPreviousSong", Self::MediaPreviousSong),
+        ("MediaNextSong", Self::MediaNextSong),
+        ("MediaEjectCD", Self::MediaEjectCD),
+        ("MediaVolUp", Self::MediaVolUp),
+        ("MediaVolDown", Self::MediaVolDown),
+        ("MediaMute", Self::MediaMute),
+        ("MediaWWW", Self::MediaWWW),
+        ("MediaBack", Self::MediaBack),
+        ("MediaForward", Self::MediaForward),
+        ("MediaStop", Self::MediaStop),
+        ("MediaFind", Self::MediaFind),
+        ("MediaScrollUp", Self::MediaScrollUp),
+        ("MediaScrollDown", Self::MediaScrollDown),
+        ("MediaEdit", Self::MediaEdit),
+        ("MediaSleep", Self::MediaSleep),
+        ("MediaCoffee", Self::MediaCoffee),
+        ("MediaRefresh", Self::MediaRefresh),
+        ("MediaCalc", Self::MediaCalc),
+    ];
+}
+
+/// Formats the canonical [name](KeyCode::name) of the key code.
+impl fmt::Display for KeyCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Parses a key code from its name, see [`KeyCode::from_name`].
+impl FromStr for KeyCode {
+    type Err = UnknownKeyCode;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_name(s).ok_or(UnknownKeyCode)
+    }
+}
+
+impl fmt::Display for UnknownKeyCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("unknown key code")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for UnknownKeyCode {}
+
+/// Returns the key code with the given name in `table`.
+const fn find(table: &[(&str, KeyCode)], name: &str) -> Option<KeyCode> {
+    let mut i = 0;
+    while i < table.len() {
+        let (n, kc) = table[i];
+        if eq(n.as_bytes(), name.as_bytes()) {
+            return Some(kc);
+        }
+
+        i += 1;
+    }
+
+    None
+}
+
+/// `const` version of `a == b`.
+const fn eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+
+        i += 1;
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::string::ToString;
+
+    use super::{KeyCode, UnknownKeyCode, UsagePage};
+
+    #[test]
+    fn names() {
+        for &(name, kc) in KeyCode::NAMES.iter() {
+            assert_eq!(kc.name(), name);
+            assert_eq!(kc.to_string(), name);
+            assert_eq!(name.parse(), Ok(kc));
+        }
+
+        for &(alias, kc) in KeyCode::ALIASES.iter() {
+            assert_eq!(alias.parse(), Ok(kc));
+            assert_ne!(kc.name(), alias);
+        }
+
+        assert_eq!("BSpace".parse(), Ok(KeyCode::BSpace));
+        assert_eq!("Backspace".parse(), Ok(KeyCode::BSpace));
+        assert_eq!("SColon".parse(), Ok(KeyCode::SColon));
+        assert_eq!("Semicolon".parse(), Ok(KeyCode::SColon));
+        assert_eq!(KeyCode::BSpace.to_string(), "BSpace");
+        assert_eq!(KeyCode::SColon.to_string(), "SColon");
+    }
+
+    #[test]
+    fn unknown() {
+        // Names are case sensitive
+        for name in ["a", "lshift", "BACKSPACE", "semicolon"] {
+            assert_eq!(name.parse::<KeyCode>(), Err(UnknownKeyCode));
+        }
+
+        for name in ["", "Kb10", "Shift", "A ", " A"] {
+            assert_eq!(KeyCode::from_name(name), None);
+            assert_eq!(name.parse::<KeyCode>(), Err(UnknownKeyCode));
+        }
+
+        assert_eq!(UnknownKeyCode.to_string(), "unknown key code");
+    }
+
+    #[test]
+    fn legend() {
+        let cases = [
+            (KeyCode::No, ""),
+            (KeyCode::A, "A"),
+            (KeyCode::Kb1, "1"),
+            (KeyCode::Kp1, "1"),
+            (KeyCode::BSpace, "Bksp"),
+            (KeyCode::SColon, ";"),
+            (KeyCode::Bslash, "\\"),
+            (KeyCode::Left, "\u{2190}"),
+            (KeyCode::RShift, "Shift"),
+            (KeyCode::F12, "F12"),
+            (KeyCode::MediaCoffee, "Lock"),
+        ];
+
+        for (kc, legend) in cases {
+            assert_eq!(kc.legend(), legend);
+        }
+    }
+
+    #[test]
+    fn modifiers() {
+        let modifiers = [
+            KeyCode::LCtrl,
+            KeyCode::LShift,
+            KeyCode::LAlt,
+            KeyCode::LGui,
+            KeyCode::RCtrl,
+            KeyCode::RShift,
+            KeyCode::RAlt,
+            KeyCode::RGui,
+        ];
+
+        for &(_, kc) in KeyCode::NAMES.iter() {
+            assert_eq!(kc.is_modifier(), modifiers.contains(&kc), "{}", kc);
+        }
+    }
+
+    #[test]
+    fn usage() {
+        let cases = [
+            (KeyCode::A, UsagePage::Keyboard, 0x04),
+            (KeyCode::RGui, UsagePage::Keyboard, 0xE7),
+            (KeyCode::MediaPlayPause, UsagePage::Consumer, 0xCD),
+            (KeyCode::MediaWWW, UsagePage::Consumer, 0x196),
+            (KeyCode::MediaEdit, UsagePage::Consumer, 0x185),
+            (KeyCode::MediaCoffee, UsagePage::Consumer, 0x19E),
+            (KeyCode::MediaCalc, UsagePage::Consumer, 0x192),
+        ];
+
+        for (kc, page, id) in cases {
+            assert_eq!(kc.usage_page(), page);
+            assert_eq!(kc.usage_id(), id);
+        }
+    }
 }
@@ -1,4 +1,4 @@
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct LedStates {
     pub num_lock: LedState,
     pub caps_lock: LedState,
@@ -13,6 +13,43 @@ pub enum LedState {
     Enabled,
 }
 
+impl LedStates {
+    /// Creates led states from a bitset in the order of HID led usages, i.e.
+    /// num lock is the least significant bit.
+    pub const fn from_bits(bits: u8) -> Self {
+        const fn f(bit: u8) -> LedState {
+            match bit {
+                0 => LedState::Disabled,
+                _ => LedState::Enabled,
+            }
+        }
+
+        Self {
+            num_lock: f(bits & 1 << 0),
+            caps_lock: f(bits & 1 << 1),
+            scroll_lock: f(bits & 1 << 2),
+            compose: f(bits & 1 << 3),
+            kana: f(bits & 1 << 4),
+        }
+    }
+
+    /// Returns led states as a bitset, see [`LedStates::from_bits`].
+    pub const fn to_bits(self) -> u8 {
+        self.num_lock as u8
+            | (self.caps_lock as u8) << 1
+            | (self.scroll_lock as u8) << 2
+            | (self.compose as u8) << 3
+            | (self.kana as u8) << 4
+    }
+}
+
+impl Default for LedStates {
+    /// All leds are disabled.
+    fn default() -> Self {
+        Self::from_bits(0)
+    }
+}
+
 impl LedState {
     pub fn enabled(self) -> bool {
         matches!(self, Self::Enabled)
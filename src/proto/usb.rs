@@ -1,6 +1,6 @@
 use usb_device::{class_prelude::*, Result};
 
-use crate::proto::{KeyCode, LedState, LedStates, Protocol, Report};
+use crate::proto::{KeyCode, LedStates, Protocol, Report};
 
 /// Version 1 implementation of the USB keyboard protocol.
 ///
@@ -80,19 +80,7 @@ impl<B: UsbBus> Protocol for UsbV1<'_, B> {
 
     #[inline(never)]
     fn leds(&self) -> LedStates {
-        let b = self.inner.leds.0;
-        let f = |i| match i {
-            0 => LedState::Disabled,
-            _ => LedState::Enabled,
-        };
-
-        LedStates {
-            num_lock: f(b & 1 << 0),
-            caps_lock: f(b & 1 << 1),
-            scroll_lock: f(b & 1 << 2),
-            compose: f(b & 1 << 3),
-            kana: f(b & 1 << 4),
-        }
+        LedStates::from_bits(self.inner.leds.0)
     }
 }
 
@@ -0,0 +1,56 @@
+use core::fmt;
+
+use embedded_hal::serial::{Read, Write};
+
+mod election;
+/// Framing of messages: `payload | crc16` is COBS-encoded, so that it doesn't
+/// contain zeroes, and surrounded by zero bytes.
+mod frame;
+mod half_duplex;
+mod message;
+mod remote;
+mod secondary;
+/// Simulated serial links, to run both halves on the host.
+#[cfg(any(test, feature = "std"))]
+pub mod sim;
+mod state;
+
+pub use election::{Election, Role};
+pub use half_duplex::{HalfDuplex, HalfDuplexError};
+pub use remote::Remote;
+pub use secondary::Secondary;
+pub use state::State;
+
+/// Maximum number of keys of the secondary half.
+pub const MAX_KEYS: usize = 256;
+
+/// Size of the bitset of keys.
+const KEY_BYTES: usize = MAX_KEYS / 8;
+
+/// Error of the split link.
+pub enum Error<S>
+where
+    S: Read<u8> + Write<u8>,
+{
+    /// Reading from the serial link failed.
+    Read(<S as Read<u8>>::Error),
+    /// Writing to the serial link failed.
+    Write(<S as Write<u8>>::Error),
+    /// The other half doesn't respond.
+    Timeout,
+}
+
+impl<S> fmt::Debug for Error<S>
+where
+    S: Read<u8> + Write<u8>,
+    <S as Read<u8>>::Error: fmt::Debug,
+    <S as Write<u8>>::Error: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Read(err) => f.debug_tuple("Read").field(err).finish(),
+            Self::Write(err) => f.debug_tuple("Write").field(err).finish(),
+            Self::Timeout => f.write_str("Timeout"),
+        }
+    }
+}
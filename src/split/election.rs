@@ -0,0 +1,192 @@
+use embedded_hal::serial::{Read, Write};
+
+use crate::split::{frame::Decoder, message::Message, Error};
+
+/// Role of a half of a split keyboard.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Role {
+    /// The half connected to the host, it drives the [`Protocol`] and polls
+    /// the other half via [`Remote`].
+    ///
+    /// [`Protocol`]: crate::proto::Protocol
+    /// [`Remote`]: super::Remote
+    Primary,
+    /// The half that is not connected to the host, it reports its keys to the
+    /// primary half via [`Secondary`].
+    ///
+    /// [`Secondary`]: super::Secondary
+    Secondary,
+}
+
+/// Decides at runtime which half is the primary one, so that both halves can
+/// run the same firmware.
+///
+/// The half that is connected to the host becomes the primary one. The
+/// firmware decides what "connected" means, usually it's either VBUS being
+/// present or the USB device being configured by the host. The half that
+/// receives a request of the primary half (i.e. a poll of its [`Remote`])
+/// before being connected to the host becomes the secondary one.
+///
+/// [`Election::poll`] should be called periodically until it returns a role,
+/// after which the serial link can be passed to [`Remote::new`] or
+/// [`Secondary::new`]. The role doesn't change after that. If both halves are
+/// connected to hosts, both become primary and their [`Remote`]s time out.
+///
+/// [`Remote`]: super::Remote
+/// [`Remote::new`]: super::Remote::new
+/// [`Secondary::new`]: super::Secondary::new
+pub struct Election<S> {
+    serial: S,
+    decoder: Decoder,
+    role: Option<Role>,
+}
+
+impl<S> Election<S> {
+    /// Starts the election on `serial`, the link to the other half.
+    pub fn new(serial: S) -> Self {
+        Self {
+            serial,
+            decoder: Decoder::new(),
+            role: None,
+        }
+    }
+
+    /// Returns the role of this half, if it's already decided.
+    pub fn role(&self) -> Option<Role> {
+        self.role
+    }
+
+    /// Returns the serial link.
+    pub fn into_inner(self) -> S {
+        self.serial
+    }
+}
+
+impl<S> Election<S>
+where
+    S: Read<u8> + Write<u8>,
+{
+    /// Tries to decide the role of this half, `host` is `true` if this half is
+    /// connected to the host.
+    pub fn poll(&mut self, host: bool) -> Result<Option<Role>, Error<S>> {
+        if self.role.is_some() {
+            return Ok(self.role);
+        }
+
+        if host {
+            self.role = Some(Role::Primary);
+            return Ok(self.role);
+        }
+
+        loop {
+            let byte = match self.serial.read() {
+                Ok(byte) => byte,
+                Err(nb::Error::WouldBlock) => return Ok(None),
+                Err(nb::Error::Other(err)) => return Err(Error::Read(err)),
+            };
+
+            let msg = self.decoder.push(byte).and_then(Message::decode);
+            if matches!(msg, Some(msg) if msg.is_request()) {
+                // The request is dropped, the primary half will retransmit it
+                self.role = Some(Role::Secondary);
+                return Ok(self.role);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_hal::serial::Write;
+
+    use super::{Election, Role};
+    use crate::split::{
+        message::Message,
+        sim::{End, Wire},
+        State,
+    };
+
+    /// Polls `election` as a half which is not connected to the host.
+    fn poll(election: &mut Election<End>) -> Option<Role> {
+        election.poll(false).unwrap()
+    }
+
+    #[test]
+    fn host() {
+        let wire = Wire::new();
+        let mut election = Election::new(wire.end());
+
+        assert_eq!(election.role(), None);
+        assert_eq!(election.poll(true).unwrap(), Some(Role::Primary));
+        assert_eq!(election.role(), Some(Role::Primary));
+    }
+
+    #[test]
+    fn requests() {
+        let requests = [
+            Message::Poll { seq: 1 },
+            Message::Sync {
+                seq: 2,
+                version: 1,
+                state: State::default(),
+            },
+        ];
+
+        for request in requests {
+            let wire = Wire::new();
+            let (mut election, mut primary) = (Election::new(wire.end()), wire.end());
+
+            assert_eq!(poll(&mut election), None);
+            request.send(&mut primary).unwrap();
+            assert_eq!(poll(&mut election), Some(Role::Secondary));
+        }
+    }
+
+    #[test]
+    fn undecided() {
+        let wire = Wire::new();
+        let (mut election, mut other) = (Election::new(wire.end()), wire.end());
+
+        // Responses are not sent to halves that can become secondary
+        Message::Keys {
+            seq: 1,
+            version: 0,
+            keys: &[1],
+        }
+        .send(&mut other)
+        .unwrap();
+        assert_eq!(poll(&mut election), None);
+
+        // Noise on the line
+        for byte in [0, 0x13, 0x37, 0, 0xFF, 0] {
+            other.write(byte).unwrap();
+            assert_eq!(poll(&mut election), None);
+        }
+
+        // A request after the noise is still recognised
+        Message::Poll { seq: 2 }.send(&mut other).unwrap();
+        assert_eq!(poll(&mut election), Some(Role::Secondary));
+    }
+
+    #[test]
+    fn sticky() {
+        let wire = Wire::new();
+        let (mut election, mut primary) = (Election::new(wire.end()), wire.end());
+
+        Message::Poll { seq: 1 }.send(&mut primary).unwrap();
+        assert_eq!(poll(&mut election), Some(Role::Secondary));
+
+        // Connecting to the host later doesn't make the half primary
+        assert_eq!(election.poll(true).unwrap(), Some(Role::Secondary));
+
+        let wire = Wire::new();
+        let (mut election, mut other) = (Election::new(wire.end()), wire.end());
+
+        assert_eq!(election.poll(true).unwrap(), Some(Role::Primary));
+
+        // Neither does disconnecting or receiving a request make it secondary
+        Message::Poll { seq: 1 }.send(&mut other).unwrap();
+        assert_eq!(poll(&mut election), Some(Role::Primary));
+        assert_eq!(election.role(), Some(Role::Primary));
+    }
+}
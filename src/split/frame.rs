@@ -0,0 +1,213 @@
+use crate::crc::crc16;
+
+/// Maximum size of an encoded frame, including the delimiters.
+pub(crate) const MAX_FRAME: usize = 64;
+
+/// Maximum size of a payload.
+pub(crate) const MAX_PAYLOAD: usize = MAX_FRAME - 5;
+
+/// Encodes `payload` into a frame, returns the size of the frame.
+///
+/// ## Panics
+///
+/// Panics if `payload` is longer than [`MAX_PAYLOAD`].
+pub(crate) fn encode(payload: &[u8], out: &mut [u8; MAX_FRAME]) -> usize {
+    assert!(payload.len() <= MAX_PAYLOAD);
+
+    let crc = crc16(payload).to_le_bytes();
+
+    // COBS: every zero is replaced by the distance to the next zero. Frames
+    // are shorter than 254 bytes, so there are no blocks without zeroes.
+    //
+    // The frame starts with a delimiter too, so that it's not lost if the
+    // previous one was cut short (for example because of a collision).
+    out[0] = 0;
+    let mut code_at = 1;
+    let mut code = 1;
+    let mut len = 2;
+
+    for &byte in payload.iter().chain(&crc) {
+        if byte == 0 {
+            out[code_at] = code;
+            code_at = len;
+            code = 1;
+        } else {
+            out[len] = byte;
+            code += 1;
+        }
+
+        len += 1;
+    }
+
+    out[code_at] = code;
+    out[len] = 0;
+    len + 1
+}
+
+/// Accumulates received bytes and decodes frames.
+pub(crate) struct Decoder {
+    buf: [u8; MAX_FRAME],
+    len: usize,
+    /// `true` if the current frame didn't fit into the buffer and is being
+    /// skipped.
+    overflow: bool,
+}
+
+impl Decoder {
+    pub(crate) const fn new() -> Self {
+        Self {
+            buf: [0; MAX_FRAME],
+            len: 0,
+            overflow: false,
+        }
+    }
+
+    /// Handles a received byte, returns the payload if it completes a valid
+    /// frame.
+    ///
+    /// Corrupted frames are silently dropped.
+    pub(crate) fn push(&mut self, byte: u8) -> Option<&[u8]> {
+        if byte != 0 {
+            match self.buf.get_mut(self.len) {
+                Some(slot) => {
+                    *slot = byte;
+                    self.len += 1;
+                }
+                None => self.overflow = true,
+            }
+
+            return None;
+        }
+
+        let len = core::mem::take(&mut self.len);
+        if core::mem::take(&mut self.overflow) || len == 0 {
+            return None;
+        }
+
+        let len = cobs_decode(&mut self.buf[..len])?;
+        let (payload, crc) = self.buf[..len].split_at(len.checked_sub(2)?);
+
+        if crc16(payload).to_le_bytes() != crc {
+            return None;
+        }
+
+        Some(payload)
+    }
+}
+
+/// Decodes COBS in place, returns the length of the decoded data.
+fn cobs_decode(buf: &mut [u8]) -> Option<usize> {
+    let (mut read, mut write) = (0, 0);
+
+    while read < buf.len() {
+        let code = usize::from(buf[read]);
+        read += 1;
+
+        if code == 0 || read + code - 1 > buf.len() {
+            return None;
+        }
+
+        buf.copy_within(read..read + code - 1, write);
+        read += code - 1;
+        write += code - 1;
+
+        // The last block isn't followed by a zero
+        if read < buf.len() {
+            buf[write] = 0;
+            write += 1;
+        }
+    }
+
+    Some(write)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::{cobs_decode, encode, Decoder, MAX_FRAME, MAX_PAYLOAD};
+    use crate::crc::crc16;
+
+    fn frame(payload: &[u8]) -> Vec<u8> {
+        let mut buf = [0; MAX_FRAME];
+        let len = encode(payload, &mut buf);
+        buf[..len].to_vec()
+    }
+
+    /// Feeds `bytes` to `decoder`, returns decoded payloads.
+    fn decode(decoder: &mut Decoder, bytes: &[u8]) -> Vec<Vec<u8>> {
+        bytes
+            .iter()
+            .filter_map(|&byte| decoder.push(byte).map(<[u8]>::to_vec))
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let full = (0..MAX_PAYLOAD).map(|i| (i % 7) as u8).collect::<Vec<_>>();
+        let payloads: [&[u8]; 5] = [&[], &[1, 2, 3], &[0], &[0, 1, 0, 0, 2, 0], &full];
+
+        for payload in payloads {
+            let frame = frame(payload);
+
+            // Delimiters only at the ends
+            assert!(frame.len() <= MAX_FRAME);
+            assert_eq!((frame[0], frame[frame.len() - 1]), (0, 0));
+            assert!(!frame[1..frame.len() - 1].contains(&0), "{:?}", frame);
+
+            assert_eq!(decode(&mut Decoder::new(), &frame), [payload]);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn too_long() {
+        frame(&[1; MAX_PAYLOAD + 1]);
+    }
+
+    #[test]
+    fn corrupted_crc() {
+        let payload = b"split";
+        let crc = crc16(payload).to_le_bytes();
+        assert!(!crc.contains(&0) && crc[1] != 0x80);
+
+        // The last byte before the delimiter is the second byte of the CRC
+        let mut corrupted = frame(payload);
+        let len = corrupted.len();
+        corrupted[len - 2] ^= 0x80;
+
+        let mut decoder = Decoder::new();
+        assert!(decode(&mut decoder, &corrupted).is_empty());
+        assert_eq!(decode(&mut decoder, &frame(payload)), [payload]);
+    }
+
+    #[test]
+    fn overflow() {
+        let mut decoder = Decoder::new();
+
+        // Garbage without delimiters, longer than any frame
+        assert!(decode(&mut decoder, &[0x55; MAX_FRAME + 10]).is_empty());
+        assert!(decoder.overflow);
+
+        // The garbage is terminated by the first delimiter of the frame
+        assert_eq!(decode(&mut decoder, &frame(&[4, 2])), [[4, 2]]);
+        assert!(!decoder.overflow);
+    }
+
+    #[test]
+    fn invalid_cobs() {
+        // The code points past the end
+        assert_eq!(cobs_decode(&mut [5, 1, 2]), None);
+        assert_eq!(cobs_decode(&mut [2, 1, 3, 7]), None);
+        // Zero code, can only come from a broken buffer
+        assert_eq!(cobs_decode(&mut [0, 1]), None);
+
+        let mut buf = [3, 1, 2, 2, 3];
+        assert_eq!(cobs_decode(&mut buf), Some(4));
+        assert_eq!(buf[..4], [1, 2, 0, 3]);
+
+        // Too short to contain a CRC
+        let mut decoder = Decoder::new();
+        assert!(decode(&mut decoder, &[0, 2, 1, 0]).is_empty());
+    }
+}
@@ -0,0 +1,301 @@
+use embedded_hal::{
+    blocking::delay::DelayUs,
+    serial::{Read, Write},
+};
+
+/// Half-duplex serial link over a single wire.
+///
+/// This wraps a UART whose TX and RX are connected to the same wire (TX must
+/// be open-drain with a pull-up), like the data line of a TRRS cable. Since
+/// both halves use the same wire, every byte written is also received by the
+/// writer. This echo is consumed and compared with the byte written: if they
+/// differ, both halves were transmitting at the same time and
+/// [`HalfDuplexError::Collision`] is returned. If there is no echo at all,
+/// the wire is probably stuck and [`HalfDuplexError::Timeout`] is returned.
+///
+/// Before starting a transmission, the line is kept idle for the turnaround
+/// time, giving the other half time to switch from transmitting to receiving.
+/// If the other half transmits during that time, the transmission doesn't
+/// start and [`HalfDuplexError::Collision`] is returned. A transmission ends
+/// with a flush.
+///
+/// Since the split link is request/response, collisions only happen when a
+/// response is late, the frame is then dropped and retransmitted.
+pub struct HalfDuplex<S, D> {
+    serial: S,
+    delay: D,
+    turnaround_us: u32,
+    timeout_us: u32,
+    /// Byte received while checking that the line is idle.
+    received: Option<u8>,
+    /// `true` if a transmission was started and not flushed yet.
+    transmitting: bool,
+}
+
+/// Error of the [`HalfDuplex`] link.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HalfDuplexError<R, W> {
+    /// Reading from the UART failed.
+    Read(R),
+    /// Writing to the UART failed.
+    Write(W),
+    /// The other half was transmitting at the same time.
+    Collision,
+    /// A written byte wasn't echoed back in time.
+    Timeout,
+}
+
+/// Interval between checks for the echo.
+const STEP_US: u32 = 10;
+
+impl<S, D> HalfDuplex<S, D> {
+    /// Creates a half-duplex link over `serial`, using `delay` for the
+    /// turnaround and timeouts.
+    ///
+    /// By default the turnaround time is 100 µs and the timeout is 1 ms.
+    pub fn new(serial: S, delay: D) -> Self {
+        Self {
+            serial,
+            delay,
+            turnaround_us: 100,
+            timeout_us: 1000,
+            received: None,
+            transmitting: false,
+        }
+    }
+
+    /// Sets the time the line must be idle before a transmission starts.
+    pub fn turnaround_us(self, turnaround_us: u32) -> Self {
+        Self {
+            turnaround_us,
+            ..self
+        }
+    }
+
+    /// Sets the time to wait for the echo of a written byte. This should be
+    /// longer than the time it takes to transmit a byte.
+    pub fn timeout_us(self, timeout_us: u32) -> Self {
+        Self { timeout_us, ..self }
+    }
+
+    /// Returns the serial port and the delay.
+    pub fn into_inner(self) -> (S, D) {
+        (self.serial, self.delay)
+    }
+}
+
+impl<S, D> HalfDuplex<S, D>
+where
+    S: Read<u8>,
+    D: DelayUs<u32>,
+{
+    /// Waits for the turnaround time, returns `false` if the other half
+    /// started transmitting.
+    fn wait_idle(&mut self) -> Result<bool, S::Error> {
+        self.delay.delay_us(self.turnaround_us);
+
+        match self.serial.read() {
+            Ok(byte) => {
+                self.received = Some(byte);
+                Ok(false)
+            }
+            Err(nb::Error::WouldBlock) => Ok(true),
+            Err(nb::Error::Other(err)) => Err(err),
+        }
+    }
+
+    /// Waits for the echo of a written byte.
+    fn echo(&mut self) -> Result<Option<u8>, S::Error> {
+        for _ in 0..=self.timeout_us / STEP_US {
+            match self.serial.read() {
+                Ok(byte) => return Ok(Some(byte)),
+                Err(nb::Error::WouldBlock) => self.delay.delay_us(STEP_US),
+                Err(nb::Error::Other(err)) => return Err(err),
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+impl<S, D> Read<u8> for HalfDuplex<S, D>
+where
+    S: Read<u8> + Write<u8>,
+{
+    type Error = HalfDuplexError<<S as Read<u8>>::Error, <S as Write<u8>>::Error>;
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        if let Some(byte) = self.received.take() {
+            return Ok(byte);
+        }
+
+        self.serial
+            .read()
+            .map_err(|err| err.map(HalfDuplexError::Read))
+    }
+}
+
+impl<S, D> Write<u8> for HalfDuplex<S, D>
+where
+    S: Read<u8> + Write<u8>,
+    D: DelayUs<u32>,
+{
+    type Error = HalfDuplexError<<S as Read<u8>>::Error, <S as Write<u8>>::Error>;
+
+    fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+        let other = |err| nb::Error::Other(err);
+
+        if !self.transmitting {
+            let idle = self.wait_idle().map_err(HalfDuplexError::Read);
+            if !idle.map_err(other)? {
+                return Err(other(HalfDuplexError::Collision));
+            }
+
+            self.transmitting = true;
+        }
+
+        nb::block!(self.serial.write(byte))
+            .map_err(HalfDuplexError::Write)
+            .map_err(other)?;
+
+        match self.echo().map_err(HalfDuplexError::Read).map_err(other)? {
+            Some(echo) if echo == byte => Ok(()),
+            Some(_) => {
+                self.transmitting = false;
+                Err(other(HalfDuplexError::Collision))
+            }
+            None => {
+                self.transmitting = false;
+                Err(other(HalfDuplexError::Timeout))
+            }
+        }
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        self.serial
+            .flush()
+            .map_err(|err| err.map(HalfDuplexError::Write))?;
+
+        self.transmitting = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::convert::Infallible;
+
+    use embedded_hal::serial::{Read, Write};
+
+    use super::{HalfDuplex, HalfDuplexError};
+    use crate::split::sim::{End, NoWait, Wire};
+
+    /// UART whose writes are jammed by another end of the wire.
+    struct Jammed {
+        end: End,
+        jammer: End,
+    }
+
+    impl Read<u8> for Jammed {
+        type Error = Infallible;
+
+        fn read(&mut self) -> nb::Result<u8, Self::Error> {
+            self.end.read()
+        }
+    }
+
+    impl Write<u8> for Jammed {
+        type Error = Infallible;
+
+        fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+            self.end.write(byte)?;
+            self.jammer.write(0x0F)
+        }
+
+        fn flush(&mut self) -> nb::Result<(), Self::Error> {
+            self.end.flush()
+        }
+    }
+
+    /// UART disconnected from the wire.
+    struct Deaf;
+
+    impl Read<u8> for Deaf {
+        type Error = Infallible;
+
+        fn read(&mut self) -> nb::Result<u8, Self::Error> {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    impl Write<u8> for Deaf {
+        type Error = Infallible;
+
+        fn write(&mut self, _byte: u8) -> nb::Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn flush(&mut self) -> nb::Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn transmit() {
+        let wire = Wire::new();
+        let mut a = HalfDuplex::new(wire.end(), NoWait);
+        let mut b = wire.end();
+
+        for byte in [1, 2, 3] {
+            a.write(byte).unwrap();
+        }
+        a.flush().unwrap();
+
+        // Echoes are consumed by the writer
+        assert_eq!(a.read(), Err(nb::Error::WouldBlock));
+        assert_eq!(b.read(), Ok(1));
+        assert_eq!(b.read(), Ok(2));
+        assert_eq!(b.read(), Ok(3));
+        assert_eq!(b.read(), Err(nb::Error::WouldBlock));
+    }
+
+    #[test]
+    fn collision_before_transmission() {
+        let wire = Wire::new();
+        let mut a = HalfDuplex::new(wire.end(), NoWait);
+        let mut b = wire.end();
+
+        b.write(0x55).unwrap();
+
+        let collision = nb::Error::Other(HalfDuplexError::Collision);
+        assert_eq!(a.write(0x01), Err(collision));
+        // The byte received while waiting is not lost
+        assert_eq!(a.read(), Ok(0x55));
+        assert_eq!(a.read(), Err(nb::Error::WouldBlock));
+
+        // The next transmission starts over
+        a.write(0x01).unwrap();
+        a.flush().unwrap();
+        assert_eq!(b.read(), Ok(0x55));
+        assert_eq!(b.read(), Ok(0x01));
+    }
+
+    #[test]
+    fn collision_during_transmission() {
+        let wire = Wire::new();
+        let end = wire.end();
+        let jammer = wire.end();
+        let mut a = HalfDuplex::new(Jammed { end, jammer }, NoWait);
+
+        let collision = nb::Error::Other(HalfDuplexError::Collision);
+        assert_eq!(a.write(0xF0), Err(collision));
+    }
+
+    #[test]
+    fn timeout() {
+        let mut a = HalfDuplex::new(Deaf, NoWait);
+
+        let timeout = nb::Error::Other(HalfDuplexError::Timeout);
+        assert_eq!(a.write(0x01), Err(timeout));
+    }
+}
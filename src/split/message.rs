@@ -0,0 +1,173 @@
+use embedded_hal::serial::Write;
+
+use crate::split::{
+    frame::{self, MAX_FRAME, MAX_PAYLOAD},
+    State,
+};
+
+/// Version of the message set, messages of other versions are ignored.
+const VERSION: u8 = 2;
+
+const POLL: u8 = 0;
+const KEYS: u8 = 1;
+const SYNC: u8 = 2;
+
+/// Message sent over the split link.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Message<'a> {
+    /// Request of the state of keys, sent by the primary half.
+    Poll { seq: u8 },
+    /// Same as [`Message::Poll`], but also carries the state of the keyboard,
+    /// sent by the primary half when the state has changed.
+    ///
+    /// `version` identifies the state, it's never `0`.
+    Sync { seq: u8, version: u8, state: State },
+    /// Pressed keys, a bitset, sent by the secondary half in response to
+    /// [`Message::Poll`] or [`Message::Sync`] with the same `seq`.
+    ///
+    /// `version` is the version of the last received state, or `0` if none
+    /// was received.
+    Keys {
+        seq: u8,
+        version: u8,
+        keys: &'a [u8],
+    },
+}
+
+impl<'a> Message<'a> {
+    /// Decodes a message from a frame payload.
+    pub(crate) fn decode(payload: &'a [u8]) -> Option<Self> {
+        match *payload {
+            [VERSION, POLL, seq] => Some(Self::Poll { seq }),
+            [VERSION, SYNC, seq, version, a, b, c, d, e, f] => Some(Self::Sync {
+                seq,
+                version,
+                state: State::decode([a, b, c, d, e, f]),
+            }),
+            [VERSION, KEYS, seq, version, ref keys @ ..] => Some(Self::Keys { seq, version, keys }),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the message is a request of the primary half.
+    pub(crate) fn is_request(&self) -> bool {
+        matches!(self, Self::Poll { .. } | Self::Sync { .. })
+    }
+
+    /// Encodes the message into a frame payload, returns its size.
+    fn encode(&self, out: &mut [u8; MAX_PAYLOAD]) -> usize {
+        let header = |kind, seq| [VERSION, kind, seq];
+
+        match *self {
+            Self::Poll { seq } => {
+                out[..3].copy_from_slice(&header(POLL, seq));
+                3
+            }
+            Self::Sync {
+                seq,
+                version,
+                state,
+            } => {
+                out[..3].copy_from_slice(&header(SYNC, seq));
+                out[3] = version;
+                out[4..][..State::SIZE].copy_from_slice(&state.encode());
+                4 + State::SIZE
+            }
+            Self::Keys { seq, version, keys } => {
+                out[..3].copy_from_slice(&header(KEYS, seq));
+                out[3] = version;
+                out[4..][..keys.len()].copy_from_slice(keys);
+                4 + keys.len()
+            }
+        }
+    }
+
+    /// Sends the message, blocking until it's written.
+    pub(crate) fn send<S>(&self, serial: &mut S) -> Result<(), S::Error>
+    where
+        S: Write<u8> + ?Sized,
+    {
+        let mut payload = [0; MAX_PAYLOAD];
+        let len = self.encode(&mut payload);
+
+        let mut buf = [0; MAX_FRAME];
+        let len = frame::encode(&payload[..len], &mut buf);
+
+        for &byte in &buf[..len] {
+            nb::block!(serial.write(byte))?;
+        }
+
+        nb::block!(serial.flush())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Message, KEYS, MAX_PAYLOAD, POLL, SYNC, VERSION};
+    use crate::{proto::LedStates, split::State};
+
+    fn encode(message: &Message<'_>) -> ([u8; MAX_PAYLOAD], usize) {
+        let mut buf = [0; MAX_PAYLOAD];
+        let len = message.encode(&mut buf);
+        (buf, len)
+    }
+
+    const STATE: State = State {
+        leds: LedStates::from_bits(0b101),
+        layers: 0x8000_0003,
+        lighting: 7,
+    };
+
+    #[test]
+    fn round_trip() {
+        let messages = [
+            Message::Poll { seq: 3 },
+            Message::Sync {
+                seq: 4,
+                version: 255,
+                state: STATE,
+            },
+            Message::Keys {
+                seq: 5,
+                version: 0,
+                keys: &[0b1001, 0, 0xFF],
+            },
+            Message::Keys {
+                seq: 6,
+                version: 1,
+                keys: &[],
+            },
+        ];
+
+        for message in messages {
+            let (buf, len) = encode(&message);
+            assert_eq!(Message::decode(&buf[..len]), Some(message));
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        // Other versions of the message set
+        assert_eq!(Message::decode(&[VERSION + 1, POLL, 3]), None);
+        assert_eq!(Message::decode(&[VERSION - 1, KEYS, 3, 0, 1]), None);
+
+        // Unknown kind
+        assert_eq!(Message::decode(&[VERSION, 3, 3]), None);
+        assert_eq!(Message::decode(&[VERSION, 0xFF, 3, 0]), None);
+
+        // Wrong sizes
+        assert_eq!(Message::decode(&[]), None);
+        assert_eq!(Message::decode(&[VERSION, POLL]), None);
+        assert_eq!(Message::decode(&[VERSION, POLL, 3, 0]), None);
+        assert_eq!(Message::decode(&[VERSION, KEYS, 3]), None);
+
+        let (buf, len) = encode(&Message::Sync {
+            seq: 1,
+            version: 1,
+            state: STATE,
+        });
+        assert_eq!(buf[1], SYNC);
+        assert_eq!(Message::decode(&buf[..len - 1]), None);
+        assert_eq!(Message::decode(&buf[..len + 1]), None);
+    }
+}
@@ -0,0 +1,408 @@
+use embedded_hal::serial::{Read, Write};
+
+use crate::{
+    phy::{KeyId, KeyState, KeyStates, Layout, TryLayout},
+    split::{frame::Decoder, message::Message, Error, State, KEY_BYTES, MAX_KEYS},
+};
+
+/// The secondary half of a split keyboard as seen by the primary half.
+///
+/// Every poll sends a request to the [`Secondary`] and handles its response
+/// to the previous request, i.e. the state of keys is one poll behind. If a
+/// response is lost or corrupted, the request is retransmitted. If there are
+/// no responses for more than [`Remote::max_retries`] polls, the link is
+/// considered down: keys that were pressed are reported as
+/// [`KeyState::Unknown`] and [`Error::Timeout`] is returned.
+///
+/// `N` is the number of keys of the secondary half, at most [`MAX_KEYS`].
+/// Usually this is combined with the layout of the primary half using
+/// [`Chain`], so that [`KeyId`]s of the secondary half are offset after the
+/// keys of the primary half.
+///
+/// The state of the keyboard set by [`Remote::set_state`] is sent to the
+/// secondary half with the requests, until the secondary half confirms that it
+/// received it. I.e. the state is only sent when it changes.
+///
+/// This implements [`Layout`] too, in which case errors are ignored and keys
+/// are released while the link is down.
+///
+/// [`Secondary`]: super::Secondary
+/// [`Chain`]: crate::phy::layouts::Chain
+pub struct Remote<S, const N: usize> {
+    serial: S,
+    decoder: Decoder,
+    keys: [u8; KEY_BYTES],
+    /// Sequence number of the outstanding request.
+    seq: u8,
+    /// Number of requests without a response in a row.
+    misses: u8,
+    max_retries: u8,
+    state: State,
+    /// Version of `state`, never `0`.
+    version: u8,
+    /// Version of the state received by the secondary half.
+    synced: u8,
+}
+
+impl<S, const N: usize> Remote<S, N> {
+    /// Creates the secondary half connected via `serial`.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `N` is bigger than [`MAX_KEYS`].
+    pub fn new(serial: S) -> Self {
+        assert!(N <= MAX_KEYS);
+
+        Self {
+            serial,
+            decoder: Decoder::new(),
+            keys: [0; KEY_BYTES],
+            seq: 0,
+            misses: 0,
+            max_retries: 3,
+            state: State::default(),
+            version: 1,
+            synced: 0,
+        }
+    }
+
+    /// Sets the number of retransmissions after which the link is considered
+    /// down, `3` by default.
+    pub fn max_retries(self, max_retries: u8) -> Self {
+        Self {
+            max_retries,
+            ..self
+        }
+    }
+
+    /// Sets the state of the keyboard that is sent to the secondary half.
+    pub fn set_state(&mut self, state: State) {
+        // `0` means that the secondary half didn't receive any state
+        let next = |version: u8| version.checked_add(1).unwrap_or(1);
+
+        if state != self.state {
+            self.state = state;
+            self.version = next(self.version);
+
+            // After many changes that the secondary half didn't confirm, the
+            // version could wrap around to the one it has
+            if self.version == self.synced {
+                self.version = next(self.version);
+            }
+        }
+    }
+
+    /// Returns `true` if the secondary half received the last state set by
+    /// [`Remote::set_state`].
+    pub fn synced(&self) -> bool {
+        self.synced == self.version
+    }
+
+    /// Returns `true` if the secondary half responds.
+    pub fn connected(&self) -> bool {
+        self.misses <= self.max_retries
+    }
+
+    /// Returns the serial link.
+    pub fn into_inner(self) -> S {
+        self.serial
+    }
+}
+
+impl<S, const N: usize> Remote<S, N>
+where
+    S: Read<u8> + Write<u8>,
+{
+    /// Handles the response to the outstanding request and sends the next one.
+    fn exchange(&mut self) -> Result<(), Error<S>> {
+        let mut error = None;
+        let mut received = false;
+
+        loop {
+            let byte = match self.serial.read() {
+                Ok(byte) => byte,
+                Err(nb::Error::WouldBlock) => break,
+                Err(nb::Error::Other(err)) => {
+                    error.get_or_insert(Error::Read(err));
+                    break;
+                }
+            };
+
+            let msg = self.decoder.push(byte).and_then(Message::decode);
+            if let Some(Message::Keys { seq, version, keys }) = msg {
+                // Responses to older requests are stale
+                if seq == self.seq {
+                    self.synced = version;
+                    let len = keys.len().min(KEY_BYTES);
+                    self.keys = [0; KEY_BYTES];
+                    self.keys[..len].copy_from_slice(&keys[..len]);
+                    received = true;
+                }
+            }
+        }
+
+        if received {
+            self.seq = self.seq.wrapping_add(1);
+            self.misses = 0;
+        } else {
+            // Retransmit the same request
+            self.misses = self.misses.saturating_add(1);
+        }
+
+        let seq = self.seq;
+        let request = if self.synced() {
+            Message::Poll { seq }
+        } else {
+            Message::Sync {
+                seq,
+                version: self.version,
+                state: self.state,
+            }
+        };
+
+        if let Err(err) = request.send(&mut self.serial) {
+            error.get_or_insert(Error::Write(err));
+        }
+
+        error.map_or(Ok(()), Err)
+    }
+}
+
+impl<S, const N: usize> TryLayout for Remote<S, N>
+where
+    S: Read<u8> + Write<u8>,
+{
+    type Error = Error<S>;
+
+    fn try_poll(&mut self, f: &mut dyn FnMut(&mut KeyStates<'_>)) -> Result<(), Self::Error> {
+        let mut res = self.exchange();
+
+        let state = if self.connected() {
+            KeyState::Pressed
+        } else {
+            res = res.and(Err(Error::Timeout));
+            KeyState::Unknown
+        };
+
+        let keys = &self.keys;
+        let mut iter = (0..N)
+            .filter(|k| keys[k / 8] & 1 << (k % 8) != 0)
+            .map(|k| (KeyId::from_raw(k as u16), state));
+
+        f(&mut iter);
+
+        res
+    }
+
+    fn try_max_key_id(&self) -> KeyId {
+        KeyId::from_raw(N as _)
+    }
+}
+
+impl<S, const N: usize> Layout for Remote<S, N>
+where
+    S: Read<u8> + Write<u8>,
+{
+    fn poll(&mut self, f: &mut dyn FnMut(&mut dyn Iterator<Item = KeyId>)) {
+        // Errors are reported as `Unknown` keys, which are released here
+        let _ = self.try_poll(&mut |iter| {
+            f(&mut iter
+                .filter(|(_, state)| state.pressed())
+                .map(|(key, _)| key))
+        });
+    }
+
+    fn max_key_id(&self) -> KeyId {
+        KeyId::from_raw(N as _)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::Remote;
+    use crate::{
+        phy::{KeyId, KeyState, Layout, TryLayout},
+        split::{
+            sim::{End, NoWait, Wire},
+            Error, HalfDuplex, Secondary, State,
+        },
+    };
+
+    type Link = HalfDuplex<End, NoWait>;
+
+    /// Layout of the secondary half with `keys` pressed.
+    struct Pressed(&'static [u16]);
+
+    impl Layout for Pressed {
+        fn poll(&mut self, f: &mut dyn FnMut(&mut dyn Iterator<Item = KeyId>)) {
+            f(&mut self.0.iter().copied().map(KeyId::from_raw))
+        }
+
+        fn max_key_id(&self) -> KeyId {
+            KeyId::from_raw(8)
+        }
+    }
+
+    fn connect(wire: &Wire, keys: &'static [u16]) -> Secondary<Link> {
+        let mut secondary = Secondary::new(HalfDuplex::new(wire.end(), NoWait));
+        secondary.scan(&mut Pressed(keys));
+        secondary
+    }
+
+    /// Polls `remote`, returns whether it timed out and the reported keys.
+    fn poll(remote: &mut Remote<Link, 8>) -> (bool, Vec<(u16, KeyState)>) {
+        let mut keys = Vec::new();
+        let res = remote
+            .try_poll(&mut |iter| keys.extend(iter.map(|(key, state)| (key.into_raw(), state))));
+
+        let timeout = match res {
+            Ok(()) => false,
+            Err(Error::Timeout) => true,
+            Err(err) => panic!("unexpected error: {:?}", err),
+        };
+
+        (timeout, keys)
+    }
+
+    #[test]
+    fn exchange() {
+        let wire = Wire::new();
+        let mut remote = Remote::<_, 8>::new(HalfDuplex::new(wire.end(), NoWait));
+        let mut secondary = connect(&wire, &[1, 5]);
+
+        let state = State {
+            layers: 0b101,
+            lighting: 3,
+            ..State::default()
+        };
+        remote.set_state(state);
+
+        // Keys are one poll behind
+        assert_eq!(poll(&mut remote), (false, Vec::new()));
+        secondary.serve().unwrap();
+        assert!(!remote.synced());
+
+        let pressed = [(1, KeyState::Pressed), (5, KeyState::Pressed)];
+        assert_eq!(poll(&mut remote), (false, pressed.to_vec()));
+        assert!(remote.synced());
+        assert_eq!(secondary.state(), state);
+
+        secondary.scan(&mut Pressed(&[7]));
+        secondary.serve().unwrap();
+        assert_eq!(
+            poll(&mut remote),
+            (false, [(7, KeyState::Pressed)].to_vec())
+        );
+
+        // Changing the state syncs it again
+        let state = State {
+            lighting: 4,
+            ..state
+        };
+        remote.set_state(state);
+        assert!(!remote.synced());
+        secondary.serve().unwrap();
+        poll(&mut remote);
+        secondary.serve().unwrap();
+        poll(&mut remote);
+        assert!(remote.synced());
+        assert_eq!(secondary.state(), state);
+    }
+
+    #[test]
+    fn retransmit() {
+        let wire = Wire::new();
+        let mut remote = Remote::<_, 8>::new(HalfDuplex::new(wire.end(), NoWait));
+        let mut secondary = connect(&wire, &[2]);
+
+        let pressed = [(2, KeyState::Pressed)].to_vec();
+        poll(&mut remote);
+        secondary.serve().unwrap();
+        assert_eq!(poll(&mut remote), (false, pressed.clone()));
+
+        // Responses to the outstanding request and its retransmission are
+        // lost, the last known state is kept
+        secondary.into_inner();
+        for _ in 0..2 {
+            assert_eq!(poll(&mut remote), (false, pressed.clone()));
+            assert!(remote.connected());
+        }
+
+        // The response to the next retransmission is accepted
+        let mut secondary = connect(&wire, &[4]);
+        assert_eq!(poll(&mut remote), (false, pressed));
+        secondary.serve().unwrap();
+        assert_eq!(
+            poll(&mut remote),
+            (false, [(4, KeyState::Pressed)].to_vec())
+        );
+        assert!(remote.connected());
+    }
+
+    #[test]
+    fn timeout() {
+        let wire = Wire::new();
+        let mut remote = Remote::<_, 8>::new(HalfDuplex::new(wire.end(), NoWait)).max_retries(1);
+        let mut secondary = connect(&wire, &[2]);
+
+        poll(&mut remote);
+        secondary.serve().unwrap();
+        assert_eq!(
+            poll(&mut remote),
+            (false, [(2, KeyState::Pressed)].to_vec())
+        );
+
+        // The secondary half is disconnected
+        secondary.into_inner();
+        assert_eq!(
+            poll(&mut remote),
+            (false, [(2, KeyState::Pressed)].to_vec())
+        );
+        assert_eq!(poll(&mut remote), (true, [(2, KeyState::Unknown)].to_vec()));
+        assert!(!remote.connected());
+        assert_eq!(poll(&mut remote), (true, [(2, KeyState::Unknown)].to_vec()));
+
+        // ... and reconnected
+        let mut secondary = connect(&wire, &[]);
+        secondary.serve().unwrap();
+        assert_eq!(poll(&mut remote), (true, [(2, KeyState::Unknown)].to_vec()));
+        secondary.serve().unwrap();
+        assert_eq!(poll(&mut remote), (false, Vec::new()));
+        assert!(remote.connected());
+    }
+
+    #[test]
+    fn version_wraps() {
+        let wire = Wire::new();
+        let mut remote = Remote::<_, 8>::new(HalfDuplex::new(wire.end(), NoWait));
+        let mut secondary = connect(&wire, &[]);
+
+        let state = |lighting| State {
+            lighting,
+            ..State::default()
+        };
+
+        remote.set_state(state(1));
+        poll(&mut remote);
+        secondary.serve().unwrap();
+        poll(&mut remote);
+        assert!(remote.synced());
+        secondary.serve().unwrap();
+
+        // The state goes through all the versions before the next poll
+        for lighting in 2..=255 {
+            remote.set_state(state(lighting));
+            assert!(!remote.synced(), "{}", lighting);
+        }
+        remote.set_state(state(0));
+        assert!(!remote.synced());
+
+        poll(&mut remote);
+        secondary.serve().unwrap();
+        poll(&mut remote);
+        assert!(remote.synced());
+        assert_eq!(secondary.state(), state(0));
+    }
+}
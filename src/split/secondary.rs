@@ -0,0 +1,112 @@
+use embedded_hal::serial::{Read, Write};
+
+use crate::{
+    phy::Layout,
+    split::{frame::Decoder, message::Message, Error, State, KEY_BYTES, MAX_KEYS},
+};
+
+/// The secondary half of a split keyboard, reports the state of its keys to
+/// the primary half (see [`Remote`]).
+///
+/// The firmware of the secondary half should periodically call
+/// [`Secondary::scan`] to update the state of keys and [`Secondary::serve`]
+/// to respond to requests of the primary half (for example when a byte is
+/// received). The state of the keyboard sent by the primary half is
+/// available via [`Secondary::state`].
+///
+/// [`Remote`]: super::Remote
+pub struct Secondary<S> {
+    serial: S,
+    decoder: Decoder,
+    keys: [u8; KEY_BYTES],
+    /// Number of bytes of `keys` that are sent.
+    len: usize,
+    state: State,
+    /// Version of `state`, `0` if no state was received.
+    version: u8,
+}
+
+impl<S> Secondary<S> {
+    /// Creates the half connected to the primary one via `serial`.
+    pub fn new(serial: S) -> Self {
+        Self {
+            serial,
+            decoder: Decoder::new(),
+            keys: [0; KEY_BYTES],
+            len: 0,
+            state: State::default(),
+            version: 0,
+        }
+    }
+
+    /// Updates the state of keys by polling `layout`.
+    ///
+    /// Keys with [`KeyId`]s not less than [`MAX_KEYS`] are ignored.
+    ///
+    /// [`KeyId`]: crate::phy::KeyId
+    pub fn scan<L>(&mut self, layout: &mut L)
+    where
+        L: Layout + ?Sized,
+    {
+        let keys = &mut self.keys;
+        *keys = [0; KEY_BYTES];
+
+        layout.poll(&mut |iter| {
+            iter.map(|k| usize::from(k.into_raw()))
+                .filter(|&k| k < MAX_KEYS)
+                .for_each(|k| keys[k / 8] |= 1 << (k % 8))
+        });
+
+        let max_key_id = usize::from(layout.max_key_id().into_raw());
+        self.len = max_key_id.min(MAX_KEYS).div_ceil(8);
+    }
+
+    /// Returns the state of the keyboard last received from the primary half,
+    /// or the default state if none was received.
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Returns the serial link.
+    pub fn into_inner(self) -> S {
+        self.serial
+    }
+}
+
+impl<S> Secondary<S>
+where
+    S: Read<u8> + Write<u8>,
+{
+    /// Handles received requests, responding with the state of keys as of
+    /// the last [`Secondary::scan`].
+    pub fn serve(&mut self) -> Result<(), Error<S>> {
+        loop {
+            let byte = match self.serial.read() {
+                Ok(byte) => byte,
+                Err(nb::Error::WouldBlock) => return Ok(()),
+                Err(nb::Error::Other(err)) => return Err(Error::Read(err)),
+            };
+
+            let seq = match self.decoder.push(byte).and_then(Message::decode) {
+                Some(Message::Poll { seq }) => seq,
+                Some(Message::Sync {
+                    seq,
+                    version,
+                    state,
+                }) => {
+                    self.state = state;
+                    self.version = version;
+                    seq
+                }
+                _ => continue,
+            };
+
+            let keys = &self.keys[..self.len];
+            let version = self.version;
+
+            (Message::Keys { seq, version, keys })
+                .send(&mut self.serial)
+                .map_err(Error::Write)?;
+        }
+    }
+}
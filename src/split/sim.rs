@@ -0,0 +1,161 @@
+use core::{cell::RefCell, convert::Infallible};
+use std::{collections::VecDeque, rc::Rc, vec::Vec};
+
+use embedded_hal::{
+    blocking::delay::DelayUs,
+    serial::{Read, Write},
+};
+
+/// Simulated single wire shared by any number of UARTs (see [`Wire::end`]).
+///
+/// Bytes written to the wire are received by all ends, including the one that
+/// wrote them. A written byte stays on the wire until the next read from any
+/// end or the next write from the same end, bytes written by different ends
+/// before that collide: the wire is open-drain, so all ends receive the
+/// bitwise AND of them.
+#[derive(Clone, Default)]
+pub struct Wire {
+    state: Rc<RefCell<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Byte currently on the wire.
+    driven: Option<u8>,
+    ends: Vec<EndState>,
+}
+
+#[derive(Default)]
+struct EndState {
+    /// Received bytes.
+    rx: VecDeque<u8>,
+    /// `true` if the end drives the byte currently on the wire.
+    driving: bool,
+}
+
+/// UART connected to a [`Wire`].
+pub struct End {
+    state: Rc<RefCell<State>>,
+    index: usize,
+}
+
+/// Delay that doesn't wait, for use with [`HalfDuplex`] on a [`Wire`].
+///
+/// [`HalfDuplex`]: super::HalfDuplex
+#[derive(Debug, Copy, Clone, Default)]
+pub struct NoWait;
+
+impl Wire {
+    /// Creates a wire without any UARTs connected to it.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Connects a new UART to the wire.
+    pub fn end(&self) -> End {
+        let mut state = self.state.borrow_mut();
+        state.ends.push(EndState::default());
+
+        End {
+            state: Rc::clone(&self.state),
+            index: state.ends.len() - 1,
+        }
+    }
+}
+
+impl State {
+    /// Delivers the byte on the wire to all ends.
+    fn settle(&mut self) {
+        if let Some(byte) = self.driven.take() {
+            self.ends.iter_mut().for_each(|end| {
+                end.rx.push_back(byte);
+                end.driving = false;
+            });
+        }
+    }
+}
+
+impl Read<u8> for End {
+    type Error = Infallible;
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        let mut state = self.state.borrow_mut();
+        state.settle();
+        state.ends[self.index]
+            .rx
+            .pop_front()
+            .ok_or(nb::Error::WouldBlock)
+    }
+}
+
+impl Write<u8> for End {
+    type Error = Infallible;
+
+    fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+        let mut state = self.state.borrow_mut();
+        if state.ends[self.index].driving {
+            state.settle();
+        }
+
+        state.driven = Some(state.driven.map_or(byte, |driven| driven & byte));
+        state.ends[self.index].driving = true;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl DelayUs<u32> for NoWait {
+    fn delay_us(&mut self, _us: u32) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_hal::serial::{Read, Write};
+
+    use std::vec::Vec;
+
+    use super::{End, Wire};
+
+    fn read_all(end: &mut End) -> Vec<u8> {
+        core::iter::from_fn(|| end.read().ok()).collect()
+    }
+
+    #[test]
+    fn echo() {
+        let wire = Wire::new();
+        let (mut a, mut b) = (wire.end(), wire.end());
+
+        a.write(0x42).unwrap();
+
+        assert_eq!(read_all(&mut b), [0x42]);
+        assert_eq!(read_all(&mut a), [0x42]);
+    }
+
+    #[test]
+    fn consecutive_writes_are_not_merged() {
+        let wire = Wire::new();
+        let (mut a, mut b) = (wire.end(), wire.end());
+
+        a.write(0xF0).unwrap();
+        a.write(0x0F).unwrap();
+        a.write(0xFF).unwrap();
+
+        assert_eq!(read_all(&mut b), [0xF0, 0x0F, 0xFF]);
+        assert_eq!(read_all(&mut a), [0xF0, 0x0F, 0xFF]);
+    }
+
+    #[test]
+    fn collision() {
+        let wire = Wire::new();
+        let (mut a, mut b) = (wire.end(), wire.end());
+
+        a.write(0xF0).unwrap();
+        b.write(0x3C).unwrap();
+
+        assert_eq!(read_all(&mut a), [0x30]);
+        assert_eq!(read_all(&mut b), [0x30]);
+    }
+}
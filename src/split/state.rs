@@ -0,0 +1,73 @@
+use crate::proto::LedStates;
+
+/// State of the keyboard that the primary half sends to the secondary one,
+/// see [`Remote::set_state`] and [`Secondary::state`].
+///
+/// [`Remote::set_state`]: super::Remote::set_state
+/// [`Secondary::state`]: super::Secondary::state
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct State {
+    /// Led states reported by the host (see [`Protocol::leds`]).
+    ///
+    /// [`Protocol::leds`]: crate::proto::Protocol::leds
+    pub leds: LedStates,
+    /// Active layers, bit `n` is set if layer `n` is active.
+    pub layers: u32,
+    /// Lighting mode, its meaning is up to the firmware.
+    pub lighting: u8,
+}
+
+impl State {
+    /// Size of the encoded state.
+    pub(crate) const SIZE: usize = 6;
+
+    pub(crate) fn encode(&self) -> [u8; Self::SIZE] {
+        let [l0, l1, l2, l3] = self.layers.to_le_bytes();
+        [self.leds.to_bits(), l0, l1, l2, l3, self.lighting]
+    }
+
+    pub(crate) fn decode(bytes: [u8; Self::SIZE]) -> Self {
+        let [leds, l0, l1, l2, l3, lighting] = bytes;
+
+        Self {
+            leds: LedStates::from_bits(leds),
+            layers: u32::from_le_bytes([l0, l1, l2, l3]),
+            lighting,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::State;
+    use crate::proto::LedStates;
+
+    #[test]
+    fn round_trip() {
+        let states = [
+            State::default(),
+            State {
+                leds: LedStates::from_bits(0b1_0110),
+                layers: 0xDEAD_BEEF,
+                lighting: 0xFF,
+            },
+            State {
+                leds: LedStates::from_bits(0b1),
+                layers: 1 << 31,
+                lighting: 0,
+            },
+        ];
+
+        for state in states {
+            assert_eq!(State::decode(state.encode()), state);
+        }
+
+        // Layers are little endian
+        let bytes = State {
+            layers: 0x0403_0201,
+            ..State::default()
+        }
+        .encode();
+        assert_eq!(bytes[1..5], [1, 2, 3, 4]);
+    }
+}
@@ -0,0 +1,51 @@
+#[cfg(any(test, feature = "std"))]
+mod mock;
+mod store;
+
+#[cfg(any(test, feature = "std"))]
+pub use mock::{MockError, MockFlash};
+pub use store::Store;
+
+/// Key of a value in a [`Store`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Key(u16);
+
+impl Key {
+    /// Keymap of the keyboard, see [`keymap::encode`].
+    ///
+    /// [`keymap::encode`]: crate::keymap::encode
+    pub const KEYMAP: Self = Self(0);
+    /// Index of the default layer.
+    pub const DEFAULT_LAYER: Self = Self(1);
+    /// Lighting settings, their meaning is up to the firmware.
+    pub const LIGHTING: Self = Self(2);
+
+    /// Creates a key from its raw representation.
+    ///
+    /// Keys below `0x100` are reserved for the keys defined by this crate.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `raw` is `0xFFFF` (erased flash).
+    pub const fn from_raw(raw: u16) -> Self {
+        assert!(raw != u16::MAX);
+        Self(raw)
+    }
+
+    /// Converts the key back to the raw representation.
+    pub const fn into_raw(self) -> u16 {
+        self.0
+    }
+}
+
+/// Error of the [`Store`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Error<E> {
+    /// Flash operation failed.
+    Flash(E),
+    /// There is no space left for the value, even after removing old values.
+    Full,
+    /// The value is too big to fit into a page, or the buffer is too small to
+    /// fit the value.
+    TooLarge,
+}
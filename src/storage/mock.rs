@@ -0,0 +1,224 @@
+use std::{vec, vec::Vec};
+
+use embedded_storage::nor_flash::{
+    ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+};
+
+/// NOR flash in memory, to test the [`Store`] on the host.
+///
+/// Writing bytes that are not erased is an error, as on most NOR flash.
+/// Power loss can be simulated with [`MockFlash::cut_power_after`].
+///
+/// [`Store`]: super::Store
+pub struct MockFlash<const WRITE_SIZE: usize = 4, const ERASE_SIZE: usize = 1024> {
+    memory: Vec<u8>,
+    /// Number of erases of every page.
+    erases: Vec<u32>,
+    /// Number of bytes that can be written or erased before power is lost.
+    power: Option<usize>,
+}
+
+/// Error of the [`MockFlash`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MockError {
+    NotAligned,
+    OutOfBounds,
+    /// Bytes that are not erased were written.
+    NotErased,
+    /// Power was lost, see [`MockFlash::cut_power_after`].
+    PowerLoss,
+}
+
+impl<const WRITE_SIZE: usize, const ERASE_SIZE: usize> MockFlash<WRITE_SIZE, ERASE_SIZE> {
+    /// Creates erased flash of `capacity` bytes.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `capacity` is not a multiple of `ERASE_SIZE`.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity.is_multiple_of(ERASE_SIZE));
+
+        Self {
+            memory: vec![0xFF; capacity],
+            erases: vec![0; capacity / ERASE_SIZE],
+            power: None,
+        }
+    }
+
+    /// Loses power after `bytes` bytes are written or erased: the operation
+    /// that exceeds the limit is done partially and this and all following
+    /// operations fail with [`MockError::PowerLoss`], until
+    /// [`MockFlash::restore_power`] is called.
+    pub fn cut_power_after(&mut self, bytes: usize) {
+        self.power = Some(bytes);
+    }
+
+    /// Stops losing power, see [`MockFlash::cut_power_after`].
+    pub fn restore_power(&mut self) {
+        self.power = None;
+    }
+
+    /// Returns the number of erases of every page.
+    pub fn erases(&self) -> &[u32] {
+        &self.erases
+    }
+
+    /// Returns the contents of the flash.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// Spends power for `len` bytes, returns the number of bytes that can be
+    /// changed before power is lost.
+    fn spend(&mut self, len: usize) -> usize {
+        match &mut self.power {
+            Some(power) => {
+                let spent = len.min(*power);
+                *power -= spent;
+                spent
+            }
+            None => len,
+        }
+    }
+
+    fn check(&self, offset: u32, len: usize, align: usize) -> Result<(), MockError> {
+        let offset = offset as usize;
+
+        if self.power == Some(0) {
+            Err(MockError::PowerLoss)
+        } else if !offset.is_multiple_of(align) || !len.is_multiple_of(align) {
+            Err(MockError::NotAligned)
+        } else if offset + len > self.memory.len() {
+            Err(MockError::OutOfBounds)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl NorFlashError for MockError {
+    fn kind(&self) -> NorFlashErrorKind {
+        match self {
+            Self::NotAligned => NorFlashErrorKind::NotAligned,
+            Self::OutOfBounds => NorFlashErrorKind::OutOfBounds,
+            Self::NotErased | Self::PowerLoss => NorFlashErrorKind::Other,
+        }
+    }
+}
+
+impl<const WRITE_SIZE: usize, const ERASE_SIZE: usize> ErrorType
+    for MockFlash<WRITE_SIZE, ERASE_SIZE>
+{
+    type Error = MockError;
+}
+
+impl<const WRITE_SIZE: usize, const ERASE_SIZE: usize> ReadNorFlash
+    for MockFlash<WRITE_SIZE, ERASE_SIZE>
+{
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        self.check(offset, bytes.len(), Self::READ_SIZE)?;
+
+        let offset = offset as usize;
+        bytes.copy_from_slice(&self.memory[offset..][..bytes.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.memory.len()
+    }
+}
+
+impl<const WRITE_SIZE: usize, const ERASE_SIZE: usize> NorFlash
+    for MockFlash<WRITE_SIZE, ERASE_SIZE>
+{
+    const WRITE_SIZE: usize = WRITE_SIZE;
+    const ERASE_SIZE: usize = ERASE_SIZE;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        let len = to.checked_sub(from).ok_or(MockError::OutOfBounds)? as usize;
+        self.check(from, len, ERASE_SIZE)?;
+
+        let from = from as usize;
+        let erased = self.spend(len);
+        self.memory[from..][..erased].fill(0xFF);
+
+        if erased < len {
+            return Err(MockError::PowerLoss);
+        }
+
+        self.erases[from / ERASE_SIZE..][..len / ERASE_SIZE]
+            .iter_mut()
+            .for_each(|erases| *erases += 1);
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.check(offset, bytes.len(), WRITE_SIZE)?;
+
+        let offset = offset as usize;
+        if self.memory[offset..][..bytes.len()]
+            .iter()
+            .any(|&byte| byte != 0xFF)
+        {
+            return Err(MockError::NotErased);
+        }
+
+        let written = self.spend(bytes.len());
+        self.memory[offset..][..written].copy_from_slice(&bytes[..written]);
+
+        if written < bytes.len() {
+            return Err(MockError::PowerLoss);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
+
+    use super::{MockError, MockFlash};
+
+    #[test]
+    fn write_erase() {
+        let mut flash = MockFlash::<4, 16>::new(32);
+
+        flash.write(4, &[1, 2, 3, 4]).unwrap();
+        assert_eq!(flash.write(4, &[0; 4]), Err(MockError::NotErased));
+        assert_eq!(flash.write(2, &[0; 4]), Err(MockError::NotAligned));
+        assert_eq!(flash.write(32, &[0; 4]), Err(MockError::OutOfBounds));
+
+        let mut buf = [0; 6];
+        flash.read(3, &mut buf).unwrap();
+        assert_eq!(buf, [0xFF, 1, 2, 3, 4, 0xFF]);
+
+        flash.erase(0, 16).unwrap();
+        assert_eq!(flash.as_bytes(), [0xFF; 32]);
+        assert_eq!(flash.erases(), [1, 0]);
+    }
+
+    #[test]
+    fn power_loss() {
+        let mut flash = MockFlash::<4, 16>::new(32);
+        flash.write(16, &[0; 8]).unwrap();
+
+        flash.cut_power_after(6);
+        flash.write(0, &[1; 4]).unwrap();
+        assert_eq!(flash.write(4, &[2; 4]), Err(MockError::PowerLoss));
+        assert_eq!(flash.erase(16, 32), Err(MockError::PowerLoss));
+        assert_eq!(&flash.as_bytes()[..8], [1, 1, 1, 1, 2, 2, 0xFF, 0xFF]);
+
+        flash.restore_power();
+        flash.cut_power_after(4);
+        assert_eq!(flash.erase(16, 32), Err(MockError::PowerLoss));
+        assert_eq!(
+            &flash.as_bytes()[16..24],
+            [0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0]
+        );
+        // Interrupted erases are not counted
+        assert_eq!(flash.erases(), [0, 0]);
+    }
+}
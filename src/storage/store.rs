@@ -0,0 +1,739 @@
+use core::ops::Range;
+
+use embedded_storage::nor_flash::NorFlash;
+
+use crate::{
+    crc::{crc16, crc16_update},
+    storage::{Error, Key},
+};
+
+/// Marks pages used by the store, "mbkb".
+const MAGIC: u32 = 0x6d626b62;
+
+/// Size of the page header: magic and sequence number.
+const PAGE_HEADER: usize = 8;
+
+/// Size of the record header: key, length and CRC.
+const RECORD_HEADER: usize = 6;
+
+/// Flag of the length of removed values.
+const REMOVED: u16 = 0x8000;
+
+/// Size of the buffer used for flash operations, must be a multiple of
+/// `READ_SIZE` and `WRITE_SIZE`.
+const CHUNK: usize = 16;
+
+/// Key/value store on NOR flash.
+///
+/// The store is a log: values are appended to the current page and old values
+/// are ignored. When the current page is full, the store moves to the next one,
+/// going around the flash region, which spreads erases evenly over the pages.
+/// To keep a free page, the values of the oldest page that weren't
+/// overwritten are copied to the current one and the oldest page is erased.
+///
+/// Every value is protected by a CRC. If power is lost during a write, the
+/// value is ignored and the previous one is used, the page is then closed.
+/// If power is lost while moving to the next page, the move is finished when
+/// the store is opened.
+///
+/// Values must fit into a page, including a 6 byte header.
+pub struct Store<F> {
+    flash: F,
+    /// Offset of the first page.
+    start: u32,
+    /// Number of pages.
+    pages: u32,
+    /// Index of the current page.
+    head: u32,
+    /// Sequence number of the current page.
+    seq: u32,
+    /// Offset of the free space in the current page, `None` if the page is
+    /// closed (a write failed).
+    end: Option<u32>,
+}
+
+/// Value in the store.
+#[derive(Copy, Clone)]
+struct Record {
+    key: u16,
+    /// Raw length, including the [`REMOVED`] flag.
+    len: u16,
+    /// Offset of the record.
+    at: u32,
+}
+
+/// Result of reading a record.
+enum Entry {
+    Record(Record),
+    /// There are no more records in the page.
+    End,
+    /// The record is corrupted, following records (if any) can't be trusted.
+    Corrupted,
+}
+
+impl Record {
+    fn removed(&self) -> bool {
+        self.len & REMOVED != 0
+    }
+
+    fn data_len(&self) -> usize {
+        usize::from(self.len & !REMOVED)
+    }
+
+    fn data(&self) -> u32 {
+        self.at + RECORD_HEADER as u32
+    }
+}
+
+impl<F: NorFlash> Store<F> {
+    /// Opens the store in the `range` of the `flash`, the range is formatted
+    /// if it doesn't contain a store.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `range` is not aligned to pages, has less than 2 pages or is
+    /// out of bounds of the `flash`.
+    pub fn open(flash: F, range: Range<u32>) -> Result<Self, Error<F::Error>> {
+        let page = F::ERASE_SIZE as u32;
+        assert!(range.start.is_multiple_of(page) && range.end.is_multiple_of(page));
+        assert!(range.end as usize <= flash.capacity());
+        assert!(range.end >= range.start + 2 * page);
+        assert!(CHUNK.is_multiple_of(F::WRITE_SIZE) && CHUNK.is_multiple_of(F::READ_SIZE));
+        assert!(F::ERASE_SIZE.is_multiple_of(CHUNK));
+
+        let mut this = Self {
+            flash,
+            start: range.start,
+            pages: (range.end - range.start) / page,
+            head: 0,
+            seq: 0,
+            end: None,
+        };
+
+        let mut head = None;
+        for page in 0..this.pages {
+            if let Some(seq) = this.page_seq(page)? {
+                if head.is_none_or(|(_, max)| seq > max) {
+                    head = Some((page, seq));
+                }
+            }
+        }
+
+        match head {
+            Some((page, seq)) => {
+                this.head = page;
+                this.seq = seq;
+
+                let (mut at, end) = this.page_range(page);
+                this.end = loop {
+                    match this.entry(at, end)? {
+                        Entry::Record(record) => at = this.next(&record),
+                        Entry::End => break Some(at),
+                        Entry::Corrupted => break None,
+                    }
+                };
+
+                // Finish moving to the current page
+                this.finish_collect()?;
+            }
+            None => {
+                // `advance` starts with the next page
+                this.head = this.pages - 1;
+                this.seq = u32::MAX;
+                this.advance()?;
+            }
+        }
+
+        Ok(this)
+    }
+
+    /// Reads the value of `key` into `buf`, returns the length of the value
+    /// or `None` if there is no value.
+    pub fn read(&mut self, key: Key, buf: &mut [u8]) -> Result<Option<usize>, Error<F::Error>> {
+        let record = match self.last(key.into_raw())? {
+            Some(record) if !record.removed() => record,
+            _ => return Ok(None),
+        };
+
+        let len = record.data_len();
+        if len > buf.len() {
+            return Err(Error::TooLarge);
+        }
+
+        self.read_at(record.data(), &mut buf[..len])?;
+        Ok(Some(len))
+    }
+
+    /// Writes the `value` of `key`, replacing the previous one.
+    pub fn write(&mut self, key: Key, value: &[u8]) -> Result<(), Error<F::Error>> {
+        if value.len() >= usize::from(REMOVED) {
+            return Err(Error::TooLarge);
+        }
+
+        // Don't wear the flash if the value is the same
+        if let Some(record) = self.last(key.into_raw())? {
+            if !record.removed() && self.equals(&record, value)? {
+                return Ok(());
+            }
+        }
+
+        self.append(key.into_raw(), value.len() as u16, value)
+    }
+
+    /// Removes the value of `key`.
+    pub fn remove(&mut self, key: Key) -> Result<(), Error<F::Error>> {
+        match self.last(key.into_raw())? {
+            Some(record) if !record.removed() => self.append(key.into_raw(), REMOVED, &[]),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the flash.
+    pub fn into_inner(self) -> F {
+        self.flash
+    }
+
+    /// Returns the offset of `page`.
+    fn page_offset(&self, page: u32) -> u32 {
+        self.start + page * F::ERASE_SIZE as u32
+    }
+
+    /// Returns the offset of the first record of `page` and the end of the
+    /// page.
+    fn page_range(&self, page: u32) -> (u32, u32) {
+        let offset = self.page_offset(page);
+        (
+            offset + align::<F>(PAGE_HEADER),
+            offset + F::ERASE_SIZE as u32,
+        )
+    }
+
+    /// Returns the sequence number of `page`, or `None` if it isn't used.
+    fn page_seq(&mut self, page: u32) -> Result<Option<u32>, Error<F::Error>> {
+        let mut header = [0; PAGE_HEADER];
+        self.read_at(self.page_offset(page), &mut header)?;
+
+        let [m0, m1, m2, m3, s0, s1, s2, s3] = header;
+        let seq = u32::from_le_bytes([s0, s1, s2, s3]);
+
+        Ok(Some(seq)
+            .filter(|&seq| u32::from_le_bytes([m0, m1, m2, m3]) == MAGIC && seq != u32::MAX))
+    }
+
+    fn entry(&mut self, at: u32, end: u32) -> Result<Entry, Error<F::Error>> {
+        if at + RECORD_HEADER as u32 > end {
+            return Ok(Entry::End);
+        }
+
+        let mut header = [0; RECORD_HEADER];
+        self.read_at(at, &mut header)?;
+
+        if header == [0xFF; RECORD_HEADER] {
+            return Ok(Entry::End);
+        }
+
+        let [k0, k1, l0, l1, c0, c1] = header;
+        let record = Record {
+            key: u16::from_le_bytes([k0, k1]),
+            len: u16::from_le_bytes([l0, l1]),
+            at,
+        };
+
+        if self.next(&record) > end {
+            return Ok(Entry::Corrupted);
+        }
+
+        let mut crc = crc16(&header[..4]);
+        let mut offset = 0;
+        while offset < record.data_len() {
+            let mut chunk = [0; CHUNK];
+            let len = (record.data_len() - offset).min(CHUNK);
+            self.read_at(record.data() + offset as u32, &mut chunk[..len])?;
+
+            crc = crc16_update(crc, &chunk[..len]);
+            offset += len;
+        }
+
+        if crc.to_le_bytes() != [c0, c1] {
+            return Ok(Entry::Corrupted);
+        }
+
+        Ok(Entry::Record(record))
+    }
+
+    /// Returns the offset of the record after `record`.
+    fn next(&self, record: &Record) -> u32 {
+        record.at + align::<F>(RECORD_HEADER + record.data_len())
+    }
+
+    /// Finds the last record of `key`.
+    fn last(&mut self, key: u16) -> Result<Option<Record>, Error<F::Error>> {
+        let mut last = None;
+
+        // From the oldest page to the newest one
+        for i in 1..=self.pages {
+            let page = (self.head + i) % self.pages;
+            if self.page_seq(page)?.is_none() {
+                continue;
+            }
+
+            let (mut at, end) = self.page_range(page);
+            while let Entry::Record(record) = self.entry(at, end)? {
+                if record.key == key {
+                    last = Some(record);
+                }
+
+                at = self.next(&record);
+            }
+        }
+
+        Ok(last)
+    }
+
+    fn equals(&mut self, record: &Record, value: &[u8]) -> Result<bool, Error<F::Error>> {
+        if record.data_len() != value.len() {
+            return Ok(false);
+        }
+
+        for (i, part) in value.chunks(CHUNK).enumerate() {
+            let mut chunk = [0; CHUNK];
+            self.read_at(record.data() + (i * CHUNK) as u32, &mut chunk[..part.len()])?;
+
+            if chunk[..part.len()] != *part {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Appends a record, moving to the next pages if needed.
+    fn append(&mut self, key: u16, len: u16, value: &[u8]) -> Result<(), Error<F::Error>> {
+        let size = align::<F>(RECORD_HEADER + value.len());
+        if align::<F>(PAGE_HEADER) + size > F::ERASE_SIZE as u32 {
+            return Err(Error::TooLarge);
+        }
+
+        // If moving to the current page failed, the oldest page must be freed
+        // before anything else is written to the current one
+        self.finish_collect()?;
+
+        // Every page could be full of values that weren't overwritten
+        for _ in 0..=self.pages {
+            let (_, end) = self.page_range(self.head);
+
+            match self.end {
+                Some(at) if at + size <= end => {
+                    // If the write fails, the state of the page is unknown
+                    self.end = None;
+                    self.write_record(at, key, len, value)?;
+                    self.end = Some(at + size);
+                    return Ok(());
+                }
+                _ => self.advance()?,
+            }
+        }
+
+        Err(Error::Full)
+    }
+
+    /// Moves to the next page, freeing the oldest one.
+    fn advance(&mut self) -> Result<(), Error<F::Error>> {
+        let head = (self.head + 1) % self.pages;
+        let seq = self.seq.wrapping_add(1);
+
+        // Pages are erased after their values are copied, but it could have
+        // been interrupted
+        self.format(head, seq, false)?;
+
+        // Keep a free page
+        self.finish_collect()
+    }
+
+    /// Makes `page` the current one, erasing it first if it isn't erased (or
+    /// if `erase` is `true`).
+    fn format(&mut self, page: u32, seq: u32, erase: bool) -> Result<(), Error<F::Error>> {
+        let (start, end) = self.page_range(page);
+        let offset = self.page_offset(page);
+
+        if erase || !self.erased(offset..end)? {
+            self.flash.erase(offset, end).map_err(Error::Flash)?;
+        }
+
+        let mut header = [0xFF; CHUNK];
+        header[..4].copy_from_slice(&MAGIC.to_le_bytes());
+        header[4..8].copy_from_slice(&seq.to_le_bytes());
+        self.write_at(offset, &header[..align::<F>(PAGE_HEADER) as usize])?;
+
+        self.head = page;
+        self.seq = seq;
+        self.end = Some(start);
+
+        Ok(())
+    }
+
+    /// Frees the oldest page if it's used, i.e. if moving to the current page
+    /// was interrupted.
+    fn finish_collect(&mut self) -> Result<(), Error<F::Error>> {
+        let tail = (self.head + 1) % self.pages;
+        if self.page_seq(tail)?.is_none() {
+            return Ok(());
+        }
+
+        // A copy was interrupted and closed the current page. Since nothing
+        // is written to the current page before the oldest one is freed, it
+        // only contains copies of values that are still in the oldest page
+        // and can be started over.
+        if self.end.is_none() {
+            self.format(self.head, self.seq, true)?;
+        }
+
+        self.collect(tail)
+    }
+
+    /// Copies the records of the page that weren't overwritten to the current
+    /// page and erases it.
+    fn collect(&mut self, page: u32) -> Result<(), Error<F::Error>> {
+        let (mut at, end) = self.page_range(page);
+
+        while let Entry::Record(record) = self.entry(at, end)? {
+            // Removed values are copied too, so that the older values (if
+            // any) don't reappear if erasing is interrupted
+            let last = self.last(record.key)?;
+            if last.map(|last| last.at) == Some(record.at) {
+                self.copy(&record)?;
+            }
+
+            at = self.next(&record);
+        }
+
+        let start = self.page_offset(page);
+        self.flash.erase(start, end).map_err(Error::Flash)
+    }
+
+    /// Copies `record` to the current page.
+    fn copy(&mut self, record: &Record) -> Result<(), Error<F::Error>> {
+        let size = self.next(record) - record.at;
+        let (_, end) = self.page_range(self.head);
+
+        let at = match self.end {
+            Some(at) if at + size <= end => at,
+            _ => return Err(Error::Full),
+        };
+
+        self.end = None;
+        for offset in (0..size).step_by(CHUNK) {
+            let mut chunk = [0; CHUNK];
+            let len = (size - offset).min(CHUNK as u32) as usize;
+
+            self.read_at(record.at + offset, &mut chunk[..len])?;
+            self.write_at(at + offset, &chunk[..len])?;
+        }
+        self.end = Some(at + size);
+
+        Ok(())
+    }
+
+    fn write_record(
+        &mut self,
+        at: u32,
+        key: u16,
+        len: u16,
+        value: &[u8],
+    ) -> Result<(), Error<F::Error>> {
+        let [k0, k1] = key.to_le_bytes();
+        let [l0, l1] = len.to_le_bytes();
+        let [c0, c1] = crc16_update(crc16(&[k0, k1, l0, l1]), value).to_le_bytes();
+
+        let mut chunk = [0xFF; CHUNK];
+        let mut filled = 0;
+        let mut offset = at;
+
+        for &byte in [k0, k1, l0, l1, c0, c1].iter().chain(value) {
+            chunk[filled] = byte;
+            filled += 1;
+
+            if filled == CHUNK {
+                self.write_at(offset, &chunk)?;
+                chunk = [0xFF; CHUNK];
+                filled = 0;
+                offset += CHUNK as u32;
+            }
+        }
+
+        if filled != 0 {
+            let len = align::<F>(filled) as usize;
+            self.write_at(offset, &chunk[..len])?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns `true` if the `range` of the flash is erased.
+    fn erased(&mut self, range: Range<u32>) -> Result<bool, Error<F::Error>> {
+        for offset in range.step_by(CHUNK) {
+            let mut chunk = [0; CHUNK];
+            self.read_at(offset, &mut chunk)?;
+
+            if chunk != [0xFF; CHUNK] {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Reads `buf.len()` bytes at `offset`, which don't need to be aligned.
+    fn read_at(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), Error<F::Error>> {
+        let mut done = 0;
+
+        while done < buf.len() {
+            let at = offset + done as u32;
+            let skip = at as usize % F::READ_SIZE;
+            let len = (skip + buf.len() - done).min(CHUNK);
+            let len = len.div_ceil(F::READ_SIZE) * F::READ_SIZE;
+
+            let mut chunk = [0; CHUNK];
+            self.flash
+                .read(at - skip as u32, &mut chunk[..len])
+                .map_err(Error::Flash)?;
+
+            let n = (len - skip).min(buf.len() - done);
+            buf[done..][..n].copy_from_slice(&chunk[skip..][..n]);
+            done += n;
+        }
+
+        Ok(())
+    }
+
+    fn write_at(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Error<F::Error>> {
+        self.flash.write(offset, bytes).map_err(Error::Flash)
+    }
+}
+
+/// Rounds `size` up to a multiple of the write size of the flash.
+fn align<F: NorFlash>(size: usize) -> u32 {
+    (size.div_ceil(F::WRITE_SIZE) * F::WRITE_SIZE) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use core::ops::Range;
+    use std::{collections::BTreeMap, vec, vec::Vec};
+
+    use embedded_storage::nor_flash::NorFlash;
+
+    use super::Store;
+    use crate::storage::{Error, Key, MockError, MockFlash};
+
+    type Flash = MockFlash<4, 1024>;
+
+    /// 4 pages.
+    fn range() -> Range<u32> {
+        0..4096
+    }
+
+    fn read<F: NorFlash>(store: &mut Store<F>, key: u16) -> Option<Vec<u8>>
+    where
+        F::Error: core::fmt::Debug,
+    {
+        let mut buf = [0; 1024];
+        let len = store.read(Key::from_raw(key), &mut buf).unwrap()?;
+        Some(buf[..len].to_vec())
+    }
+
+    fn apply<F: NorFlash>(
+        store: &mut Store<F>,
+        key: u16,
+        value: Option<&[u8]>,
+    ) -> Result<(), Error<F::Error>> {
+        match value {
+            Some(value) => store.write(Key::from_raw(key), value),
+            None => store.remove(Key::from_raw(key)),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut store = Store::open(Flash::new(4096), range()).unwrap();
+        assert_eq!(read(&mut store, 0x100), None);
+
+        store.write(Key::from_raw(0x100), b"hello").unwrap();
+        store.write(Key::from_raw(0x101), &[7; 100]).unwrap();
+        store.write(Key::from_raw(0x102), &[]).unwrap();
+        store.write(Key::from_raw(0x100), b"world!").unwrap();
+        store.remove(Key::from_raw(0x103)).unwrap();
+
+        assert_eq!(read(&mut store, 0x100), Some(b"world!".to_vec()));
+        assert_eq!(read(&mut store, 0x101), Some(vec![7; 100]));
+        assert_eq!(read(&mut store, 0x102), Some(Vec::new()));
+        assert_eq!(read(&mut store, 0x103), None);
+
+        store.remove(Key::from_raw(0x101)).unwrap();
+        assert_eq!(read(&mut store, 0x101), None);
+
+        // Values survive reopening
+        let mut store = Store::open(store.into_inner(), range()).unwrap();
+        assert_eq!(read(&mut store, 0x100), Some(b"world!".to_vec()));
+        assert_eq!(read(&mut store, 0x101), None);
+        assert_eq!(read(&mut store, 0x102), Some(Vec::new()));
+
+        let key = Key::from_raw(0x100);
+        assert_eq!(store.read(key, &mut [0; 5]), Err(Error::TooLarge));
+        assert_eq!(store.write(key, &[0; 1024]), Err(Error::TooLarge));
+        assert_eq!(read(&mut store, 0x100), Some(b"world!".to_vec()));
+    }
+
+    #[test]
+    fn same_value() {
+        let mut store = Store::open(Flash::new(4096), range()).unwrap();
+        store.write(Key::from_raw(0x100), b"value").unwrap();
+        store.remove(Key::from_raw(0x101)).unwrap();
+
+        let before = store.into_inner();
+        let mut store = Store::open(Flash::new(4096), range()).unwrap();
+        store.write(Key::from_raw(0x100), b"value").unwrap();
+        store.remove(Key::from_raw(0x101)).unwrap();
+        store.write(Key::from_raw(0x100), b"value").unwrap();
+        store.remove(Key::from_raw(0x101)).unwrap();
+
+        assert_eq!(store.into_inner().as_bytes(), before.as_bytes());
+    }
+
+    #[test]
+    fn wear_leveling() {
+        let mut store = Store::open(Flash::new(4096), range()).unwrap();
+        store.write(Key::from_raw(0x100), &[1; 300]).unwrap();
+
+        for i in 0..1000u32 {
+            let value = [i as u8; 50];
+            store
+                .write(Key::from_raw(0x200 + (i % 4) as u16), &value)
+                .unwrap();
+        }
+
+        assert_eq!(read(&mut store, 0x100), Some(vec![1; 300]));
+        for i in 996..1000u32 {
+            let value = vec![i as u8; 50];
+            assert_eq!(read(&mut store, 0x200 + (i % 4) as u16), Some(value));
+        }
+
+        let flash = store.into_inner();
+        let erases = flash.erases();
+        let min = *erases.iter().min().unwrap();
+        let max = *erases.iter().max().unwrap();
+        assert!(min >= 10, "{:?}", erases);
+        assert!(max - min <= 1, "{:?}", erases);
+    }
+
+    #[test]
+    fn full() {
+        let mut store = Store::open(Flash::new(4096), range()).unwrap();
+
+        let mut keys = 0;
+        let err = loop {
+            match store.write(Key::from_raw(0x100 + keys), &[keys as u8; 200]) {
+                Ok(()) => keys += 1,
+                Err(err) => break err,
+            }
+        };
+
+        assert_eq!(err, Error::Full);
+        assert!(keys >= 8, "{}", keys);
+
+        let mut store = Store::open(store.into_inner(), range()).unwrap();
+        for key in 0..keys {
+            assert_eq!(read(&mut store, 0x100 + key), Some(vec![key as u8; 200]));
+        }
+
+        // Removing a value frees space
+        store.remove(Key::from_raw(0x100)).unwrap();
+        store.write(Key::from_raw(0x100 + keys), &[0; 200]).unwrap();
+        assert_eq!(read(&mut store, 0x100), None);
+        assert_eq!(read(&mut store, 0x100 + keys), Some(vec![0; 200]));
+    }
+
+    /// Writes and removals, `None` removes the value.
+    fn workload() -> Vec<(u16, Option<Vec<u8>>)> {
+        // Values that are never overwritten are copied by every collection
+        let mut ops: Vec<_> = (0..4)
+            .map(|key| (0x100 + key, Some(vec![key as u8; 25])))
+            .collect();
+
+        ops.extend((0..100).map(|i: usize| {
+            let value = vec![i as u8; 4 + i * 13 % 20];
+            (0x200 + (i % 3) as u16, Some(value).filter(|_| i % 7 != 6))
+        }));
+
+        ops
+    }
+
+    /// Applies `ops` to the store, counting the ones that succeeded.
+    fn run(
+        flash: &mut MockFlash<4, 256>,
+        ops: &[(u16, Option<Vec<u8>>)],
+        done: &mut usize,
+    ) -> Result<(), Error<MockError>> {
+        let mut store = Store::open(flash, 0..1024)?;
+
+        for (key, value) in ops {
+            apply(&mut store, *key, value.as_deref())?;
+            *done += 1;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn power_loss() {
+        let ops = workload();
+
+        for cut in 0.. {
+            // Smaller pages to collect more often
+            let mut flash = MockFlash::<4, 256>::new(1024);
+            flash.cut_power_after(cut);
+
+            let mut done = 0;
+            let res = run(&mut flash, &ops, &mut done);
+            match res {
+                Ok(()) | Err(Error::Flash(MockError::PowerLoss)) => {}
+                Err(err) => panic!("cut after {} bytes: {:?}", cut, err),
+            }
+
+            flash.restore_power();
+            let mut store = Store::open(&mut flash, 0..1024)
+                .unwrap_or_else(|err| panic!("cut after {} bytes: {:?}", cut, err));
+
+            // Either the old or the new value of the interrupted write
+            let mut expected = BTreeMap::new();
+            for (key, value) in &ops[..done] {
+                expected.insert(*key, value.clone());
+            }
+            let interrupted = ops.get(done).filter(|_| res.is_err());
+
+            for &(key, _) in &ops {
+                let value = read(&mut store, key);
+                let old = expected.get(&key).cloned().flatten();
+                let new = interrupted
+                    .filter(|(k, _)| *k == key)
+                    .map(|(_, value)| value.clone());
+
+                assert!(
+                    value == old || Some(&value) == new.as_ref(),
+                    "cut after {} bytes: key {:#x} is {:?}",
+                    cut,
+                    key,
+                    value
+                );
+            }
+
+            // The store is still usable
+            store.write(Key::from_raw(0x300), b"after").unwrap();
+            assert_eq!(read(&mut store, 0x300), Some(b"after".to_vec()));
+
+            if res.is_ok() {
+                break;
+            }
+        }
+    }
+}
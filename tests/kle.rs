@@ -0,0 +1,49 @@
+//! Code generated by the KLE importer, as a build script would write it.
+//!
+//! `kle/keys.rs` and `kle/keys_fixed.rs` are the expected outputs, they are
+//! both compared to the output of the importer and `include!`d, to check that
+//! the generated code compiles.
+#![cfg(feature = "kle")]
+
+use mbkb::phy::{
+    top::{fixed, kle, KeyPos, Shape},
+    KeyId,
+};
+
+const JSON: &str = r#"[
+    [{"w": 1.5}, "1", {"w": 1.25, "w2": 1.75, "l": true}, "0"],
+    [{"r": 90, "rx": 1, "ry": 1}, "2"]
+]"#;
+
+static KEYS: &[KeyPos] = include!("kle/keys.rs");
+
+static KEYS_FIXED: &[fixed::KeyPos] = include!("kle/keys_fixed.rs");
+
+fn positions() -> Vec<KeyPos> {
+    kle::key_positions(&kle::parse(JSON).unwrap()).unwrap()
+}
+
+#[test]
+fn to_rust() {
+    let positions = positions();
+
+    assert_eq!(kle::to_rust(&positions), include_str!("kle/keys.rs"));
+    assert_eq!(KEYS, &positions[..]);
+
+    assert_eq!(KEYS[0].id, KeyId::from_raw(0));
+    assert_eq!(KEYS[0].shape, Shape::Stepped { step: 1.25 });
+    assert_eq!(KEYS[2].origin, (1., 1.));
+}
+
+#[test]
+fn to_rust_fixed() {
+    let positions = positions();
+
+    assert_eq!(
+        kle::to_rust_fixed(&positions),
+        include_str!("kle/keys_fixed.rs")
+    );
+
+    let expected = positions.iter().map(fixed::KeyPos::from_f32);
+    assert!(KEYS_FIXED.iter().copied().eq(expected));
+}
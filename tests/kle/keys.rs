@@ -0,0 +1,5 @@
+&[
+    ::mbkb::phy::top::KeyPos::new(::mbkb::phy::KeyId::from_raw(0), 2.375, 0.5).size(1.75, 1.0).shape(::mbkb::phy::top::Shape::Stepped { step: 1.25 }).rotate(0.0, (0.0, 0.0)),
+    ::mbkb::phy::top::KeyPos::new(::mbkb::phy::KeyId::from_raw(1), 0.75, 0.5).size(1.5, 1.0).shape(::mbkb::phy::top::Shape::Rect).rotate(0.0, (0.0, 0.0)),
+    ::mbkb::phy::top::KeyPos::new(::mbkb::phy::KeyId::from_raw(2), 1.5, 1.5).size(1.0, 1.0).shape(::mbkb::phy::top::Shape::Rect).rotate(1.5707964, (1.0, 1.0)),
+]
\ No newline at end of file
@@ -0,0 +1,5 @@
+&[
+    ::mbkb::phy::top::fixed::KeyPos::new(::mbkb::phy::KeyId::from_raw(0), ::mbkb::phy::top::fixed::Units(608), ::mbkb::phy::top::fixed::Units(128)).size(::mbkb::phy::top::fixed::Units(448), ::mbkb::phy::top::fixed::Units(256)).shape(::mbkb::phy::top::fixed::Shape::Stepped { step: ::mbkb::phy::top::fixed::Units(320) }).rotate(::mbkb::phy::top::fixed::Angle(0), (::mbkb::phy::top::fixed::Units(0), ::mbkb::phy::top::fixed::Units(0))),
+    ::mbkb::phy::top::fixed::KeyPos::new(::mbkb::phy::KeyId::from_raw(1), ::mbkb::phy::top::fixed::Units(192), ::mbkb::phy::top::fixed::Units(128)).size(::mbkb::phy::top::fixed::Units(384), ::mbkb::phy::top::fixed::Units(256)).shape(::mbkb::phy::top::fixed::Shape::Rect).rotate(::mbkb::phy::top::fixed::Angle(0), (::mbkb::phy::top::fixed::Units(0), ::mbkb::phy::top::fixed::Units(0))),
+    ::mbkb::phy::top::fixed::KeyPos::new(::mbkb::phy::KeyId::from_raw(2), ::mbkb::phy::top::fixed::Units(384), ::mbkb::phy::top::fixed::Units(384)).size(::mbkb::phy::top::fixed::Units(256), ::mbkb::phy::top::fixed::Units(256)).shape(::mbkb::phy::top::fixed::Shape::Rect).rotate(::mbkb::phy::top::fixed::Angle(90000), (::mbkb::phy::top::fixed::Units(256), ::mbkb::phy::top::fixed::Units(256))),
+]
\ No newline at end of file